@@ -0,0 +1,26 @@
+// fuzz/fuzz_targets/cue_parse.rs
+//! Parse arbitrary text as a CUE sheet, in both strict and lenient mode.
+//! A sheet that parses without errors must survive a round trip through
+//! `to_cue_text` (parsed in the same mode) with the same layout.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use psx_vcd::cue::{CueSheet, Severity};
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    for lenient in [false, true] {
+        let (sheet, diagnostics) = CueSheet::parse_text(&text, lenient);
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            continue;
+        }
+
+        let (reparsed, diagnostics) = CueSheet::parse_text(&sheet.to_cue_text(), lenient);
+        assert!(
+            diagnostics.iter().all(|d| d.severity != Severity::Error),
+            "canonical CUE rejected: {:?}",
+            diagnostics
+        );
+        assert_eq!(reparsed.get_total_tracks(), sheet.get_total_tracks());
+    }
+});
@@ -0,0 +1,34 @@
+// fuzz/fuzz_targets/vcd_header.rs
+//! Build a VCD header from an arbitrary CUE and BIN size. Every header
+//! that is produced must carry valid BCD in its descriptors and track
+//! entries; layouts that do not fit must be rejected, not mangled.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use psx_vcd::cue::CueSheet;
+use psx_vcd::utils::Msf;
+use psx_vcd::vcd::VcdConverter;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 9 {
+        return;
+    }
+    let bin_size = u64::from_le_bytes(data[..8].try_into().unwrap());
+    let gap_plus = data[8] & 1 != 0;
+    let gap_minus = data[8] & 2 != 0 && !gap_plus;
+    let (sheet, _) = CueSheet::parse_text(&String::from_utf8_lossy(&data[9..]), true);
+
+    let Ok(header) = VcdConverter::new(gap_plus, gap_minus).create_vcd_header(bin_size, &sheet)
+    else {
+        return;
+    };
+
+    let bcd = |offset: usize| [header[offset], header[offset + 1], header[offset + 2]];
+    assert!(Msf::from_bcd(bcd(27)).is_some(), "invalid lead-out BCD");
+    for track in 0..sheet.get_total_tracks() {
+        let base = 30 + track * 10;
+        assert!(Msf::from_bcd(bcd(base + 3)).is_some(), "invalid INDEX 00 BCD");
+        assert!(Msf::from_bcd(bcd(base + 7)).is_some(), "invalid INDEX 01 BCD");
+    }
+    assert_eq!(&header[0x400..0x404], b"kHn ");
+});
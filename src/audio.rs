@@ -0,0 +1,398 @@
+// src/audio.rs
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecParameters, DecoderOptions};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::error::{Error, Result};
+use crate::sector::SECTOR_SIZE;
+use crate::status;
+
+/// CDDA is 44.1 kHz, 16-bit, stereo
+const CDDA_SAMPLE_RATE: u32 = 44_100;
+const CDDA_BITS_PER_SAMPLE: u32 = 16;
+const CDDA_FRAME_SIZE: u64 = 4; // 2 channels x 2 bytes
+
+/// Audio container referenced by a `FILE ... WAVE` entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Flac,
+    Ogg,
+    Mp3,
+}
+
+impl AudioFormat {
+    /// Identify an audio container from its magic bytes
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        if header.len() >= 12 && &header[..4] == b"RIFF" && &header[8..12] == b"WAVE" {
+            Some(AudioFormat::Wav)
+        } else if header.starts_with(b"fLaC") {
+            Some(AudioFormat::Flac)
+        } else if header.starts_with(b"OggS") {
+            Some(AudioFormat::Ogg)
+        } else if header.starts_with(b"ID3") {
+            Some(AudioFormat::Mp3)
+        } else {
+            None
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Mp3 => "mp3",
+        }
+    }
+}
+
+impl std::fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            AudioFormat::Wav => "WAV",
+            AudioFormat::Flac => "FLAC",
+            AudioFormat::Ogg => "Ogg Vorbis",
+            AudioFormat::Mp3 => "MP3",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl serde::Serialize for AudioFormat {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Detect whether a FILE entry must be decoded instead of copied
+///
+/// Files are probed by content, so a mislabelled `BINARY` FLAC is still
+/// decoded. Files without a known container are copied as raw sectors
+/// whatever their FILE type (`MOTOROLA`, or a raw BIN labelled `WAVE`).
+pub fn detect_file(path: &Path, file_type: &str) -> Result<Option<AudioFormat>> {
+    let mut header = [0u8; 12];
+    let read = File::open(path)
+        .and_then(|mut file| file.read(&mut header))
+        .map_err(|e| Error::io(path, e))?;
+    if header[..read].starts_with(b"MAC ") {
+        return Err(Error::audio(
+            path,
+            "APE (Monkey's Audio) is not supported; convert the track to FLAC or WAV",
+        ));
+    }
+    let mut format = AudioFormat::detect(&header[..read]);
+
+    // MP3 files without an ID3 tag start straight with a frame sync
+    if format.is_none()
+        && file_type.eq_ignore_ascii_case("MP3")
+        && read >= 2
+        && header[0] == 0xFF
+        && header[1] & 0xE0 == 0xE0
+    {
+        format = Some(AudioFormat::Mp3);
+    }
+
+    if format.is_none() && !file_type.eq_ignore_ascii_case("BINARY") {
+        status!(
+            "[!] {}: FILE type {} but no known audio container, copying it as raw data",
+            path.display(),
+            file_type
+        );
+    }
+    Ok(format)
+}
+
+/// Size in bytes of the decoded CDDA stream, padded to whole sectors
+pub fn cdda_size(path: &Path, format: AudioFormat) -> Result<u64> {
+    let mut reader = open_reader(path, format)?;
+    let params = reader
+        .default_track()
+        .map(|t| t.codec_params.clone())
+        .ok_or_else(|| Error::audio(path, "no audio track found"))?;
+    check_params(path, &params)?;
+
+    let frames = match params.n_frames {
+        Some(frames) => frames,
+        None => count_frames(reader.as_mut(), &params)
+            .map_err(|e| Error::audio(path, format!("cannot decode: {}", e)))?,
+    };
+    Ok(pad_to_sector(frames * CDDA_FRAME_SIZE))
+}
+
+/// Decode an audio file into `length` bytes of raw CDDA sectors
+///
+/// The stream is padded with silence (or cut) to exactly `length` bytes so
+/// it matches the layout computed from [`cdda_size`].
+pub fn decode_to_cdda<W: Write>(
+    path: &Path,
+    format: AudioFormat,
+    output: &mut W,
+    length: u64,
+) -> Result<()> {
+    let mut cdda = CddaWriter {
+        output,
+        length,
+        written: 0,
+        pcm: Vec::new(),
+    };
+
+    let mut reader = open_reader(path, format)?;
+    let track = reader
+        .default_track()
+        .ok_or_else(|| Error::audio(path, "no audio track found"))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    check_params(path, &params)?;
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .map_err(|e| Error::audio(path, format!("cannot create decoder: {}", e)))?;
+
+    while !cdda.is_full() {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(DecodeError::IoError(e)) => return Err(Error::io(path, e)),
+            Err(e) => return Err(Error::audio(path, format!("cannot read: {}", e))),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = decoder
+            .decode(&packet)
+            .map_err(|e| Error::audio(path, format!("cannot decode: {}", e)))?;
+        let spec = *decoded.spec();
+        let mut samples = SampleBuffer::<i16>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+        cdda.write(path, samples.samples(), spec.channels.count())?;
+    }
+
+    cdda.pad(path)
+}
+
+/// Decoded PCM written out as CDDA, cut at `length` bytes
+///
+/// Write failures are reported on the audio file being decoded.
+struct CddaWriter<'a, W> {
+    output: &'a mut W,
+    length: u64,
+    written: u64,
+    pcm: Vec<u8>,
+}
+
+impl<W: Write> CddaWriter<'_, W> {
+    fn is_full(&self) -> bool {
+        self.written >= self.length
+    }
+
+    /// Write interleaved samples, duplicating mono into both channels
+    fn write(&mut self, path: &Path, samples: &[i16], channels: usize) -> Result<()> {
+        self.pcm.clear();
+        for sample in samples {
+            self.pcm.extend_from_slice(&sample.to_le_bytes());
+            if channels == 1 {
+                self.pcm.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+
+        let take = (self.pcm.len() as u64).min(self.length - self.written) as usize;
+        self.output
+            .write_all(&self.pcm[..take])
+            .map_err(|e| write_error(path, e))?;
+        self.written += take as u64;
+        Ok(())
+    }
+
+    /// Sector-aligned padding with digital silence
+    fn pad(self, path: &Path) -> Result<()> {
+        let silence = [0u8; SECTOR_SIZE];
+        let mut written = self.written;
+        while written < self.length {
+            let take = (self.length - written).min(SECTOR_SIZE as u64) as usize;
+            self.output
+                .write_all(&silence[..take])
+                .map_err(|e| write_error(path, e))?;
+            written += take as u64;
+        }
+        Ok(())
+    }
+}
+
+fn open_reader(path: &Path, format: AudioFormat) -> Result<Box<dyn FormatReader>> {
+    let file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(format.extension());
+
+    let format_options = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &format_options, &MetadataOptions::default())
+        .map_err(|e| Error::audio(path, format!("cannot read {} file: {}", format, e)))?;
+    Ok(probed.format)
+}
+
+fn check_params(path: &Path, params: &CodecParameters) -> Result<()> {
+    check_format(
+        path,
+        params.sample_rate.unwrap_or(0),
+        params.channels.map(|c| c.count()).unwrap_or(0),
+        params.bits_per_sample,
+    )
+}
+
+/// Only 44.1 kHz mono/stereo audio maps onto CDDA without resampling
+fn check_format(path: &Path, sample_rate: u32, channels: usize, bits: Option<u32>) -> Result<()> {
+    if sample_rate != CDDA_SAMPLE_RATE {
+        return Err(Error::audio(
+            path,
+            format!(
+                "unsupported sample rate {} Hz (CDDA requires 44100 Hz)",
+                sample_rate
+            ),
+        ));
+    }
+    if channels != 1 && channels != 2 {
+        return Err(Error::audio(
+            path,
+            format!(
+                "unsupported channel count {} (CDDA requires mono or stereo)",
+                channels
+            ),
+        ));
+    }
+    if let Some(bits) = bits {
+        if bits != CDDA_BITS_PER_SAMPLE {
+            return Err(Error::audio(
+                path,
+                format!("unsupported {}-bit samples (CDDA requires 16-bit)", bits),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Count frames by decoding when the container does not store a length
+fn count_frames(
+    reader: &mut dyn FormatReader,
+    params: &CodecParameters,
+) -> std::result::Result<u64, DecodeError> {
+    let mut decoder = symphonia::default::get_codecs().make(params, &DecoderOptions::default())?;
+    let mut frames = 0u64;
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        frames += decoder.decode(&packet)?.frames() as u64;
+    }
+    Ok(frames)
+}
+
+fn write_error(path: &Path, e: std::io::Error) -> Error {
+    Error::io(
+        path,
+        std::io::Error::new(e.kind(), format!("writing decoded audio: {}", e)),
+    )
+}
+
+fn pad_to_sector(bytes: u64) -> u64 {
+    bytes.div_ceil(SECTOR_SIZE as u64) * SECTOR_SIZE as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_wav(path: &Path, channels: u16, bits: u16, frames: u32) {
+        let block_align = channels * bits / 8;
+        let data_size = frames * block_align as u32;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&44_100u32.to_le_bytes());
+        wav.extend_from_slice(&(44_100 * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&bits.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for i in 0..data_size {
+            wav.push((i % 200) as u8);
+        }
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[test]
+    fn test_wav_to_cdda_with_padding() {
+        let path = std::env::temp_dir().join("psx-vcd-audio-stereo.wav");
+        write_wav(&path, 2, 16, 1000);
+
+        let format = detect_file(&path, "WAVE").unwrap().unwrap();
+        assert_eq!(format, AudioFormat::Wav);
+        let size = cdda_size(&path, format).unwrap();
+        assert_eq!(size, 2 * SECTOR_SIZE as u64);
+
+        let mut out = Vec::new();
+        decode_to_cdda(&path, format, &mut out, size).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(out.len() as u64, size);
+        let expected: Vec<u8> = (0..4000u32).map(|i| (i % 200) as u8).collect();
+        assert_eq!(&out[..4000], &expected[..]);
+        assert!(out[4000..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_unknown_container_is_raw() {
+        let path =
+            std::env::temp_dir().join(format!("psx-vcd-audio-raw-{}.bin", std::process::id()));
+        std::fs::write(&path, vec![0x5Au8; SECTOR_SIZE]).unwrap();
+        let formats: Vec<_> = ["BINARY", "WAVE", "MOTOROLA"]
+            .iter()
+            .map(|file_type| detect_file(&path, file_type).unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(formats, [None, None, None]);
+    }
+
+    #[test]
+    fn test_reject_ape() {
+        let path =
+            std::env::temp_dir().join(format!("psx-vcd-audio-ape-{}.ape", std::process::id()));
+        std::fs::write(&path, b"MAC \x96\x0f\x00\x00").unwrap();
+        let result = detect_file(&path, "WAVE");
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Audio { .. })));
+    }
+
+    #[test]
+    fn test_reject_24_bit_wav() {
+        let path = std::env::temp_dir().join("psx-vcd-audio-24bit.wav");
+        write_wav(&path, 2, 24, 100);
+        let result = cdda_size(&path, AudioFormat::Wav);
+        std::fs::remove_file(&path).unwrap();
+        let err = result.unwrap_err();
+        assert!(matches!(err, Error::Audio { .. }), "{:?}", err);
+        assert!(err.to_string().contains("24-bit"));
+    }
+}
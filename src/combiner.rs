@@ -1,9 +1,16 @@
 // src/combiner.rs
-use crate::cue::CueSheet;
-use crate::utils::Msf;
-use anyhow::{Context, Result};
+use crate::audio::{self, AudioFormat};
+use crate::cue::{CueSheet, DiscIndexes, TrackExtent, TrackType};
+use crate::error::{Error, Result};
+use crate::patch::Patch;
+use crate::progress::{NoProgress, ProgressReporter, ProgressWriter};
+use crate::sector::{self, SECTOR_SIZE};
+use crate::status;
+use crate::utils::Lba;
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{Read, Seek, Write};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
 const BUFFER_SIZE: usize = 1024 * 1024; // 1MB buffer
@@ -16,9 +23,36 @@ pub struct CombinedBinInfo {
 }
 
 /// Combines multiple BIN files into a single output file
-pub struct BinCombiner;
+///
+/// WAV, FLAC and Ogg Vorbis audio files are decoded to raw CDDA sectors.
+/// Patches (PPF, IPS, BPS, xdelta3) are applied to the combined sector
+/// stream before it is handed over to the VCD converter.
+///
+/// MODE2/2336 and MODE1/2048 tracks are always converted to the raw
+/// 2352-byte MODE2 layout POPS expects. In repair mode every other data
+/// sector is rebuilt as well (sync, header MSF, EDC and ECC).
+pub struct BinCombiner {
+    patches: Vec<Patch>,
+    repair: bool,
+    progress: RefCell<Box<dyn ProgressReporter + Send>>,
+}
 
 impl BinCombiner {
+    /// Create a new combiner with the patches to apply after combining
+    pub fn new(patches: Vec<Patch>, repair: bool) -> Self {
+        Self {
+            patches,
+            repair,
+            progress: RefCell::new(Box::new(NoProgress)),
+        }
+    }
+
+    /// Report the bytes written while combining to `progress`
+    pub fn with_progress(mut self, progress: Box<dyn ProgressReporter + Send>) -> Self {
+        self.progress = RefCell::new(progress);
+        self
+    }
+
     /// Combine multiple BIN files referenced in a CUE sheet into a single BIN file
     ///
     /// This handles two scenarios:
@@ -29,63 +63,238 @@ impl BinCombiner {
     /// - INDEX 00 = 00:00:00
     /// - INDEX 01 = 00:02:00 (150 sectors pregap)
     pub fn combine(
+        &self,
         cue_sheet: &mut CueSheet,
         cue_dir: &Path,
         output_path: &Path,
     ) -> Result<CombinedBinInfo> {
+        let mut info = self.combine_tracks(cue_sheet, cue_dir, output_path)?;
+
+        if !self.patches.is_empty() {
+            for patch in &self.patches {
+                patch.apply(output_path)?;
+            }
+            info.total_bytes = std::fs::metadata(output_path)
+                .map_err(|e| Error::io(output_path, e))?
+                .len();
+        }
+
+        Ok(info)
+    }
+
+    /// Work out the combined layout from file sizes alone
+    ///
+    /// Makes the same CUE sheet changes as [`combine`](Self::combine) and
+    /// returns the size the combined BIN will have, without reading or
+    /// writing any track data. Patches are not applied, so one that resizes
+    /// the image is not accounted for.
+    pub fn plan(&self, cue_sheet: &mut CueSheet) -> Result<CombinedBinInfo> {
         let total_tracks = cue_sheet.get_total_tracks();
+        let total_bytes = self.combined_size(cue_sheet);
+        if self.is_plain_copy(cue_sheet) {
+            Self::fix_track01_pregap(cue_sheet);
+        } else {
+            Self::finish_layout(cue_sheet)?;
+        }
+        Ok(CombinedBinInfo {
+            total_bytes,
+            track_count: total_tracks,
+        })
+    }
+
+    /// Stream the combined BIN without writing it
+    ///
+    /// Yields the bytes [`combine`](Self::combine) would write, before
+    /// patches are applied. `cue_sheet` is the sheet as parsed, with file
+    /// sizes loaded, not the one updated by `combine` or `plan`. Files are
+    /// opened only when the stream reaches them; a compressed audio file is
+    /// then decoded into memory as a whole.
+    pub fn reader(&self, cue_sheet: &CueSheet, cue_dir: &Path) -> Box<dyn Read> {
+        let mut reader = CombinedReader::default();
 
-        // Special case: single file with single track - just copy it
-        if cue_sheet.files.len() == 1 && total_tracks == 1 {
-            return Self::handle_single_file(cue_sheet, cue_dir, output_path);
+        let mut start = 0u64;
+        for segment in self.segments(cue_sheet) {
+            let path = cue_dir.join(segment.filename());
+            let segment_start = start;
+            start += segment_len(&segment);
+            reader.push(move || {
+                segment.open(&path, segment_start).map_err(|e| {
+                    std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+                })
+            });
         }
 
-        println!(
-            "  Combining {} track(s) from {} file(s)...",
-            total_tracks,
-            cue_sheet.files.len()
-        );
+        Box::new(reader)
+    }
 
-        let mut output_file =
-            File::create(output_path).context("Failed to create output BIN file")?;
+    /// Size of the combined BIN before patches, from file sizes alone
+    ///
+    /// `cue_sheet` is the sheet as parsed, with file sizes loaded. Unlike
+    /// [`plan`](Self::plan) it is left unchanged.
+    pub fn combined_size(&self, cue_sheet: &CueSheet) -> u64 {
+        self.segments(cue_sheet).iter().map(segment_len).sum()
+    }
 
-        let mut total_bytes = 0u64;
-        let mut buffer = vec![0u8; BUFFER_SIZE];
+    /// Layout of the combined BIN, in output order
+    ///
+    /// [`combine`](Self::combine), [`plan`](Self::plan) and
+    /// [`reader`](Self::reader) all work from this list.
+    fn segments(&self, cue_sheet: &CueSheet) -> Vec<Segment> {
+        if self.is_plain_copy(cue_sheet) {
+            let file = &cue_sheet.files[0];
+            let track = &file.tracks[0];
+            return vec![Segment::Track {
+                filename: file.filename.clone(),
+                number: track.number,
+                track_type: track.track_type,
+                extent: TrackExtent {
+                    offset: 0,
+                    length: file.file_size,
+                    sectors: (file.file_size / SECTOR_SIZE as u64) as u32,
+                },
+                rebuild: false,
+            }];
+        }
 
-        // Process each FILE entry in the CUE
+        let mut segments = Vec::new();
         for file_obj in &cue_sheet.files {
-            let input_path = cue_dir.join(&file_obj.filename);
-
-            let mut input_file = File::open(&input_path)
-                .with_context(|| format!("Failed to open BIN: {}", file_obj.filename))?;
-
-            if cue_sheet.files.len() > 1 {
-                // Multi-file case: each FILE is a complete track
-                Self::process_multifile_track(
-                    &mut input_file,
-                    &mut output_file,
-                    file_obj,
-                    &mut buffer,
-                    &mut total_bytes,
-                )?;
-            } else {
-                // Single-file case: extract tracks by MSF position
-                Self::process_singlefile_tracks(
-                    &mut input_file,
-                    &mut output_file,
-                    file_obj,
-                    &mut buffer,
-                    &mut total_bytes,
-                )?;
+            if let Some(format) = file_obj.audio_format {
+                segments.push(Segment::Decode {
+                    filename: file_obj.filename.clone(),
+                    format,
+                    length: file_obj.file_size,
+                });
+                continue;
+            }
+            for (track, extent) in file_obj.tracks.iter().zip(file_obj.track_extents()) {
+                segments.push(Segment::Track {
+                    filename: file_obj.filename.clone(),
+                    number: track.number,
+                    track_type: track.track_type,
+                    extent,
+                    rebuild: self.rebuilds(track.track_type),
+                });
             }
         }
+        segments
+    }
 
-        output_file.flush()?;
+    /// Single raw file with a single track: copied as-is
+    fn is_plain_copy(&self, cue_sheet: &CueSheet) -> bool {
+        let needs_conversion = cue_sheet
+            .files
+            .iter()
+            .flat_map(|f| &f.tracks)
+            .any(|t| t.sector_size() != SECTOR_SIZE);
+        cue_sheet.files.len() == 1
+            && cue_sheet.get_total_tracks() == 1
+            && !self.repair
+            && !needs_conversion
+    }
 
-        // Recalculate MSF positions for multi-file CUEs
-        if cue_sheet.files.len() > 1 {
-            println!("  Recalculating MSF positions for combined BIN...");
-            cue_sheet.recalculate_msf_for_combined();
+    /// Whether sectors of a track are rebuilt rather than copied
+    fn rebuilds(&self, track_type: TrackType) -> bool {
+        track_type != TrackType::Audio && (self.repair || track_type.sector_size() != SECTOR_SIZE)
+    }
+
+    /// Write all tracks into the output file
+    fn combine_tracks(
+        &self,
+        cue_sheet: &mut CueSheet,
+        cue_dir: &Path,
+        output_path: &Path,
+    ) -> Result<CombinedBinInfo> {
+        let total_tracks = cue_sheet.get_total_tracks();
+        let plain_copy = self.is_plain_copy(cue_sheet);
+        let segments = self.segments(cue_sheet);
+
+        let label = if plain_copy {
+            // Special case: single raw file with single track - just copy it
+            status!(
+                "  Single track detected, copying: {}",
+                cue_sheet.files[0].filename
+            );
+            "Copying"
+        } else {
+            status!(
+                "  Combining {} track(s) from {} file(s)...",
+                total_tracks,
+                cue_sheet.files.len()
+            );
+            "Combining"
+        };
+
+        let output_file = File::create(output_path).map_err(|e| Error::io(output_path, e))?;
+        let mut progress = self.progress.borrow_mut();
+        let mut output_file = ProgressWriter::new(
+            output_file,
+            progress.as_mut(),
+            label,
+            segments.iter().map(segment_len).sum(),
+        );
+
+        let mut total_bytes = 0u64;
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+
+        for segment in &segments {
+            let input_path = cue_dir.join(segment.filename());
+
+            match segment {
+                Segment::Decode {
+                    filename,
+                    format,
+                    length,
+                } => {
+                    // Compressed/WAVE audio: decode to raw CDDA sectors
+                    status!("  Decoding: {} ({}, {} bytes)", filename, format, length);
+                    let mut writer = BufWriter::with_capacity(BUFFER_SIZE, &mut output_file);
+                    audio::decode_to_cdda(&input_path, *format, &mut writer, *length)?;
+                    writer.flush().map_err(|e| Error::io(output_path, e))?;
+                    total_bytes += length;
+                }
+                Segment::Track {
+                    filename,
+                    number,
+                    track_type,
+                    extent,
+                    ..
+                } => {
+                    if !plain_copy {
+                        status!(
+                            "    Track {:02} [{}]: {} at byte {} ({} bytes)",
+                            number,
+                            track_type,
+                            filename,
+                            extent.offset,
+                            extent.length
+                        );
+                    }
+                    let mut input = segment
+                        .open(&input_path, total_bytes)
+                        .map_err(|e| Error::io(&input_path, e))?;
+                    loop {
+                        let read = input
+                            .read(&mut buffer)
+                            .map_err(|e| Error::io(&input_path, e))?;
+                        if read == 0 {
+                            break;
+                        }
+                        output_file
+                            .write_all(&buffer[..read])
+                            .map_err(|e| Error::io(output_path, e))?;
+                        total_bytes += read as u64;
+                    }
+                }
+            }
+        }
+
+        output_file
+            .finish()
+            .map_err(|e| Error::io(output_path, e))?;
+        if plain_copy {
+            Self::fix_track01_pregap(cue_sheet);
+        } else {
+            Self::finish_layout(cue_sheet)?;
         }
 
         Ok(CombinedBinInfo {
@@ -94,115 +303,364 @@ impl BinCombiner {
         })
     }
 
-    /// Handle single-file, single-track case with proper pregap setup
-    fn handle_single_file(
-        cue_sheet: &mut CueSheet,
-        cue_dir: &Path,
-        output_path: &Path,
-    ) -> Result<CombinedBinInfo> {
-        let input_path = cue_dir.join(&cue_sheet.files[0].filename);
-        println!(
-            "  Single track detected, copying: {}",
-            cue_sheet.files[0].filename
-        );
+    /// Move the CUE sheet indexes to their positions in the combined BIN
+    fn finish_layout(cue_sheet: &mut CueSheet) -> Result<()> {
+        // Recalculate MSF positions for multi-file CUEs
+        if cue_sheet.files.len() > 1 {
+            status!("  Recalculating MSF positions for combined BIN...");
+            cue_sheet.recalculate_msf_for_combined()?;
+        } else if cue_sheet.get_total_tracks() == 1 {
+            Self::fix_track01_pregap(cue_sheet);
+        }
 
-        std::fs::copy(&input_path, output_path).context("Failed to copy single BIN file")?;
+        Self::upgrade_track_types(cue_sheet);
+        Ok(())
+    }
 
-        let file_size = std::fs::metadata(output_path)?.len();
+    /// Record the converted 2352-byte layout in the CUE sheet
+    fn upgrade_track_types(cue_sheet: &mut CueSheet) {
+        for file in &mut cue_sheet.files {
+            file.file_size = file.sector_count() as u64 * SECTOR_SIZE as u64;
 
+            for track in &mut file.tracks {
+                let raw_type = track.track_type.raw_type();
+                if raw_type != track.track_type {
+                    status!(
+                        "    Track {:02}: upgraded {} -> {}",
+                        track.number,
+                        track.track_type,
+                        raw_type
+                    );
+                    track.track_type = raw_type;
+                }
+            }
+        }
+    }
+
+    /// Set up the Track 01 pregap for single-track games
+    fn fix_track01_pregap(cue_sheet: &mut CueSheet) {
         // CRITICAL: Even for single-file games, Track 01 must have proper pregap
         // INDEX 00 = 00:00:00, INDEX 01 = 00:02:00 (150 sectors pregap)
-        println!("  Fixing Track 01 indexes for single-file game...");
+        status!("  Fixing Track 01 indexes for single-file game...");
         if let Some(file) = cue_sheet.files.get_mut(0) {
             if let Some(track) = file.tracks.get_mut(0) {
-                track.index00_msf = Some(Msf::from_sectors(0));
-                track.index01_msf = Msf::from_sectors(150);
-                println!("    Track 01: INDEX 00=00:00:00 INDEX 01=00:02:00");
+                track.disc = Some(DiscIndexes::TRACK01);
+                status!("    Track 01: INDEX 00=00:00:00 INDEX 01=00:02:00");
             }
         }
-
-        Ok(CombinedBinInfo {
-            total_bytes: file_size,
-            track_count: 1,
-        })
     }
+}
 
-    /// Process multi-file track (each FILE is a complete track)
-    fn process_multifile_track(
-        input_file: &mut File,
-        output_file: &mut File,
-        file_obj: &crate::cue::FileEntry,
-        buffer: &mut [u8],
-        total_bytes: &mut u64,
-    ) -> Result<()> {
-        println!(
-            "  Processing: {} ({} bytes)",
-            file_obj.filename, file_obj.file_size
-        );
+/// One piece of the combined BIN
+enum Segment {
+    /// A track of a raw FILE, copied as-is or with every sector rebuilt
+    Track {
+        filename: String,
+        number: u8,
+        track_type: TrackType,
+        extent: TrackExtent,
+        rebuild: bool,
+    },
+    /// A WAVE, FLAC, Ogg Vorbis or MP3 file decoded to CDDA
+    Decode {
+        filename: String,
+        format: AudioFormat,
+        length: u64,
+    },
+}
 
-        for track in &file_obj.tracks {
-            println!(
-                "    Track {:02} [{}]: Complete file",
-                track.number, track.track_type
-            );
+impl Segment {
+    fn filename(&self) -> &str {
+        match self {
+            Segment::Track { filename, .. } | Segment::Decode { filename, .. } => filename,
         }
+    }
 
-        // Copy entire file
-        loop {
-            let bytes_read = input_file.read(buffer)?;
-            if bytes_read == 0 {
-                break;
+    /// Read the bytes of the segment from `path`, the segment starting at
+    /// byte `start` of the combined BIN
+    fn open(&self, path: &Path, start: u64) -> std::io::Result<Box<dyn Read>> {
+        match *self {
+            Segment::Track {
+                track_type,
+                extent,
+                rebuild,
+                ..
+            } => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(extent.offset))?;
+                let input = BufReader::with_capacity(BUFFER_SIZE, file.take(extent.length));
+                if rebuild {
+                    // Every sector is rebuilt at its position in the combined BIN
+                    let lba = Lba::new((start / SECTOR_SIZE as u64) as u32);
+                    Ok(Box::new(RebuildReader::new(input, track_type, lba)))
+                } else {
+                    Ok(Box::new(input))
+                }
+            }
+            Segment::Decode { format, length, .. } => {
+                let mut pcm = Vec::with_capacity(length as usize);
+                audio::decode_to_cdda(path, format, &mut pcm, length)
+                    .map_err(std::io::Error::other)?;
+                Ok(Box::new(Cursor::new(pcm)))
             }
-            output_file.write_all(&buffer[..bytes_read])?;
-            *total_bytes += bytes_read as u64;
         }
+    }
+}
 
-        Ok(())
+/// Bytes a segment adds to the combined BIN
+fn segment_len(segment: &Segment) -> u64 {
+    match segment {
+        Segment::Track {
+            extent,
+            rebuild: true,
+            ..
+        } => extent.sectors as u64 * SECTOR_SIZE as u64,
+        Segment::Track { extent, .. } => extent.length,
+        Segment::Decode { length, .. } => *length,
     }
+}
 
-    /// Process single-file with multiple tracks (extract by MSF position)
-    fn process_singlefile_tracks(
-        input_file: &mut File,
-        output_file: &mut File,
-        file_obj: &crate::cue::FileEntry,
-        buffer: &mut [u8],
-        total_bytes: &mut u64,
-    ) -> Result<()> {
-        let file_size = input_file.metadata()?.len();
+/// Error for a rebuilt sector that would lie past 99:59:74
+fn past_end_of_disc() -> std::io::Error {
+    std::io::Error::other("combined disc extends past 99:59:74")
+}
 
-        for (idx, track) in file_obj.tracks.iter().enumerate() {
-            let start_bytes = track.index01_msf.to_sectors() as u64 * track.sector_size() as u64;
+type OpenSegment = Box<dyn FnOnce() -> std::io::Result<Box<dyn Read>>>;
 
-            let end_bytes = if idx + 1 < file_obj.tracks.len() {
-                file_obj.tracks[idx + 1].index01_msf.to_sectors() as u64
-                    * file_obj.tracks[idx + 1].sector_size() as u64
-            } else {
-                file_size
-            };
+/// Concatenation of segments that are opened on first read
+#[derive(Default)]
+struct CombinedReader {
+    pending: VecDeque<OpenSegment>,
+    current: Option<Box<dyn Read>>,
+}
 
-            let track_bytes = end_bytes - start_bytes;
+impl CombinedReader {
+    fn push(&mut self, open: impl FnOnce() -> std::io::Result<Box<dyn Read>> + 'static) {
+        self.pending.push_back(Box::new(open));
+    }
+}
 
-            println!(
-                "    Track {:02} [{}]: MSF {} ({} bytes)",
-                track.number, track.track_type, track.index01_msf, track_bytes
-            );
+impl Read for CombinedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(current) = &mut self.current {
+                let read = current.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+            }
+            match self.pending.pop_front() {
+                Some(open) => self.current = Some(open()?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
 
-            // Seek to track start and copy data
-            input_file.seek(std::io::SeekFrom::Start(start_bytes))?;
+/// Rebuilds every sector of a data track at consecutive disc positions
+///
+/// `lba` is the position of the first sector, or None past 99:59:74.
+struct RebuildReader<R> {
+    input: R,
+    track_type: TrackType,
+    lba: Option<Lba>,
+    sector: [u8; SECTOR_SIZE],
+    position: usize,
+}
 
-            let mut remaining = track_bytes;
-            while remaining > 0 {
-                let to_read = (remaining as usize).min(BUFFER_SIZE);
-                let bytes_read = input_file.read(&mut buffer[..to_read])?;
-                if bytes_read == 0 {
-                    break;
-                }
-                output_file.write_all(&buffer[..bytes_read])?;
-                remaining -= bytes_read as u64;
-                *total_bytes += bytes_read as u64;
+impl<R: Read> RebuildReader<R> {
+    fn new(input: R, track_type: TrackType, lba: Option<Lba>) -> Self {
+        Self {
+            input,
+            track_type,
+            lba,
+            sector: [0; SECTOR_SIZE],
+            position: SECTOR_SIZE,
+        }
+    }
+}
+
+impl<R: Read> Read for RebuildReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == SECTOR_SIZE {
+            let mut input = [0u8; SECTOR_SIZE];
+            let input = &mut input[..self.track_type.sector_size()];
+            match self.input.read_exact(input) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(0),
+                Err(e) => return Err(e),
             }
+            let lba = self.lba.ok_or_else(past_end_of_disc)?;
+            self.sector = sector::rebuild_sector(input, self.track_type, lba);
+            self.lba = lba.checked_offset(1);
+            self.position = 0;
         }
 
-        Ok(())
+        let count = buf.len().min(SECTOR_SIZE - self.position);
+        buf[..count].copy_from_slice(&self.sector[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cue::{FileEntry, Track};
+    use crate::utils::Msf;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_combine_mixed_sector_sizes() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-combine-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 20 MODE2/2336 Form 1 sectors followed by 5 audio sectors in one BIN
+        let mut bin = Vec::new();
+        for i in 0..20u8 {
+            let mut sector = [i; 2336];
+            sector[..8].copy_from_slice(&[0, 0, 0x08, 0, 0, 0, 0x08, 0]);
+            bin.extend_from_slice(&sector);
+        }
+        let audio: Vec<u8> = (0..5 * SECTOR_SIZE).map(|i| (i % 251) as u8).collect();
+        bin.extend_from_slice(&audio);
+        std::fs::write(dir.join("disc.bin"), &bin).unwrap();
+
+        let mut file = FileEntry::new("disc.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2336, Lba::new(0).unwrap()));
+        file.tracks
+            .push(Track::new(2, TrackType::Audio, Lba::new(20).unwrap()));
+        file.file_size = bin.len() as u64;
+        let mut cue_sheet = CueSheet::new();
+        cue_sheet.files.push(file);
+
+        let mut planned = cue_sheet.clone();
+        let plan = BinCombiner::new(Vec::new(), false)
+            .plan(&mut planned)
+            .unwrap();
+
+        let mut streamed = Vec::new();
+        BinCombiner::new(Vec::new(), false)
+            .reader(&cue_sheet, &dir)
+            .read_to_end(&mut streamed)
+            .unwrap();
+
+        let progress = Arc::new(Mutex::new((0, 0)));
+        let output = dir.join("combined.bin");
+        let info = BinCombiner::new(Vec::new(), false)
+            .with_progress(Box::new(LastProgress(progress.clone())))
+            .combine(&mut cue_sheet, &dir, &output)
+            .unwrap();
+        let combined = std::fs::read(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(info.total_bytes, 25 * SECTOR_SIZE as u64);
+        assert_eq!(combined.len(), 25 * SECTOR_SIZE);
+        for (lba, sector) in combined.chunks(SECTOR_SIZE).take(20).enumerate() {
+            sector::verify_sector(sector, Lba::new(lba as u32).unwrap()).unwrap();
+            assert_eq!(sector[24], lba as u8);
+        }
+        assert_eq!(&combined[20 * SECTOR_SIZE..], &audio[..]);
+
+        let file = &cue_sheet.files[0];
+        assert_eq!(file.tracks[0].track_type, TrackType::Mode2_2352);
+        assert_eq!(file.file_size, combined.len() as u64);
+
+        assert_eq!(plan.total_bytes, info.total_bytes);
+        assert_eq!(planned.to_cue_text(), cue_sheet.to_cue_text());
+        assert_eq!(streamed, combined);
+        assert_eq!(
+            *progress.lock().unwrap(),
+            (info.total_bytes, info.total_bytes)
+        );
+    }
+
+    #[test]
+    fn test_combiner_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<BinCombiner>();
+        assert_send::<crate::vcd::VcdConverter>();
+    }
+
+    /// Records the announced total and the last reported byte count
+    struct LastProgress(Arc<Mutex<(u64, u64)>>);
+
+    impl ProgressReporter for LastProgress {
+        fn start(&mut self, _label: &str, total: u64) {
+            *self.0.lock().unwrap() = (total, 0);
+        }
+        fn update(&mut self, done: u64) {
+            self.0.lock().unwrap().1 = done;
+        }
+        fn finish(&mut self) {}
+    }
+
+    #[test]
+    fn test_combine_multi_file_leading_pregap() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-pregap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Track 02 only has INDEX 01 00:02:00: its file starts with the pregap
+        let data: Vec<u8> = (0..300 * SECTOR_SIZE).map(|i| (i % 7) as u8).collect();
+        let audio: Vec<u8> = (0..200 * SECTOR_SIZE).map(|i| (i % 13) as u8).collect();
+        std::fs::write(dir.join("track01.bin"), &data).unwrap();
+        std::fs::write(dir.join("track02.bin"), &audio).unwrap();
+
+        let mut cue_sheet = CueSheet::new();
+        let mut file = FileEntry::new("track01.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2352, Lba::new(0).unwrap()));
+        file.file_size = data.len() as u64;
+        cue_sheet.files.push(file);
+        let mut file = FileEntry::new("track02.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(2, TrackType::Audio, Lba::new(150).unwrap()));
+        file.file_size = audio.len() as u64;
+        cue_sheet.files.push(file);
+
+        let combiner = BinCombiner::new(Vec::new(), false);
+        let mut planned = cue_sheet.clone();
+        let plan = combiner.plan(&mut planned).unwrap();
+        let output = dir.join("combined.bin");
+        let info = combiner.combine(&mut cue_sheet, &dir, &output).unwrap();
+        let combined = std::fs::read(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(combined.len(), 500 * SECTOR_SIZE);
+        assert_eq!(&combined[..data.len()], &data[..]);
+        assert_eq!(&combined[data.len()..], &audio[..]);
+        assert_eq!(info.total_bytes, combined.len() as u64);
+        assert_eq!(plan.total_bytes, info.total_bytes);
+
+        // The CUE sheet describes the same 500 sectors as the BIN
+        let sectors: u32 = cue_sheet.files.iter().map(|f| f.sector_count()).sum();
+        assert_eq!(sectors, 500);
+        let track = &cue_sheet.files[1].tracks[0];
+        assert_eq!(track.index01, Lba::new(150).unwrap());
+        assert_eq!(track.disc.unwrap().index01, Msf::new(0, 6, 0));
+    }
+
+    #[test]
+    fn test_plan_multi_file() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-plan-{}", std::process::id()));
+        let mut disc = crate::synth::SyntheticDisc::new("SLUS_012.34");
+        disc.audio_tracks = vec!["tone:100".parse().unwrap(), "silence:80".parse().unwrap()];
+        disc.multi_file = true;
+        let cue_path = disc.write(&dir, "plan").unwrap();
+
+        let mut cue_sheet = CueSheet::parse(&cue_path).unwrap();
+        cue_sheet.load_file_sizes(&dir).unwrap();
+        let mut planned = cue_sheet.clone();
+
+        let combiner = BinCombiner::new(Vec::new(), false);
+        let plan = combiner.plan(&mut planned).unwrap();
+        let info = combiner
+            .combine(&mut cue_sheet, &dir, &dir.join("combined.bin"))
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(plan.total_bytes, info.total_bytes);
+        assert_eq!(planned.to_cue_text(), cue_sheet.to_cue_text());
     }
 }
@@ -1,10 +1,11 @@
 // src/cue.rs
-use anyhow::{bail, Context, Result};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use crate::error::{Error, Result};
 use std::path::Path;
 
-use crate::utils::Msf;
+use crate::audio::{self, AudioFormat};
+use crate::resolve::{self, Substitution};
+use crate::status;
+use crate::utils::{Lba, Msf};
 
 /// CD-ROM track type enumeration
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -16,18 +17,22 @@ pub enum TrackType {
     Mode2_2352,
 }
 
-impl TrackType {
-    pub fn from_str(s: &str) -> Option<Self> {
+impl std::str::FromStr for TrackType {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         match s {
-            "AUDIO" => Some(TrackType::Audio),
-            "MODE1/2048" => Some(TrackType::Mode1_2048),
-            "MODE1/2352" => Some(TrackType::Mode1_2352),
-            "MODE2/2336" => Some(TrackType::Mode2_2336),
-            "MODE2/2352" => Some(TrackType::Mode2_2352),
-            _ => None,
+            "AUDIO" => Ok(TrackType::Audio),
+            "MODE1/2048" => Ok(TrackType::Mode1_2048),
+            "MODE1/2352" => Ok(TrackType::Mode1_2352),
+            "MODE2/2336" => Ok(TrackType::Mode2_2336),
+            "MODE2/2352" => Ok(TrackType::Mode2_2352),
+            _ => Err(()),
         }
     }
+}
 
+impl TrackType {
     pub fn sector_size(&self) -> usize {
         match self {
             TrackType::Audio => 2352,
@@ -37,6 +42,17 @@ impl TrackType {
             TrackType::Mode2_2352 => 2352,
         }
     }
+
+    /// Track type after rebuilding sectors to the raw 2352-byte layout
+    ///
+    /// MODE1/2048 images of PlayStation discs are stripped Mode 2 Form 1
+    /// data, so they are upgraded to MODE2/2352 like MODE2/2336 tracks.
+    pub fn raw_type(&self) -> TrackType {
+        match self {
+            TrackType::Mode1_2048 | TrackType::Mode2_2336 => TrackType::Mode2_2352,
+            other => *other,
+        }
+    }
 }
 
 impl std::fmt::Display for TrackType {
@@ -52,22 +68,64 @@ impl std::fmt::Display for TrackType {
     }
 }
 
+impl serde::Serialize for TrackType {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
 /// CUE track structure
-#[derive(Debug, Clone)]
+///
+/// INDEX positions are sectors of the track's FILE, as the CUE gives them.
+/// Combining never rewrites them; the positions the VCD header gets are
+/// kept apart in [`disc`](Self::disc).
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Track {
     pub number: u8,
     pub track_type: TrackType,
-    pub index00_msf: Option<Msf>,
-    pub index01_msf: Msf,
+    #[serde(rename = "index00_msf", serialize_with = "serialize_index00")]
+    pub index00: Option<Lba>,
+    #[serde(rename = "index01_msf", serialize_with = "serialize_index01")]
+    pub index01: Lba,
+    /// Disc positions, set once the combined layout is worked out
+    #[serde(skip)]
+    pub disc: Option<DiscIndexes>,
+}
+
+/// INDEX positions of a track on the disc, as written to the VCD header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscIndexes {
+    pub index00: Msf,
+    pub index01: Msf,
+}
+
+impl DiscIndexes {
+    /// Track 01 of a combined disc: its 2-second pregap, then LBA 0
+    pub const TRACK01: DiscIndexes = DiscIndexes {
+        index00: Msf {
+            minutes: 0,
+            seconds: 0,
+            frames: 0,
+        },
+        index01: Msf {
+            minutes: 0,
+            seconds: 2,
+            frames: 0,
+        },
+    };
 }
 
 impl Track {
-    pub fn new(number: u8, track_type: TrackType, index01_msf: Msf) -> Self {
+    pub fn new(number: u8, track_type: TrackType, index01: Lba) -> Self {
         Self {
             number,
             track_type,
-            index00_msf: None,
-            index01_msf,
+            index00: None,
+            index01,
+            disc: None,
         }
     }
 
@@ -78,15 +136,66 @@ impl Track {
     pub fn sector_size(&self) -> usize {
         self.track_type.sector_size()
     }
+
+    /// First index of the track (INDEX 00 when the pregap is present)
+    pub fn first_index(&self) -> Lba {
+        self.index00.unwrap_or(self.index01)
+    }
+
+    /// Sectors of INDEX 00 pregap before INDEX 01
+    pub fn pregap_sectors(&self) -> u32 {
+        self.index01.frames_since(self.first_index()).unwrap_or(0)
+    }
+
+    /// Positions for the VCD header
+    ///
+    /// Tracks the combiner did not place (the later tracks of a
+    /// single-file CUE, or any track of a CUE given to `convert`) keep the
+    /// positions their CUE gives them.
+    pub fn disc_indexes(&self) -> DiscIndexes {
+        self.disc.unwrap_or(DiscIndexes {
+            index00: self.first_index().to_relative_msf(),
+            index01: self.index01.to_relative_msf(),
+        })
+    }
+}
+
+fn serialize_index00<S: serde::Serializer>(
+    index: &Option<Lba>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&index.map(Lba::to_relative_msf), serializer)
+}
+
+fn serialize_index01<S: serde::Serializer>(
+    index: &Lba,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&index.to_relative_msf(), serializer)
+}
+
+/// Location of a track's data inside its FILE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackExtent {
+    /// Byte offset of the first sector (pregap included; the first track
+    /// of a FILE starts at byte 0)
+    pub offset: u64,
+    /// Length in bytes
+    pub length: u64,
+    /// Length in sectors of the track's own sector size
+    pub sectors: u32,
 }
 
 /// CUE file entry (FILE directive)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct FileEntry {
     pub filename: String,
     pub file_type: String,
     pub tracks: Vec<Track>,
+    /// Size in bytes; decoded CDDA size for compressed audio files
     pub file_size: u64,
+    /// Set when the file is WAV/FLAC/Ogg audio that has to be decoded
+    pub audio_format: Option<AudioFormat>,
 }
 
 impl FileEntry {
@@ -96,174 +205,697 @@ impl FileEntry {
             file_type,
             tracks: Vec::new(),
             file_size: 0,
+            audio_format: None,
         }
     }
+
+    /// Byte ranges of every track in the file
+    ///
+    /// The extents cover the whole file: sectors before the first INDEX
+    /// (a pregap given only as `INDEX 01 00:02:00`, say) belong to the
+    /// first track, as they do on the disc. INDEX positions count sectors,
+    /// so byte offsets are accumulated from the sector size of each
+    /// preceding track. This keeps discs that mix MODE2/2336 data with
+    /// 2352-byte audio in one file addressable.
+    pub fn track_extents(&self) -> Vec<TrackExtent> {
+        let mut extents = Vec::with_capacity(self.tracks.len());
+        let start = |idx: usize| match idx {
+            0 => 0,
+            _ => self.tracks[idx].first_index().get(),
+        };
+        let mut offset = 0u64;
+
+        for (idx, track) in self.tracks.iter().enumerate() {
+            let sector_size = track.sector_size() as u64;
+            let length = if idx + 1 < self.tracks.len() {
+                let sectors = start(idx + 1).saturating_sub(start(idx));
+                (sectors as u64 * sector_size).min(self.file_size.saturating_sub(offset))
+            } else {
+                self.file_size.saturating_sub(offset)
+            };
+
+            extents.push(TrackExtent {
+                offset,
+                length,
+                sectors: (length / sector_size) as u32,
+            });
+            offset += length;
+        }
+        extents
+    }
+
+    /// Number of sectors in the file, honoring per-track sector sizes
+    pub fn sector_count(&self) -> u32 {
+        self.track_extents().iter().map(|e| e.sectors).sum()
+    }
 }
 
-/// Complete CUE sheet structure
-#[derive(Debug, Clone)]
-pub struct CueSheet {
-    pub files: Vec<FileEntry>,
+/// Severity of a CUE diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
 }
 
-impl CueSheet {
-    pub fn new() -> Self {
-        Self { files: Vec::new() }
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
     }
+}
 
-    /// Parse a CUE file and validate its structure
-    pub fn parse(cue_path: &Path) -> Result<Self> {
-        let file = File::open(cue_path)
-            .with_context(|| format!("Failed to open CUE file: {}", cue_path.display()))?;
-        let reader = BufReader::new(file);
+impl serde::Serialize for Severity {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
 
-        let mut cue_sheet = CueSheet::new();
-        let mut current_file: Option<FileEntry> = None;
-        let mut current_track: Option<Track> = None;
+/// What an error diagnostic turns into when parsing fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiagnosticKind {
+    Syntax,
+    TrackType,
+    Structure,
+}
 
-        for line in reader.lines() {
-            let line = line?;
-            let trimmed = line.trim();
+/// Problem found while parsing a CUE sheet
+///
+/// `line` and `column` are 1-based; structural problems that do not belong
+/// to a line (such as an empty sheet) use line 0.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(skip)]
+    kind: DiagnosticKind,
+}
 
-            // Skip empty lines and comments
-            if trimmed.is_empty() || trimmed.starts_with("REM") {
-                continue;
+impl Diagnostic {
+    fn into_error(self, cue_path: &Path) -> Error {
+        match self.kind {
+            DiagnosticKind::Syntax => Error::CueSyntax {
+                path: cue_path.to_path_buf(),
+                line: self.line,
+                column: self.column,
+                message: self.message,
+            },
+            DiagnosticKind::TrackType => {
+                Error::UnsupportedTrackType(format!("{} (line {})", self.message, self.line))
             }
-
-            if trimmed.starts_with("FILE ") {
-                Self::handle_file_directive(
-                    &mut cue_sheet,
-                    &mut current_file,
-                    &mut current_track,
-                    trimmed,
-                )?;
-            } else if trimmed.starts_with("TRACK ") {
-                Self::handle_track_directive(&mut current_file, &mut current_track, trimmed)?;
-            } else if trimmed.starts_with("INDEX ") {
-                Self::handle_index_directive(&mut current_track, trimmed)?;
+            DiagnosticKind::Structure if self.line > 0 => {
+                Error::InvalidCue(format!("line {}: {}", self.line, self.message))
             }
+            DiagnosticKind::Structure => Error::InvalidCue(self.message),
         }
+    }
+}
 
-        // Save last file and track
-        if let Some(mut file) = current_file {
-            if let Some(track) = current_track {
-                file.tracks.push(track);
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.line > 0 {
+            write!(f, "{}:{}: ", self.line, self.column)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// A whitespace-separated CUE token
+struct Token<'a> {
+    /// Byte offset in the line
+    offset: usize,
+    text: &'a str,
+    quoted: bool,
+}
+
+impl Token<'_> {
+    fn column(&self) -> usize {
+        self.offset + 1
+    }
+}
+
+/// Split a CUE line into tokens, keeping quoted strings together
+///
+/// Returns the tokens and whether a quote was left open.
+fn tokenize(line: &str) -> (Vec<Token<'_>>, bool) {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        if c == '"' {
+            chars.next();
+            let text_start = start + 1;
+            let mut end = None;
+            for (i, c) in chars.by_ref() {
+                if c == '"' {
+                    end = Some(i);
+                    break;
+                }
+            }
+            match end {
+                Some(end) => tokens.push(Token {
+                    offset: start,
+                    text: &line[text_start..end],
+                    quoted: true,
+                }),
+                None => {
+                    tokens.push(Token {
+                        offset: start,
+                        text: &line[text_start..],
+                        quoted: true,
+                    });
+                    return (tokens, true);
+                }
             }
-            cue_sheet.files.push(file);
+        } else {
+            let mut end = line.len();
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_whitespace() {
+                    end = i;
+                    break;
+                }
+                chars.next();
+            }
+            tokens.push(Token {
+                offset: start,
+                text: &line[start..end],
+                quoted: false,
+            });
+        }
+    }
+
+    (tokens, false)
+}
+
+/// The filename of a FILE line, with the byte range it takes up
+///
+/// The range includes the quotes of a quoted name. Names are taken the way
+/// the parser takes them, so an unquoted name with spaces runs up to the
+/// file type.
+pub(crate) fn file_line_name(line: &str) -> Option<(&str, std::ops::Range<usize>)> {
+    let (tokens, unterminated) = tokenize(line);
+    if unterminated || tokens.len() < 3 || !tokens[0].text.eq_ignore_ascii_case("FILE") {
+        return None;
+    }
+    if tokens.len() > 3 && !tokens[1].quoted {
+        let type_offset = tokens[tokens.len() - 1].offset;
+        let name = line[tokens[1].offset..type_offset].trim_end();
+        return Some((name, tokens[1].offset..tokens[1].offset + name.len()));
+    }
+    let name = &tokens[1];
+    let quotes = if name.quoted { 2 } else { 0 };
+    Some((
+        name.text,
+        name.offset..name.offset + name.text.len() + quotes,
+    ))
+}
+
+/// CUE commands that carry no information psx-vcd needs
+const IGNORED_COMMANDS: &[&str] = &[
+    "CATALOG",
+    "CDTEXTFILE",
+    "FLAGS",
+    "ISRC",
+    "PERFORMER",
+    "SONGWRITER",
+    "TITLE",
+];
+
+/// Line-by-line CUE parser that collects diagnostics instead of stopping
+struct CueParser {
+    lenient: bool,
+    sheet: CueSheet,
+    diagnostics: Vec<Diagnostic>,
+    current_file: Option<FileEntry>,
+    current_track: Option<PendingTrack>,
+    /// Tracks that appeared before any FILE (lenient mode)
+    orphan_tracks: Vec<Track>,
+    last_track_number: u8,
+    first_index01_line: Option<usize>,
+    line: usize,
+}
+
+struct PendingTrack {
+    track: Track,
+    line: usize,
+    column: usize,
+    has_index01: bool,
+    orphan: bool,
+}
+
+impl CueParser {
+    fn new(lenient: bool) -> Self {
+        Self {
+            lenient,
+            sheet: CueSheet::new(),
+            diagnostics: Vec::new(),
+            current_file: None,
+            current_track: None,
+            orphan_tracks: Vec::new(),
+            last_track_number: 0,
+            first_index01_line: None,
+            line: 0,
+        }
+    }
+
+    fn report(&mut self, column: usize, severity: Severity, kind: DiagnosticKind, message: String) {
+        self.diagnostics.push(Diagnostic {
+            line: self.line,
+            column,
+            severity,
+            message,
+            kind,
+        });
+    }
+
+    fn error(&mut self, column: usize, message: String) {
+        self.report(column, Severity::Error, DiagnosticKind::Syntax, message);
+    }
+
+    fn warning(&mut self, column: usize, message: String) {
+        self.report(column, Severity::Warning, DiagnosticKind::Syntax, message);
+    }
+
+    /// A mistake that lenient mode recovers from: a warning when lenient,
+    /// an error otherwise
+    fn recoverable(&mut self, column: usize, message: String) {
+        if self.lenient {
+            self.warning(column, message);
+        } else {
+            self.error(column, format!("{} (use --lenient to accept)", message));
         }
+    }
 
-        cue_sheet.validate()?;
-        Ok(cue_sheet)
+    /// Check that a keyword is uppercase, reporting lowercase ones
+    fn keyword(&mut self, token: &Token) -> String {
+        let upper = token.text.to_ascii_uppercase();
+        if upper != token.text {
+            self.recoverable(
+                token.column(),
+                format!("keyword '{}' should be uppercase", token.text),
+            );
+        }
+        upper
     }
 
-    /// Handle FILE directive
-    fn handle_file_directive(
-        cue_sheet: &mut CueSheet,
-        current_file: &mut Option<FileEntry>,
-        current_track: &mut Option<Track>,
-        trimmed: &str,
-    ) -> Result<()> {
-        // Save previous file if exists
-        if let Some(mut file) = current_file.take() {
-            if let Some(track) = current_track.take() {
-                file.tracks.push(track);
+    fn parse(mut self, text: &str) -> (CueSheet, Vec<Diagnostic>) {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+        for (idx, raw) in text.lines().enumerate() {
+            self.line = idx + 1;
+            let (tokens, unclosed_quote) = tokenize(raw);
+            let Some(command) = tokens.first() else {
+                continue;
+            };
+            if unclosed_quote {
+                let column = tokens.last().map(|t| t.column()).unwrap_or(1);
+                self.recoverable(column, "missing closing quote".to_string());
+            }
+
+            if command.text.eq_ignore_ascii_case("REM") {
+                continue;
+            }
+            let keyword = self.keyword(command);
+            match keyword.as_str() {
+                "FILE" => self.file_command(raw, &tokens),
+                "TRACK" => self.track_command(&tokens),
+                "INDEX" => self.index_command(&tokens),
+                "PREGAP" | "POSTGAP" => self.warning(
+                    command.column(),
+                    format!("{} is not supported and is ignored", keyword),
+                ),
+                k if IGNORED_COMMANDS.contains(&k) => {}
+                _ => self.warning(
+                    command.column(),
+                    format!("unknown command '{}' ignored", command.text),
+                ),
             }
-            cue_sheet.files.push(file);
         }
 
-        // Parse new FILE
-        let parts: Vec<&str> = trimmed.splitn(2, '"').collect();
-        if parts.len() < 2 {
-            bail!("Invalid FILE line: {}", trimmed);
+        self.finish_file();
+        self.finish();
+        self.diagnostics
+            .sort_by_key(|d| (d.line == 0, d.line, d.column));
+        (self.sheet, self.diagnostics)
+    }
+
+    fn file_command(&mut self, raw: &str, tokens: &[Token]) {
+        self.finish_file();
+
+        if tokens.len() < 3 {
+            self.error(
+                tokens[0].column(),
+                "FILE needs a filename and a file type".to_string(),
+            );
+            return;
         }
-        let rest: Vec<&str> = parts[1].splitn(2, '"').collect();
-        if rest.len() < 2 {
-            bail!("Invalid FILE line: {}", trimmed);
+
+        let (filename, type_token) = if tokens.len() > 3 && !tokens[1].quoted {
+            // Unquoted filename with spaces: take everything up to the type
+            self.recoverable(
+                tokens[1].column(),
+                "filename containing spaces should be quoted".to_string(),
+            );
+            let type_token = &tokens[tokens.len() - 1];
+            let name = raw[tokens[1].offset..type_token.offset].trim();
+            (name.to_string(), type_token)
+        } else {
+            if let Some(extra) = tokens.get(3) {
+                self.warning(
+                    extra.column(),
+                    "extra text after file type ignored".to_string(),
+                );
+            }
+            (tokens[1].text.to_string(), &tokens[2])
+        };
+        if filename.contains('"') {
+            self.error(
+                tokens[1].column(),
+                "filename contains a stray quote".to_string(),
+            );
+            return;
         }
-        let filename = rest[0].to_string();
-        let file_type = rest[1].trim().to_string();
 
-        *current_file = Some(FileEntry::new(filename, file_type));
-        Ok(())
+        let file_type = self.keyword(type_token);
+        match file_type.as_str() {
+            // Contents are probed when the file is loaded; anything that
+            // is not a known audio container is copied as raw sectors
+            "BINARY" | "WAVE" | "MOTOROLA" | "AIFF" => {}
+            "MP3" => self.recoverable(
+                type_token.column(),
+                "file type MP3 is non-standard, decoding it as audio".to_string(),
+            ),
+            _ => self.error(
+                type_token.column(),
+                format!("unknown file type '{}'", type_token.text),
+            ),
+        }
+
+        let mut file = FileEntry::new(filename, file_type);
+        file.tracks.append(&mut self.orphan_tracks);
+        self.current_file = Some(file);
     }
 
-    /// Handle TRACK directive
-    fn handle_track_directive(
-        current_file: &mut Option<FileEntry>,
-        current_track: &mut Option<Track>,
-        trimmed: &str,
-    ) -> Result<()> {
-        // Save previous track if exists
-        if let Some(ref mut file) = current_file {
-            if let Some(track) = current_track.take() {
-                file.tracks.push(track);
+    fn track_command(&mut self, tokens: &[Token]) {
+        self.finish_track();
+
+        if tokens.len() < 3 {
+            self.error(
+                tokens[0].column(),
+                "TRACK needs a number and a track type".to_string(),
+            );
+            return;
+        }
+
+        let Ok(mut number) = tokens[1].text.parse::<u8>() else {
+            self.error(
+                tokens[1].column(),
+                format!("invalid track number '{}'", tokens[1].text),
+            );
+            return;
+        };
+
+        if number == 0 || number > 99 {
+            self.error(
+                tokens[1].column(),
+                format!("track number {} is outside 1-99", number),
+            );
+            return;
+        }
+        if self.last_track_number >= 99 {
+            self.error(
+                tokens[0].column(),
+                "more than 99 tracks; a disc holds at most 99".to_string(),
+            );
+            return;
+        }
+
+        let type_name = self.keyword(&tokens[2]);
+        let Ok(track_type) = type_name.parse::<TrackType>() else {
+            self.report(
+                tokens[2].column(),
+                Severity::Error,
+                DiagnosticKind::TrackType,
+                format!("unknown track type '{}'", tokens[2].text),
+            );
+            return;
+        };
+
+        let expected = self.last_track_number + 1;
+        if number != expected {
+            if self.lenient {
+                self.warning(
+                    tokens[1].column(),
+                    format!("TRACK {:02} renumbered to {:02}", number, expected),
+                );
+                number = expected;
+            } else {
+                self.error(
+                    tokens[1].column(),
+                    format!(
+                        "TRACK {:02} out of sequence, expected {:02} (use --lenient to renumber)",
+                        number, expected
+                    ),
+                );
             }
         }
+        self.last_track_number = number;
 
-        let parts: Vec<&str> = trimmed.split_whitespace().collect();
-        if parts.len() < 3 {
-            bail!("Invalid TRACK line: {}", trimmed);
+        let orphan = self.current_file.is_none();
+        if orphan {
+            self.recoverable(tokens[0].column(), "TRACK before any FILE".to_string());
         }
 
-        let track_num: u8 = parts[1]
-            .parse()
-            .with_context(|| format!("Invalid track number: {}", parts[1]))?;
-        let track_type = TrackType::from_str(parts[2])
-            .ok_or_else(|| anyhow::anyhow!("Unknown track type: {}", parts[2]))?;
+        self.current_track = Some(PendingTrack {
+            track: Track::new(number, track_type, Lba::ZERO),
+            line: self.line,
+            column: tokens[0].column(),
+            has_index01: false,
+            orphan,
+        });
+    }
 
-        *current_track = Some(Track::new(track_num, track_type, Msf::new(0, 0, 0)));
-        Ok(())
+    fn index_command(&mut self, tokens: &[Token]) {
+        if self.current_track.is_none() {
+            self.recoverable(tokens[0].column(), "INDEX before any TRACK".to_string());
+            return;
+        }
+        if tokens.len() < 3 {
+            self.error(
+                tokens[0].column(),
+                "INDEX needs a number and an MSF position".to_string(),
+            );
+            return;
+        }
+
+        let Ok(number) = tokens[1].text.parse::<u8>() else {
+            self.error(
+                tokens[1].column(),
+                format!("invalid index number '{}'", tokens[1].text),
+            );
+            return;
+        };
+        let Ok(msf) = tokens[2].text.parse::<Msf>() else {
+            self.error(
+                tokens[2].column(),
+                format!("invalid MSF '{}'", tokens[2].text),
+            );
+            return;
+        };
+        let Some(lba) = Lba::from_relative_msf(msf) else {
+            self.error(
+                tokens[2].column(),
+                format!("INDEX {} is past the end of a CD (99:59:74 on disc)", msf),
+            );
+            return;
+        };
+
+        let is_first_track = self.sheet.files.iter().all(|f| f.tracks.is_empty())
+            && self
+                .current_file
+                .as_ref()
+                .map(|f| f.tracks.is_empty())
+                .unwrap_or(true)
+            && self.orphan_tracks.is_empty();
+        let line = self.line;
+        let pending = self.current_track.as_mut().unwrap();
+        match number {
+            0 => pending.track.index00 = Some(lba),
+            1 => {
+                pending.track.index01 = lba;
+                pending.has_index01 = true;
+                if is_first_track {
+                    self.first_index01_line = Some(line);
+                }
+            }
+            _ => self.warning(tokens[1].column(), format!("INDEX {:02} ignored", number)),
+        }
     }
 
-    /// Handle INDEX directive
-    fn handle_index_directive(current_track: &mut Option<Track>, trimmed: &str) -> Result<()> {
-        let parts: Vec<&str> = trimmed.split_whitespace().collect();
-        if parts.len() < 3 {
-            bail!("Invalid INDEX line: {}", trimmed);
+    fn finish_track(&mut self) {
+        let Some(pending) = self.current_track.take() else {
+            return;
+        };
+
+        if !pending.has_index01 {
+            self.diagnostics.push(Diagnostic {
+                line: pending.line,
+                column: pending.column,
+                severity: Severity::Error,
+                message: format!("TRACK {:02} has no INDEX 01", pending.track.number),
+                kind: DiagnosticKind::Structure,
+            });
         }
 
-        let index_num: u8 = parts[1]
-            .parse()
-            .with_context(|| format!("Invalid index number: {}", parts[1]))?;
-        let msf = Msf::from_str(parts[2]).with_context(|| format!("Invalid MSF: {}", parts[2]))?;
+        match self.current_file.as_mut() {
+            Some(file) if !pending.orphan => file.tracks.push(pending.track),
+            _ => self.orphan_tracks.push(pending.track),
+        }
+    }
 
-        if let Some(ref mut track) = current_track {
-            match index_num {
-                0 => track.index00_msf = Some(msf),
-                1 => track.index01_msf = msf,
-                _ => {} // Ignore other indexes
+    fn finish_file(&mut self) {
+        self.finish_track();
+        if let Some(file) = self.current_file.take() {
+            if file.tracks.is_empty() {
+                self.warning(1, format!("FILE \"{}\" has no tracks", file.filename));
             }
+            self.sheet.files.push(file);
         }
-        Ok(())
     }
 
-    /// Validate CUE sheet structure
-    fn validate(&self) -> Result<()> {
-        if self.files.is_empty() {
-            bail!("CUE file contains no FILE entries");
+    /// Sheet-level checks once every line has been read
+    fn finish(&mut self) {
+        if !self.orphan_tracks.is_empty() {
+            self.line = 0;
+            self.report(
+                0,
+                Severity::Error,
+                DiagnosticKind::Structure,
+                "TRACK without any FILE".to_string(),
+            );
+        }
+
+        if self.sheet.files.is_empty() {
+            self.line = 0;
+            self.report(
+                0,
+                Severity::Error,
+                DiagnosticKind::Structure,
+                "CUE file contains no FILE entries".to_string(),
+            );
+            return;
         }
 
         // Validate first track starts at 00:00:00
-        if let Some(first_file) = self.files.first() {
-            if let Some(first_track) = first_file.tracks.first() {
-                if first_track.index01_msf.to_sectors() != 0 {
-                    bail!("First track INDEX 01 must be 00:00:00");
-                }
+        let first_track = self.sheet.files.iter().find_map(|f| f.tracks.first());
+        if let (Some(track), Some(line)) = (first_track, self.first_index01_line) {
+            if track.index01 != Lba::ZERO {
+                self.line = line;
+                self.report(
+                    1,
+                    Severity::Error,
+                    DiagnosticKind::Structure,
+                    "First track INDEX 01 must be 00:00:00".to_string(),
+                );
             }
         }
+    }
+}
 
-        Ok(())
+/// Complete CUE sheet structure
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct CueSheet {
+    pub files: Vec<FileEntry>,
+}
+
+impl CueSheet {
+    pub fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    /// Parse a CUE file and validate its structure
+    ///
+    /// Fails on the first error; use [`CueSheet::parse_with_diagnostics`]
+    /// to get warnings or to parse leniently.
+    pub fn parse(cue_path: &Path) -> Result<Self> {
+        Self::parse_with_diagnostics(cue_path, false).map(|(sheet, _)| sheet)
+    }
+
+    /// Parse a CUE file, returning the warnings found along the way
+    ///
+    /// In lenient mode lowercase keywords, unquoted filenames, `MP3` file
+    /// types, INDEX/TRACK lines out of place and wrong track numbering are
+    /// accepted with a warning. Any remaining error fails the parse.
+    pub fn parse_with_diagnostics(
+        cue_path: &Path,
+        lenient: bool,
+    ) -> Result<(Self, Vec<Diagnostic>)> {
+        let (sheet, diagnostics) = Self::parse_text(&Self::read_text(cue_path)?, lenient);
+
+        if let Some(error) = diagnostics.iter().find(|d| d.severity == Severity::Error) {
+            return Err(error.clone().into_error(cue_path));
+        }
+        Ok((sheet, diagnostics))
+    }
+
+    /// Collect every diagnostic of a CUE file without failing on errors
+    pub fn lint(cue_path: &Path, lenient: bool) -> Result<Vec<Diagnostic>> {
+        Ok(Self::parse_text(&Self::read_text(cue_path)?, lenient).1)
+    }
+
+    /// Parse CUE text into a sheet and its diagnostics
+    pub fn parse_text(text: &str, lenient: bool) -> (Self, Vec<Diagnostic>) {
+        CueParser::new(lenient).parse(text)
+    }
+
+    fn read_text(cue_path: &Path) -> Result<String> {
+        let bytes = std::fs::read(cue_path).map_err(|e| Error::io(cue_path, e))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
     }
 
     /// Load file sizes for all BIN files referenced in CUE
-    pub fn load_file_sizes(&mut self, cue_dir: &Path) -> Result<()> {
+    ///
+    /// Audio files (WAV, FLAC, Ogg) are probed and sized as the raw CDDA
+    /// sectors they decode to. FILE entries that do not exist are first
+    /// resolved against the directory (see [`resolve::resolve_filenames`]);
+    /// the substitutions made are returned.
+    pub fn load_file_sizes(&mut self, cue_dir: &Path) -> Result<Vec<Substitution>> {
+        let substitutions = resolve::resolve_filenames(self, cue_dir);
+
         for file in &mut self.files {
             let file_path = cue_dir.join(&file.filename);
-            file.file_size = std::fs::metadata(&file_path)
-                .with_context(|| format!("Failed to get size of: {}", file.filename))?
-                .len();
+            if !file_path.exists() {
+                return Err(Error::MissingFile(file_path));
+            }
+            file.audio_format = audio::detect_file(&file_path, &file.file_type)?;
+
+            file.file_size = match file.audio_format {
+                Some(format) => {
+                    if let Some(track) = file.tracks.iter().find(|t| !t.is_audio()) {
+                        return Err(Error::UnsupportedTrackType(format!(
+                            "{} is a {} file but TRACK {:02} is {}",
+                            file.filename, format, track.number, track.track_type
+                        )));
+                    }
+                    audio::cdda_size(&file_path, format)?
+                }
+                None => std::fs::metadata(&file_path)
+                    .map_err(|e| Error::io(&file_path, e))?
+                    .len(),
+            };
         }
-        Ok(())
+        Ok(substitutions)
     }
 
     /// Get total number of tracks across all files
@@ -276,15 +908,23 @@ impl CueSheet {
         self.files.last()?.tracks.last()
     }
 
-    /// Validate that all tracks are MODE2/2352
-    pub fn validate_mode2(&self) -> Result<()> {
+    /// Validate that the first track is MODE2/2352
+    ///
+    /// With `convert_sectors`, tracks that are converted to MODE2/2352 while
+    /// combining (MODE2/2336 and MODE1/2048) are accepted as well.
+    pub fn validate_mode2(&self, convert_sectors: bool) -> Result<()> {
         for file in &self.files {
             for track in &file.tracks {
-                if track.number == 1 && track.track_type != TrackType::Mode2_2352 {
-                    bail!(
+                let track_type = if convert_sectors {
+                    track.track_type.raw_type()
+                } else {
+                    track.track_type
+                };
+                if track.number == 1 && track_type != TrackType::Mode2_2352 {
+                    return Err(Error::UnsupportedTrackType(format!(
                         "First track must be MODE2/2352, found: {}",
                         track.track_type
-                    );
+                    )));
                 }
             }
         }
@@ -293,28 +933,53 @@ impl CueSheet {
 
     /// Print detailed CUE sheet information
     pub fn print_info(&self) {
-        println!("\n=== CUE Sheet Information ===");
+        status!("\n=== CUE Sheet Information ===");
         for (file_idx, file) in self.files.iter().enumerate() {
-            println!(
+            status!(
                 "FILE #{}: \"{}\" {}",
                 file_idx + 1,
                 file.filename,
                 file.file_type
             );
-            println!(
+            status!(
                 "  Size: {:.2} MB",
                 file.file_size as f64 / (1024.0 * 1024.0)
             );
+            if let Some(format) = file.audio_format {
+                status!("  Audio: {} (decoded to CDDA)", format);
+            }
+            for track in &file.tracks {
+                status!("  TRACK {:02} {}", track.number, track.track_type);
+                if let Some(idx00) = track.index00 {
+                    status!("    INDEX 00 {}", idx00.to_relative_msf());
+                }
+                status!("    INDEX 01 {}", track.index01.to_relative_msf());
+            }
+        }
+        status!("Total tracks: {}", self.get_total_tracks());
+        status!("=============================\n");
+    }
+
+    /// Render the sheet as CUE text
+    pub fn to_cue_text(&self) -> String {
+        let mut text = String::new();
+        for file in &self.files {
+            text.push_str(&format!("FILE \"{}\" {}\n", file.filename, file.file_type));
             for track in &file.tracks {
-                println!("  TRACK {:02} {}", track.number, track.track_type);
-                if let Some(idx00) = track.index00_msf {
-                    println!("    INDEX 00 {}", idx00);
+                text.push_str(&format!(
+                    "  TRACK {:02} {}\n",
+                    track.number, track.track_type
+                ));
+                if let Some(idx00) = track.index00 {
+                    text.push_str(&format!("    INDEX 00 {}\n", idx00.to_relative_msf()));
                 }
-                println!("    INDEX 01 {}", track.index01_msf);
+                text.push_str(&format!(
+                    "    INDEX 01 {}\n",
+                    track.index01.to_relative_msf()
+                ));
             }
         }
-        println!("Total tracks: {}", self.get_total_tracks());
-        println!("=============================\n");
+        text
     }
 
     /// Recalculate MSF positions for a combined BIN file
@@ -324,82 +989,252 @@ impl CueSheet {
     /// - Track 02+: Applies +150 sector adjustment for pregaps
     ///
     /// The logic matches the original cue2pops behavior exactly for
-    /// proper compatibility with POPSTARTER/OPL.
-    pub fn recalculate_msf_for_combined(&mut self) {
-        let mut accumulated_sectors = 0u32;
+    /// proper compatibility with POPSTARTER/OPL. The result goes to each
+    /// track's [`disc`](Track::disc) positions; the CUE positions are kept.
+    /// Fails when a position lands past 99:59:74.
+    pub fn recalculate_msf_for_combined(&mut self) -> Result<()> {
+        // Start of the current file in the combined BIN
+        let mut accumulated = Lba::ZERO;
+        let overflow = || Error::HeaderOverflow("combined disc extends past 99:59:74".to_string());
+        let disc_msf = |lba: Option<Lba>| lba.map(Lba::to_absolute_msf).ok_or_else(overflow);
 
-        println!("  === Recalculating MSF (cue2pops v2.0 logic) ===");
+        status!("  === Recalculating MSF (cue2pops v2.0 logic) ===");
 
         for file in &mut self.files {
-            let physical_sectors = (file.file_size / file.tracks[0].sector_size() as u64) as u32;
+            let physical_sectors = file.sector_count();
 
             for track in &mut file.tracks {
-                if track.number == 1 {
+                let disc = if track.number == 1 {
                     // Track 01: Always starts at 00:00:00 for INDEX 00
-                    // INDEX 01 is always at 00:02:00 (150 sectors pregap)
-                    track.index00_msf = Some(Msf::from_sectors(0));
-                    track.index01_msf = Msf::from_sectors(150);
-
-                    println!(
+                    // INDEX 01 is always at 00:02:00 (LBA 0 after the lead-in)
+                    let disc = DiscIndexes::TRACK01;
+                    status!(
                         "    Track {:02}: INDEX 00={} INDEX 01={} | Physical: {} sectors",
                         track.number,
-                        track.index00_msf.unwrap(),
-                        track.index01_msf,
+                        disc.index00,
+                        disc.index01,
                         physical_sectors
                     );
+                    disc
+                } else if track.index00.is_some() {
+                    // CRITICAL: cue2pops applies +150 sectors (2 seconds) adjustment
+                    // on top of the lead-in for tracks with explicit INDEX 00:
+                    // INDEX 00 = accumulated + 150 (unconditional), on disc MSF
+                    // INDEX 01 = INDEX 00 + 150 (pregap length)
+                    let index00 = accumulated + Lba::LEAD_IN;
+                    let index01 = index00.and_then(|lba| lba + Lba::LEAD_IN);
+                    let disc = DiscIndexes {
+                        index00: disc_msf(index00)?,
+                        index01: disc_msf(index01)?,
+                    };
 
-                    // Track 01 adds its physical size including the 150 sector pregap
-                    accumulated_sectors += physical_sectors;
+                    status!(
+                        "    Track {:02}: INDEX 00={} (LBA {}) | INDEX 01={} (LBA {}) | Physical: {} sectors",
+                        track.number,
+                        disc.index00,
+                        index00.unwrap(),
+                        disc.index01,
+                        index01.unwrap(),
+                        physical_sectors
+                    );
+                    disc
                 } else {
-                    // CRITICAL: cue2pops applies +150 sectors (2 seconds) adjustment
-                    // This happens TWICE for tracks with explicit INDEX 00:
-                    // 1. Once for the physical pregap
-                    // 2. Once for the "unconditional" adjustment
-
-                    if track.index00_msf.is_some() {
-                        // Track has explicit pregap (INDEX 00 in original CUE)
-                        // INDEX 00 = accumulated + 150 (physical) + 150 (unconditional)
-                        // INDEX 01 = INDEX 00 + 150 (pregap length)
-                        let index00_sector = accumulated_sectors + 150 + 150;
-                        let index01_sector = index00_sector + 150;
-
-                        track.index00_msf = Some(Msf::from_sectors(index00_sector));
-                        track.index01_msf = Msf::from_sectors(index01_sector);
-
-                        println!(
-                            "    Track {:02}: INDEX 00={} (sector {}) | INDEX 01={} (sector {}) | Physical: {} sectors",
-                            track.number,
-                            track.index00_msf.unwrap(),
-                            index00_sector,
-                            track.index01_msf,
-                            index01_sector,
-                            physical_sectors
-                        );
-                    } else {
-                        // Track without explicit pregap
-                        // Apply +150 unconditional adjustment
-                        let adjusted_sector = accumulated_sectors + 150;
-                        track.index00_msf = Some(Msf::from_sectors(adjusted_sector));
-                        track.index01_msf = Msf::from_sectors(adjusted_sector);
-
-                        println!(
-                            "    Track {:02}: INDEX 00={} INDEX 01={} (sector {}) | Physical: {} sectors",
-                            track.number,
-                            track.index00_msf.unwrap(),
-                            track.index01_msf,
-                            adjusted_sector,
-                            physical_sectors
-                        );
+                    // Track without explicit pregap: both indexes at the
+                    // start of the file on disc MSF
+                    let start = disc_msf(Some(accumulated))?;
+                    status!(
+                        "    Track {:02}: INDEX 00={} INDEX 01={} (LBA {}) | Physical: {} sectors",
+                        track.number,
+                        start,
+                        start,
+                        accumulated,
+                        physical_sectors
+                    );
+                    DiscIndexes {
+                        index00: start,
+                        index01: start,
                     }
+                };
+                track.disc = Some(disc);
 
-                    // Add this file's physical sectors (which includes the pregap)
-                    accumulated_sectors += physical_sectors;
-                }
-
-                println!("    -> Accumulated: {} sectors", accumulated_sectors);
+                // Add this file's physical sectors (which includes the pregap)
+                accumulated = (accumulated + physical_sectors).ok_or_else(overflow)?;
+                status!("    -> Accumulated: {} sectors", accumulated);
             }
         }
 
-        println!("  ===============================================\n");
+        status!("  ===============================================\n");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixed_file() -> FileEntry {
+        let mut file = FileEntry::new("disc.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2336, Lba::new(0).unwrap()));
+        let mut audio = Track::new(2, TrackType::Audio, Lba::new(160).unwrap());
+        audio.index00 = Some(Lba::new(10).unwrap());
+        file.tracks.push(audio);
+        file.tracks
+            .push(Track::new(3, TrackType::Audio, Lba::new(180).unwrap()));
+        file.file_size = 10 * 2336 + 170 * 2352 + 40 * 2352;
+        file
+    }
+
+    #[test]
+    fn test_track_extents_mixed_sector_sizes() {
+        let extents = mixed_file().track_extents();
+        assert_eq!(
+            extents,
+            vec![
+                TrackExtent {
+                    offset: 0,
+                    length: 10 * 2336,
+                    sectors: 10
+                },
+                TrackExtent {
+                    offset: 10 * 2336,
+                    length: 170 * 2352,
+                    sectors: 170
+                },
+                TrackExtent {
+                    offset: 10 * 2336 + 170 * 2352,
+                    length: 40 * 2352,
+                    sectors: 40
+                },
+            ]
+        );
+        assert_eq!(mixed_file().sector_count(), 220);
+    }
+
+    #[test]
+    fn test_track_extents_leading_pregap() {
+        // Pregap only given by INDEX 01: the leading sectors are part of
+        // the track, not skipped
+        let mut file = FileEntry::new("track02.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(2, TrackType::Audio, Lba::new(150).unwrap()));
+        file.file_size = 400 * 2352;
+
+        assert_eq!(
+            file.track_extents(),
+            [TrackExtent {
+                offset: 0,
+                length: 400 * 2352,
+                sectors: 400
+            }]
+        );
+        assert_eq!(file.sector_count(), 400);
+    }
+
+    #[test]
+    fn test_parse_errors_are_typed() {
+        let path = std::env::temp_dir().join("psx-vcd-cue-errors.cue");
+        let parse = |text: &str| {
+            std::fs::write(&path, text).unwrap();
+            CueSheet::parse(&path)
+        };
+
+        let err = parse("FILE \"a.bin\" BINARY\n  TRACK xx MODE2/2352\n").unwrap_err();
+        assert!(matches!(err, Error::CueSyntax { line: 2, .. }), "{:?}", err);
+        assert_eq!(err.exit_code(), 3);
+
+        let err = parse("FILE \"a.bin\" BINARY\n  TRACK 01 CDG\n").unwrap_err();
+        assert!(matches!(err, Error::UnsupportedTrackType(_)), "{:?}", err);
+
+        let err = parse("REM nothing\n").unwrap_err();
+        assert!(matches!(err, Error::InvalidCue(_)), "{:?}", err);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_lenient_recovery() {
+        let text = "INDEX 01 00:00:00\n\
+                    file My Game.bin binary\n\
+                    \x20 TRACK 01 MODE2/2352\n\
+                    \x20   index 01 00:00:00\n\
+                    \x20 TRACK 03 AUDIO\n\
+                    \x20   INDEX 01 00:10:00\n\
+                    CATALOG 0000000000000\n\
+                    FOO bar\n";
+
+        let (_, strict) = CueSheet::parse_text(text, false);
+        let errors: Vec<_> = strict
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| (d.line, d.column))
+            .collect();
+        assert_eq!(errors, [(1, 1), (2, 1), (2, 6), (2, 18), (4, 5), (5, 9)]);
+
+        let (sheet, lenient) = CueSheet::parse_text(text, true);
+        assert!(lenient.iter().all(|d| d.severity == Severity::Warning));
+        assert_eq!(lenient.last().unwrap().line, 8); // unknown command
+        assert_eq!(sheet.files.len(), 1);
+        assert_eq!(sheet.files[0].filename, "My Game.bin");
+        assert_eq!(sheet.files[0].file_type, "BINARY");
+        let numbers: Vec<u8> = sheet.files[0].tracks.iter().map(|t| t.number).collect();
+        assert_eq!(numbers, [1, 2]);
+    }
+
+    #[test]
+    fn test_structure_diagnostics() {
+        let (sheet, diagnostics) = CueSheet::parse_text(
+            "  TRACK 01 MODE2/2352\n\
+             \x20   INDEX 01 00:00:00\n\
+             FILE \"a.bin\" BINARY\n\
+             \x20 TRACK 02 AUDIO\n\
+             FILE \"b.bin\" AIFF\n",
+            true,
+        );
+        // TRACK before FILE is attached to the next FILE
+        assert_eq!(sheet.files[0].tracks.len(), 2);
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(errors, ["TRACK 02 has no INDEX 01"]);
+        assert_eq!(sheet.files[1].file_type, "AIFF");
+    }
+
+    #[test]
+    fn test_out_of_range_values() {
+        let (_, diagnostics) = CueSheet::parse_text(
+            "FILE game (1).\"x.bin\" BINARY\n\
+             FILE \"a.bin\" BINARY\n\
+             \x20 TRACK 00 MODE2/2352\n\
+             \x20 TRACK 01 MODE2/2352\n\
+             \x20   INDEX 01 00:60:00\n",
+            true,
+        );
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "filename contains a stray quote",
+                "track number 0 is outside 1-99",
+                "TRACK 01 has no INDEX 01",
+                "invalid MSF '00:60:00'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_mode2_conversion() {
+        let mut sheet = CueSheet::new();
+        sheet.files.push(mixed_file());
+        assert!(sheet.validate_mode2(false).is_err());
+        assert!(sheet.validate_mode2(true).is_ok());
+
+        sheet.files[0].tracks[0].track_type = TrackType::Mode1_2352;
+        assert!(sheet.validate_mode2(true).is_err());
     }
 }
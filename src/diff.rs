@@ -0,0 +1,403 @@
+// src/diff.rs
+//! Field-by-field and sector-by-sector comparison of VCD images
+//!
+//! Either side can be an existing VCD or a CUE sheet, in which case the
+//! header is built the way `auto` would build it and the payload is the
+//! combined BIN streamed straight from the source files.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::combiner::BinCombiner;
+use crate::cue::CueSheet;
+use crate::sector::SECTOR_SIZE;
+use crate::utils::{Lba, Msf};
+use crate::vcd::{
+    bcd_to_u8, header_fields, HeaderField, HeaderFieldKind as FieldKind, VcdConverter, VcdHeader,
+    VCD_HEADER_SIZE,
+};
+
+const BUFFER_SIZE: usize = 1024 * 1024; // 1MB buffer
+
+/// Show the bytes of a header field according to its kind
+fn format_field(field: &HeaderField, header: &[u8]) -> String {
+    let bytes = &header[field.range()];
+    match field.kind {
+        FieldKind::Byte => format!("0x{:02X}", bytes[0]),
+        FieldKind::TrackType => match bytes[0] {
+            0x41 => "0x41 (data)".to_string(),
+            0x01 => "0x01 (audio)".to_string(),
+            other => format!("0x{:02X}", other),
+        },
+        FieldKind::Bcd => match bcd_to_u8(bytes[0]) {
+            Some(value) => format!("{:02}", value),
+            None => format!("0x{:02X} (not BCD)", bytes[0]),
+        },
+        FieldKind::Msf => match Msf::from_bcd([bytes[0], bytes[1], bytes[2]]) {
+            Some(msf) => msf.to_string(),
+            None => format!(
+                "{:02X} {:02X} {:02X} (not BCD)",
+                bytes[0], bytes[1], bytes[2]
+            ),
+        },
+        FieldKind::Count => {
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_string()
+        }
+        FieldKind::Text => {
+            let text: String = bytes
+                .iter()
+                .map(|&b| match b {
+                    0x20..=0x7E => (b as char).to_string(),
+                    _ => format!("\\x{:02X}", b),
+                })
+                .collect();
+            format!("\"{}\"", text)
+        }
+    }
+}
+
+/// One decoded header field on both sides
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldComparison {
+    pub name: String,
+    pub offset: usize,
+    pub left: String,
+    pub right: String,
+}
+
+impl FieldComparison {
+    pub fn differs(&self) -> bool {
+        self.left != self.right
+    }
+}
+
+/// Field-by-field comparison of two VCD headers
+#[derive(Debug, Clone, Serialize)]
+pub struct HeaderDiff {
+    pub fields: Vec<FieldComparison>,
+    /// Differing bytes outside the known fields
+    pub other_bytes: usize,
+    pub first_other_offset: Option<usize>,
+}
+
+impl HeaderDiff {
+    pub fn differing(&self) -> impl Iterator<Item = &FieldComparison> {
+        self.fields.iter().filter(|f| f.differs())
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.other_bytes == 0 && self.differing().next().is_none()
+    }
+}
+
+/// Decode two headers field by field
+///
+/// Track entries are listed up to the larger of the two track counts.
+pub fn compare_headers(left: &[u8], right: &[u8]) -> HeaderDiff {
+    let track_count = [left, right]
+        .iter()
+        .filter_map(|h| bcd_to_u8(h[17]))
+        .max()
+        .unwrap_or(0)
+        .min(99) as usize;
+    let fields = header_fields(track_count);
+
+    let mut known = vec![false; VCD_HEADER_SIZE];
+    for field in &fields {
+        known[field.range()].fill(true);
+    }
+    let mut other = (0..VCD_HEADER_SIZE).filter(|&i| !known[i] && left[i] != right[i]);
+    let first_other_offset = other.next();
+    let other_bytes = first_other_offset.map_or(0, |_| 1 + other.count());
+
+    HeaderDiff {
+        fields: fields
+            .iter()
+            .map(|field| FieldComparison {
+                name: field.name.clone(),
+                offset: field.offset,
+                left: format_field(field, left),
+                right: format_field(field, right),
+            })
+            .collect(),
+        other_bytes,
+        first_other_offset,
+    }
+}
+
+/// Differing sectors within one track
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackDiff {
+    pub number: u8,
+    pub sectors: u64,
+    pub differing: u64,
+}
+
+/// Sector-by-sector comparison of two payloads
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PayloadDiff {
+    pub left_sectors: u64,
+    pub right_sectors: u64,
+    pub differing: u64,
+    /// Payload LBA (0 = 00:02:00) of the first differing sector
+    pub first_difference: Option<u32>,
+    pub tracks: Vec<TrackDiff>,
+}
+
+impl PayloadDiff {
+    pub fn is_identical(&self) -> bool {
+        self.differing == 0 && self.left_sectors == self.right_sectors
+    }
+
+    /// Disc position of the first differing sector
+    pub fn first_difference_msf(&self) -> Option<Msf> {
+        self.first_difference
+            .and_then(Lba::new)
+            .map(Lba::to_absolute_msf)
+    }
+}
+
+/// Compare two payloads sector by sector
+///
+/// `track_starts` holds the track numbers and payload LBAs where each track
+/// begins, in order; sectors present on one side only do not count as
+/// differing but show up in the sector totals.
+pub fn compare_payloads(
+    left: &mut dyn Read,
+    right: &mut dyn Read,
+    track_starts: &[(u8, u32)],
+) -> std::io::Result<PayloadDiff> {
+    let mut left = BufReader::with_capacity(BUFFER_SIZE, left);
+    let mut right = BufReader::with_capacity(BUFFER_SIZE, right);
+    let mut left_sector = [0u8; SECTOR_SIZE];
+    let mut right_sector = [0u8; SECTOR_SIZE];
+
+    let mut diff = PayloadDiff {
+        tracks: track_starts
+            .iter()
+            .map(|&(number, _)| TrackDiff {
+                number,
+                sectors: 0,
+                differing: 0,
+            })
+            .collect(),
+        ..PayloadDiff::default()
+    };
+
+    let mut lba = 0u32;
+    let mut track = 0usize;
+    loop {
+        let left_len = read_sector(&mut left, &mut left_sector)?;
+        let right_len = read_sector(&mut right, &mut right_sector)?;
+        if left_len == 0 && right_len == 0 {
+            break;
+        }
+        diff.left_sectors += (left_len > 0) as u64;
+        diff.right_sectors += (right_len > 0) as u64;
+
+        if left_len > 0 && right_len > 0 {
+            while track_starts
+                .get(track + 1)
+                .is_some_and(|&(_, start)| lba >= start)
+            {
+                track += 1;
+            }
+            let differs = left_sector[..left_len] != right_sector[..right_len];
+            if let Some(entry) = diff.tracks.get_mut(track) {
+                entry.sectors += 1;
+                entry.differing += differs as u64;
+            }
+            if differs {
+                diff.differing += 1;
+                diff.first_difference.get_or_insert(lba);
+            }
+        }
+        lba = lba.saturating_add(1);
+    }
+
+    Ok(diff)
+}
+
+/// Read up to one sector; returns the number of bytes read (0 at the end)
+fn read_sector(reader: &mut impl Read, sector: &mut [u8; SECTOR_SIZE]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < SECTOR_SIZE {
+        match reader.read(&mut sector[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// Track numbers and payload start LBAs from a header's track entries
+pub fn track_starts(header: &[u8]) -> Option<Vec<(u8, u32)>> {
+    let header = VcdHeader::parse(header).ok()?;
+    Some(
+        header
+            .tracks
+            .iter()
+            .zip(header.track_positions())
+            .map(|(t, (start, _))| (t.number, start.get()))
+            .collect(),
+    )
+}
+
+/// Track numbers and payload start LBAs of the combined BIN of a CUE
+fn cue_track_starts(cue_sheet: &CueSheet) -> Vec<(u8, u32)> {
+    let mut starts = Vec::new();
+    let mut lba = 0u32;
+    for file in &cue_sheet.files {
+        for (track, extent) in file.tracks.iter().zip(file.track_extents()) {
+            starts.push((track.number, lba));
+            lba = lba.saturating_add(extent.sectors);
+        }
+    }
+    starts
+}
+
+/// One side of a comparison: a header and the disc sectors after it
+pub struct DiffSide {
+    pub path: PathBuf,
+    pub header: Vec<u8>,
+    pub payload: Box<dyn Read>,
+    /// Where each track's data starts, when known from a CUE
+    layout: Option<Vec<(u8, u32)>>,
+}
+
+impl DiffSide {
+    /// An existing VCD file
+    pub fn vcd(path: &Path) -> Result<Self> {
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open: {}", path.display()))?;
+        let mut header = vec![0u8; VCD_HEADER_SIZE];
+        if file.read_exact(&mut header).is_err() {
+            bail!("{} is shorter than a VCD header", path.display());
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            header,
+            payload: Box::new(file),
+            layout: None,
+        })
+    }
+
+    /// The VCD a conversion of `cue_sheet` (file sizes loaded) would write
+    pub fn cue(path: &Path, cue_sheet: &CueSheet, converter: &VcdConverter) -> Result<Self> {
+        let cue_dir = path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
+        let combiner = BinCombiner::new(Vec::new(), false);
+
+        let mut planned = cue_sheet.clone();
+        let plan = combiner.plan(&mut planned)?;
+        let header = converter.create_vcd_header(plan.total_bytes, &planned)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            header,
+            payload: combiner.reader(cue_sheet, cue_dir),
+            layout: Some(cue_track_starts(cue_sheet)),
+        })
+    }
+}
+
+/// Complete comparison of two sides
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffReport {
+    pub header: HeaderDiff,
+    pub payload: PayloadDiff,
+}
+
+impl DiffReport {
+    pub fn is_identical(&self) -> bool {
+        self.header.is_identical() && self.payload.is_identical()
+    }
+}
+
+/// Compare headers, then payloads
+///
+/// Sectors are counted per track using the layout of a CUE side when there
+/// is one, and otherwise the track entries of the left header (or the right
+/// one when the left header cannot be decoded).
+pub fn compare(left: &mut DiffSide, right: &mut DiffSide) -> Result<DiffReport> {
+    let header = compare_headers(&left.header, &right.header);
+    let tracks = left
+        .layout
+        .clone()
+        .or_else(|| right.layout.clone())
+        .or_else(|| track_starts(&left.header))
+        .or_else(|| track_starts(&right.header))
+        .unwrap_or_else(|| vec![(1, 0)]);
+
+    let payload = compare_payloads(&mut left.payload, &mut right.payload, &tracks)
+        .context("Failed to read payload")?;
+    Ok(DiffReport { header, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::SyntheticDisc;
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_diff_vcd_against_cue() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-diff-{}", std::process::id()));
+        let mut disc = SyntheticDisc::new("SLUS_012.34");
+        disc.audio_tracks = vec!["tone:100".parse().unwrap()];
+        disc.multi_file = true;
+        let cue_path = disc.write(&dir, "diff").unwrap();
+
+        let mut cue_sheet = CueSheet::parse(&cue_path).unwrap();
+        cue_sheet.load_file_sizes(&dir).unwrap();
+        let converter = VcdConverter::new(false, false);
+
+        let mut combined = cue_sheet.clone();
+        let combined_bin = dir.join("combined.bin");
+        BinCombiner::new(Vec::new(), false)
+            .combine(&mut combined, &dir, &combined_bin)
+            .unwrap();
+        let vcd_path = dir.join("diff.VCD");
+        converter
+            .convert_to_vcd(&combined_bin, &vcd_path, &combined)
+            .unwrap();
+
+        let mut vcd = DiffSide::vcd(&vcd_path).unwrap();
+        let mut cue = DiffSide::cue(&cue_path, &cue_sheet, &converter).unwrap();
+        let report = compare(&mut vcd, &mut cue).unwrap();
+        assert!(report.is_identical());
+        assert_eq!(report.payload.left_sectors, 300 + 250);
+
+        // One corrupt sector in the audio track and a different lead-out
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&vcd_path)
+            .unwrap();
+        file.seek(SeekFrom::Start(27)).unwrap();
+        file.write_all(&[0x00, 0x09, 0x00]).unwrap();
+        let lba = 400u64;
+        file.seek(SeekFrom::Start(
+            VCD_HEADER_SIZE as u64 + lba * SECTOR_SIZE as u64,
+        ))
+        .unwrap();
+        file.write_all(&[0xFF; 16]).unwrap();
+        drop(file);
+
+        let mut vcd = DiffSide::vcd(&vcd_path).unwrap();
+        let mut cue = DiffSide::cue(&cue_path, &cue_sheet, &converter).unwrap();
+        let report = compare(&mut vcd, &mut cue).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let differing: Vec<&str> = report.header.differing().map(|f| f.name.as_str()).collect();
+        assert_eq!(differing, ["A2 lead-out"]);
+        assert_eq!(report.payload.first_difference, Some(400));
+        assert_eq!(report.payload.differing, 1);
+        assert_eq!(report.payload.tracks[0].differing, 0);
+        assert_eq!(report.payload.tracks[1].differing, 1);
+    }
+}
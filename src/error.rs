@@ -0,0 +1,117 @@
+// src/error.rs
+use std::path::{Path, PathBuf};
+
+/// Errors returned by the library
+///
+/// Each category maps to a distinct process exit code (see [`Error::exit_code`])
+/// so scripts can tell a broken CUE from a missing BIN or a full disk.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Malformed CUE line
+    #[error("{}:{line}:{column}: {message}", path.display())]
+    CueSyntax {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    /// CUE sheet that parses but cannot describe a PlayStation disc
+    #[error("Invalid CUE sheet: {0}")]
+    InvalidCue(String),
+
+    /// BIN (or audio) file referenced by the CUE does not exist
+    #[error("BIN file not found: {}", .0.display())]
+    MissingFile(PathBuf),
+
+    /// Track type that cannot be converted for POPS
+    #[error("Unsupported track type: {0}")]
+    UnsupportedTrackType(String),
+
+    /// Disc layout that does not fit in the VCD header
+    #[error("VCD header overflow: {0}")]
+    HeaderOverflow(String),
+
+    /// Read or write failure
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// Audio file that cannot be decoded to CDDA
+    #[error("{}: {message}", path.display())]
+    Audio { path: PathBuf, message: String },
+
+    /// Patch that cannot be read or does not fit the image
+    #[error("{}: {message}", path.display())]
+    Patch { path: PathBuf, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Wrap an I/O error with the path it happened on
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// Categorize an error chain raised while working on `path`
+    ///
+    /// Read and write failures stay I/O errors on `path`, with the chain as
+    /// their message. Anything else, including running out of data in a
+    /// file that is too short, becomes the error `other` makes of the
+    /// message.
+    pub(crate) fn classify(
+        path: &Path,
+        err: anyhow::Error,
+        other: impl FnOnce(String) -> Error,
+    ) -> Self {
+        let message = format!("{:#}", err);
+        match err.chain().find_map(|c| c.downcast_ref::<std::io::Error>()) {
+            Some(io) if io.kind() != std::io::ErrorKind::UnexpectedEof => {
+                Error::io(path, std::io::Error::new(io.kind(), message))
+            }
+            _ => other(message),
+        }
+    }
+
+    /// Audio error on `path`
+    pub(crate) fn audio(path: impl Into<PathBuf>, message: impl Into<String>) -> Self {
+        Error::Audio {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Process exit code for this error category
+    ///
+    /// 1 is used for uncategorized failures and 2 by the argument parser.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::CueSyntax { .. } | Error::InvalidCue(_) => 3,
+            Error::MissingFile(_) => 4,
+            Error::UnsupportedTrackType(_) => 5,
+            Error::Io { .. } => 6,
+            Error::HeaderOverflow(_) => 7,
+            Error::Audio { .. } => 8,
+            Error::Patch { .. } => 9,
+        }
+    }
+}
+
+/// Exit code for any error chain, looking through anyhow context
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    for cause in err.chain() {
+        if let Some(error) = cause.downcast_ref::<Error>() {
+            return error.exit_code();
+        } else if cause.downcast_ref::<std::io::Error>().is_some() {
+            return 6;
+        }
+    }
+    1
+}
@@ -0,0 +1,348 @@
+// src/flac.rs
+use anyhow::{bail, Result};
+use std::io::{Read, Write};
+
+/// Samples per channel in each FLAC frame (block size code 5)
+const BLOCK_SIZE: usize = 4608;
+const SAMPLE_RATE: u32 = 44_100;
+const CHANNELS: usize = 2;
+const BITS_PER_SAMPLE: u32 = 16;
+const MAX_RICE_PARAM: u32 = 14;
+
+/// Encode 44.1 kHz 16-bit stereo PCM (CDDA byte order) into a FLAC stream
+///
+/// Frames use fixed linear predictors (order 0-4) with a single Rice
+/// partition, falling back to verbatim subframes for noise. This keeps the
+/// encoder small while still compressing typical game music well.
+pub fn encode_cdda<R: Read, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    total_bytes: u64,
+) -> Result<()> {
+    if total_bytes % 4 != 0 {
+        bail!(
+            "CDDA length {} is not a whole number of stereo frames",
+            total_bytes
+        );
+    }
+    let total_samples = total_bytes / 4;
+
+    output.write_all(b"fLaC")?;
+    output.write_all(&stream_info(total_samples))?;
+
+    let mut raw = vec![0u8; BLOCK_SIZE * 4];
+    let mut channels = [vec![0i32; BLOCK_SIZE], vec![0i32; BLOCK_SIZE]];
+    let mut remaining = total_samples;
+    let mut frame_number = 0u64;
+
+    while remaining > 0 {
+        let block = remaining.min(BLOCK_SIZE as u64) as usize;
+        input.read_exact(&mut raw[..block * 4])?;
+
+        for (i, frame) in raw[..block * 4].chunks_exact(4).enumerate() {
+            channels[0][i] = i16::from_le_bytes([frame[0], frame[1]]) as i32;
+            channels[1][i] = i16::from_le_bytes([frame[2], frame[3]]) as i32;
+        }
+
+        let frame = encode_frame(
+            frame_number,
+            &[&channels[0][..block], &channels[1][..block]],
+        );
+        output.write_all(&frame)?;
+
+        remaining -= block as u64;
+        frame_number += 1;
+    }
+
+    Ok(())
+}
+
+/// Last (and only) metadata block: STREAMINFO
+fn stream_info(total_samples: u64) -> Vec<u8> {
+    let mut bits = BitWriter::new();
+    bits.write(1, 1); // last metadata block
+    bits.write(0, 7); // STREAMINFO
+    bits.write(34, 24);
+    bits.write(BLOCK_SIZE as u64, 16); // min block size
+    bits.write(BLOCK_SIZE as u64, 16); // max block size
+    bits.write(0, 24); // min frame size (unknown)
+    bits.write(0, 24); // max frame size (unknown)
+    bits.write(SAMPLE_RATE as u64, 20);
+    bits.write(CHANNELS as u64 - 1, 3);
+    bits.write(BITS_PER_SAMPLE as u64 - 1, 5);
+    bits.write(total_samples, 36);
+    for _ in 0..16 {
+        bits.write(0, 8); // MD5 not computed
+    }
+    bits.into_bytes()
+}
+
+fn encode_frame(frame_number: u64, channels: &[&[i32]]) -> Vec<u8> {
+    let block = channels[0].len();
+    let mut bits = BitWriter::new();
+
+    bits.write(0b11_1111_1111_1110, 14); // sync
+    bits.write(0, 1); // reserved
+    bits.write(0, 1); // fixed block size stream
+    let size_code = if block == BLOCK_SIZE {
+        5
+    } else if block <= 256 {
+        6
+    } else {
+        7
+    };
+    bits.write(size_code, 4);
+    bits.write(0b1001, 4); // 44.1 kHz
+    bits.write(0b0001, 4); // left/right
+    bits.write(0b100, 3); // 16 bits per sample
+    bits.write(0, 1); // reserved
+    write_utf8_number(&mut bits, frame_number);
+    match size_code {
+        6 => bits.write(block as u64 - 1, 8),
+        7 => bits.write(block as u64 - 1, 16),
+        _ => {}
+    }
+    let crc = crc8(bits.bytes());
+    bits.write(crc as u64, 8);
+
+    for samples in channels {
+        encode_subframe(&mut bits, samples);
+    }
+
+    bits.align();
+    let crc = crc16(bits.bytes());
+    bits.write(crc as u64, 16);
+    bits.into_bytes()
+}
+
+fn encode_subframe(bits: &mut BitWriter, samples: &[i32]) {
+    let verbatim_bits = samples.len() as u64 * BITS_PER_SAMPLE as u64;
+
+    let mut best: Option<(usize, u32, u64)> = None;
+    for order in 0..=4.min(samples.len()) {
+        let residuals = fixed_residuals(samples, order);
+        let (param, cost) = best_rice_param(&residuals);
+        let cost = cost + order as u64 * BITS_PER_SAMPLE as u64;
+        if best.map(|(_, _, c)| cost < c).unwrap_or(true) {
+            best = Some((order, param, cost));
+        }
+    }
+
+    match best {
+        Some((order, param, cost)) if cost < verbatim_bits => {
+            bits.write(0, 1);
+            bits.write(0b001000 | order as u64, 6); // FIXED
+            bits.write(0, 1); // no wasted bits
+            for &sample in &samples[..order] {
+                bits.write_signed(sample, BITS_PER_SAMPLE);
+            }
+
+            bits.write(0b00, 2); // Rice, 4-bit parameters
+            bits.write(0, 4); // partition order 0
+            bits.write(param as u64, 4);
+            for residual in fixed_residuals(samples, order) {
+                bits.write_rice(residual, param);
+            }
+        }
+        _ => {
+            bits.write(0, 1);
+            bits.write(0b000001, 6); // VERBATIM
+            bits.write(0, 1);
+            for &sample in samples {
+                bits.write_signed(sample, BITS_PER_SAMPLE);
+            }
+        }
+    }
+}
+
+/// Residuals of the fixed FLAC predictor of the given order
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i32> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |k: usize| samples[i - k];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+/// Pick the Rice parameter with the smallest encoded size
+fn best_rice_param(residuals: &[i32]) -> (u32, u64) {
+    (0..=MAX_RICE_PARAM)
+        .map(|param| {
+            let cost: u64 = residuals
+                .iter()
+                .map(|&r| (zigzag(r) >> param) as u64 + 1 + param as u64)
+                .sum();
+            (param, cost)
+        })
+        .min_by_key(|&(_, cost)| cost)
+        .unwrap_or((0, 0))
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Frame number in the UTF-8-like variable length coding
+fn write_utf8_number(bits: &mut BitWriter, value: u64) {
+    if value < 0x80 {
+        bits.write(value, 8);
+        return;
+    }
+
+    let mut continuation = 1;
+    while value >= 1u64 << (5 * continuation + 6) {
+        continuation += 1;
+    }
+    let marker = (0xFF00u64 >> (continuation + 1)) & 0xFF;
+    bits.write(marker | (value >> (6 * continuation)), 8);
+    for i in (0..continuation).rev() {
+        bits.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+/// MSB-first bit writer
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            acc: 0,
+            count: 0,
+        }
+    }
+
+    fn write(&mut self, value: u64, width: u32) {
+        if width == 0 {
+            return;
+        }
+        self.acc = (self.acc << width) | (value & ((1u64 << width) - 1));
+        self.count += width;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.bytes.push((self.acc >> self.count) as u8);
+        }
+        self.acc &= (1u64 << self.count) - 1;
+    }
+
+    fn write_signed(&mut self, value: i32, width: u32) {
+        self.write(value as u64 & ((1u64 << width) - 1), width);
+    }
+
+    fn write_rice(&mut self, value: i32, param: u32) {
+        let folded = zigzag(value);
+        let mut quotient = folded >> param;
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+        self.write(1, quotient + 1);
+        self.write((folded & ((1 << param) - 1)) as u64, param);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+
+    /// Completed bytes (the writer must be byte-aligned for CRCs)
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{self, AudioFormat};
+
+    #[test]
+    fn test_flac_roundtrip() {
+        // A sine-ish ramp on the left channel, noise on the right, 2.5 blocks
+        let frames = BLOCK_SIZE * 2 + BLOCK_SIZE / 2;
+        let mut pcm = Vec::with_capacity(frames * 4);
+        let mut noise = 0x1234_5678u32;
+        for i in 0..frames {
+            let left = ((i as f64 / 20.0).sin() * 12000.0) as i16;
+            noise = noise.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let right = (noise >> 16) as i16;
+            pcm.extend_from_slice(&left.to_le_bytes());
+            pcm.extend_from_slice(&right.to_le_bytes());
+        }
+        // Pad to whole sectors so the decoded length matches exactly
+        pcm.resize(pcm.len().div_ceil(2352) * 2352, 0);
+
+        let mut flac = Vec::new();
+        encode_cdda(&mut pcm.as_slice(), &mut flac, pcm.len() as u64).unwrap();
+        assert!(flac.len() < pcm.len());
+
+        let path = std::env::temp_dir().join("psx-vcd-flac-roundtrip.flac");
+        std::fs::write(&path, &flac).unwrap();
+        let size = audio::cdda_size(&path, AudioFormat::Flac).unwrap();
+        let mut decoded = Vec::new();
+        audio::decode_to_cdda(&path, AudioFormat::Flac, &mut decoded, size).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(decoded, pcm);
+    }
+
+    #[test]
+    fn test_utf8_frame_numbers() {
+        for (value, expected) in [
+            (0x7Fu64, vec![0x7F]),
+            (0x80, vec![0xC2, 0x80]),
+            (0x7FF, vec![0xDF, 0xBF]),
+            (0x800, vec![0xE0, 0xA0, 0x80]),
+            (0xFFFF, vec![0xEF, 0xBF, 0xBF]),
+        ] {
+            let mut bits = BitWriter::new();
+            write_utf8_number(&mut bits, value);
+            assert_eq!(bits.into_bytes(), expected, "frame number {:#x}", value);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// src/lib.rs
+//! PlayStation BIN/CUE to POPSTARTER VCD conversion
+//!
+//! The `psx-vcd` binary is a thin CLI over these modules. Fallible entry
+//! points of the CUE parser, combiner, patcher, audio decoders, ripper,
+//! verifier and VCD writer return [`Error`], whose variants map to the CLI
+//! exit codes.
+
+pub mod audio;
+pub mod combiner;
+pub mod cue;
+pub mod diff;
+pub mod error;
+pub mod flac;
+pub mod libcrypt;
+pub mod naming;
+pub mod output;
+pub mod patch;
+pub mod preflight;
+pub mod progress;
+pub mod report;
+pub mod resolve;
+pub mod rip;
+pub mod scan;
+pub mod sector;
+pub mod synth;
+pub mod titledb;
+pub mod utils;
+pub mod vcd;
+pub mod verify;
+pub mod vmc;
+
+pub use error::{Error, Result};
@@ -0,0 +1,300 @@
+// src/libcrypt.rs
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::utils::normalize_serial;
+
+/// PAL releases known to be protected by LibCrypt (subset of the redump list)
+///
+/// The list only covers serials; an SBI/LSD subchannel file next to the CUE
+/// is the authoritative source when available.
+const LIBCRYPT_SERIALS: &[&str] = &[
+    "SCES-00311", // MediEvil
+    "SCES-01492", // MediEvil (France)
+    "SCES-01493", // MediEvil (Germany)
+    "SCES-01494", // MediEvil (Italy)
+    "SCES-01495", // MediEvil (Spain)
+    "SCES-01564", // Ape Escape
+    "SCES-02028", // Ape Escape (France)
+    "SCES-02029", // Ape Escape (Germany)
+    "SCES-02030", // Ape Escape (Italy)
+    "SCES-02031", // Ape Escape (Spain)
+    "SCES-01979", // Formula One 99
+    "SCES-02104", // Spyro 2: Gateway to Glimmer
+    "SCES-02105", // CTR: Crash Team Racing
+    "SCES-02544", // MediEvil II
+    "SCES-02834", // Crash Bash
+    "SCES-02835", // Spyro: Year of the Dragon
+];
+
+const SBI_MAGIC: &[u8] = b"SBI\0";
+const LSD_ENTRY_SIZE: usize = 15; // 3 bytes MSF + 12 bytes Q subchannel
+
+/// How LibCrypt protection was detected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LibCryptEvidence {
+    /// Serial is in the embedded LibCrypt list
+    SerialList,
+    /// SBI file with modified subchannel sectors
+    Sbi { path: PathBuf, sectors: usize },
+    /// LSD file with modified subchannel sectors
+    Lsd { path: PathBuf, sectors: usize },
+}
+
+impl std::fmt::Display for LibCryptEvidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LibCryptEvidence::SerialList => write!(f, "known serial"),
+            LibCryptEvidence::Sbi { sectors, .. } => {
+                write!(f, "SBI file, {} modified sector(s)", sectors)
+            }
+            LibCryptEvidence::Lsd { sectors, .. } => {
+                write!(f, "LSD file, {} modified sector(s)", sectors)
+            }
+        }
+    }
+}
+
+/// LibCrypt detection result for a game
+#[derive(Debug, Clone, Default)]
+pub struct LibCryptInfo {
+    pub evidence: Vec<LibCryptEvidence>,
+    /// PPF crack found next to the CUE/BIN
+    pub crack: Option<PathBuf>,
+    /// SBI/LSD files that could not be read, and why
+    pub warnings: Vec<String>,
+}
+
+impl LibCryptInfo {
+    pub fn is_protected(&self) -> bool {
+        !self.evidence.is_empty()
+    }
+
+    /// Short human-readable summary of the evidence
+    pub fn describe(&self) -> String {
+        self.evidence
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Detect LibCrypt protection for a disc
+///
+/// `image_path` is the CUE or BIN file; SBI/LSD files and PPF cracks are
+/// searched for in the same directory. A subchannel file that cannot be
+/// read is skipped and reported in [`LibCryptInfo::warnings`].
+pub fn detect(game_id: Option<&str>, image_path: &Path) -> LibCryptInfo {
+    let mut info = LibCryptInfo::default();
+
+    if game_id.map(is_known_serial).unwrap_or(false) {
+        info.evidence.push(LibCryptEvidence::SerialList);
+    }
+
+    let dir = image_path.parent().unwrap_or(Path::new("."));
+    let stem = image_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let entries: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path())).collect(),
+        Err(_) => Vec::new(),
+    };
+
+    for path in &entries {
+        if file_stem(path) != stem {
+            continue;
+        }
+        let evidence = match extension(path).as_str() {
+            "sbi" => read_subchannel_file(path, count_sbi_entries)
+                .with_context(|| format!("Invalid SBI file: {}", path.display()))
+                .map(|sectors| LibCryptEvidence::Sbi {
+                    path: path.clone(),
+                    sectors,
+                }),
+            "lsd" => read_subchannel_file(path, count_lsd_entries)
+                .with_context(|| format!("Invalid LSD file: {}", path.display()))
+                .map(|sectors| LibCryptEvidence::Lsd {
+                    path: path.clone(),
+                    sectors,
+                }),
+            _ => continue,
+        };
+        match evidence {
+            Ok(evidence) => info.evidence.push(evidence),
+            Err(e) => info.warnings.push(format!("{:#}", e)),
+        }
+    }
+
+    if info.is_protected() {
+        let serial = game_id.map(normalize_serial);
+        info.crack = entries
+            .iter()
+            .filter(|p| extension(p) == "ppf")
+            .find(|p| {
+                let name = file_stem(p);
+                name.contains(&stem)
+                    || serial
+                        .as_ref()
+                        .map(|s| normalize_serial(&name).contains(s.as_str()))
+                        .unwrap_or(false)
+            })
+            .cloned();
+    }
+
+    info
+}
+
+/// Check whether a serial (any separator style) is in the LibCrypt list
+pub fn is_known_serial(game_id: &str) -> bool {
+    let serial = normalize_serial(game_id);
+    LIBCRYPT_SERIALS
+        .iter()
+        .any(|known| normalize_serial(known) == serial)
+}
+
+fn read_subchannel_file(path: &Path, count: fn(&[u8]) -> Result<usize>) -> Result<usize> {
+    count(&std::fs::read(path)?)
+}
+
+/// Count subchannel entries in an SBI file
+///
+/// Layout: "SBI\0" followed by entries of 3 BCD MSF bytes, a type byte
+/// and 10 (type 1) or 3 (types 2 and 3) bytes of Q subchannel data.
+fn count_sbi_entries(data: &[u8]) -> Result<usize> {
+    if !data.starts_with(SBI_MAGIC) {
+        bail!("missing SBI signature");
+    }
+
+    let mut pos = SBI_MAGIC.len();
+    let mut count = 0;
+    while pos < data.len() {
+        if pos + 4 > data.len() {
+            bail!("truncated entry at offset {}", pos);
+        }
+        let payload = match data[pos + 3] {
+            1 => 10,
+            2 | 3 => 3,
+            other => bail!("unknown entry type {} at offset {}", other, pos),
+        };
+        pos += 4 + payload;
+        if pos > data.len() {
+            bail!("truncated entry data");
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Count subchannel entries in an LSD file (15 bytes per entry)
+fn count_lsd_entries(data: &[u8]) -> Result<usize> {
+    if data.len() % LSD_ENTRY_SIZE != 0 {
+        bail!(
+            "size {} is not a multiple of {}",
+            data.len(),
+            LSD_ENTRY_SIZE
+        );
+    }
+    Ok(data.len() / LSD_ENTRY_SIZE)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_serial_any_format() {
+        assert!(is_known_serial("SCES_015.64"));
+        assert!(is_known_serial("sces-01564"));
+        assert!(!is_known_serial("SLUS_000.67"));
+    }
+
+    #[test]
+    fn test_sbi_entries() {
+        let mut sbi = SBI_MAGIC.to_vec();
+        sbi.extend_from_slice(&[0x03, 0x08, 0x05, 1]);
+        sbi.extend_from_slice(&[0u8; 10]);
+        sbi.extend_from_slice(&[0x03, 0x08, 0x10, 2, 0, 0, 0]);
+        assert_eq!(count_sbi_entries(&sbi).unwrap(), 2);
+
+        sbi.pop();
+        assert!(count_sbi_entries(&sbi).is_err());
+        assert!(count_sbi_entries(b"XXXX").is_err());
+
+        let mut bad_type = SBI_MAGIC.to_vec();
+        bad_type.extend_from_slice(&[0x03, 0x08, 0x05, 7, 0, 0, 0]);
+        assert!(count_sbi_entries(&bad_type).is_err());
+    }
+
+    #[test]
+    fn test_lsd_entries() {
+        assert_eq!(count_lsd_entries(&[]).unwrap(), 0);
+        assert_eq!(count_lsd_entries(&[0u8; 3 * LSD_ENTRY_SIZE]).unwrap(), 3);
+        assert!(count_lsd_entries(&[0u8; LSD_ENTRY_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_detect_and_crack_matching() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-libcrypt-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let cue = dir.join("Ape Escape.cue");
+        std::fs::write(&cue, "").unwrap();
+
+        // Unknown serial, no subchannel data: unprotected
+        let info = detect(Some("SLUS_000.67"), &cue);
+        assert!(!info.is_protected());
+        assert!(info.crack.is_none());
+
+        // Known serial: the crack is matched by serial
+        std::fs::write(dir.join("SCES-01564 crack.ppf"), b"PPF30").unwrap();
+        let info = detect(Some("SCES_015.64"), &cue);
+        assert_eq!(info.evidence, [LibCryptEvidence::SerialList]);
+        assert_eq!(
+            info.crack.as_deref(),
+            Some(dir.join("SCES-01564 crack.ppf").as_path())
+        );
+
+        // SBI next to the CUE: protected whatever the serial; the crack is
+        // matched by name
+        std::fs::remove_file(dir.join("SCES-01564 crack.ppf")).unwrap();
+        std::fs::write(dir.join("Ape Escape (fix).ppf"), b"PPF30").unwrap();
+        let mut sbi = SBI_MAGIC.to_vec();
+        sbi.extend_from_slice(&[0x03, 0x08, 0x05, 2, 0, 0, 0]);
+        std::fs::write(dir.join("Ape Escape.sbi"), &sbi).unwrap();
+        let info = detect(None, &cue);
+        assert_eq!(
+            info.evidence,
+            [LibCryptEvidence::Sbi {
+                path: dir.join("Ape Escape.sbi"),
+                sectors: 1,
+            }]
+        );
+        assert_eq!(
+            info.crack.as_deref(),
+            Some(dir.join("Ape Escape (fix).ppf").as_path())
+        );
+
+        // A malformed LSD is reported and skipped
+        std::fs::write(dir.join("Ape Escape.lsd"), [0u8; 20]).unwrap();
+        let info = detect(None, &cue);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(info.evidence.len(), 1);
+        assert_eq!(info.warnings.len(), 1);
+        assert!(info.warnings[0].contains("Invalid LSD file"));
+    }
+}
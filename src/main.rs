@@ -1,46 +1,255 @@
 // src/main.rs
-mod combiner;
-mod cue;
-mod utils;
-mod vcd;
-
 use anyhow::{bail, Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::io::IsTerminal;
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::ExitCode;
+
+use psx_vcd::{
+    combiner, cue, diff, error, libcrypt, naming, output, patch, preflight, progress, report,
+    resolve, rip, scan, sector, status, synth, titledb, utils, vcd, verify, vmc,
+};
 
 use combiner::BinCombiner;
 use cue::CueSheet;
-use utils::detect_game_id;
-use vcd::VcdConverter;
+use output::PartialFile;
+use patch::Patch;
+use progress::ConsoleProgress;
+use report::{GameIdReport, RunReport};
+use rip::{PregapMode, RipFormat, RipLayout};
+use sector::SECTOR_SIZE;
+use titledb::TitleDb;
+use utils::{Lba, Msf};
+use vcd::{VcdConverter, VcdHeader};
+use vmc::MemoryCard;
 
 /// Automatic PSX BIN/CUE to VCD converter for OPL/POPSTARTER
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// Output format: human-readable text, or a single JSON document on
+    /// stdout (progress then goes to stderr)
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Policy for output files that already exist
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Overwrite {
+    /// Fail without writing anything (default)
+    Never,
+    /// Replace the existing file
+    Always,
+    /// Ask on a terminal, fail otherwise
+    Ask,
+}
+
+/// Options shared by the modes that combine BIN files
+#[derive(clap::Args, Debug)]
+struct CombineArgs {
+    /// Apply a PPF, IPS, BPS or xdelta3 patch (repeatable)
+    #[arg(long = "patch", value_name = "FILE")]
+    patches: Vec<PathBuf>,
+
+    /// Rebuild sync, headers and EDC/ECC of every data sector
+    #[arg(long)]
+    repair: bool,
+
+    /// Accept common CUE mistakes with a warning (see `lint`)
+    #[arg(long)]
+    lenient: bool,
+
+    /// Rewrite the CUE with the BIN names actually found (keeps a .bak copy)
+    #[arg(long)]
+    fix_cue: bool,
+}
+
+/// Options shared by the modes that write a VCD
+#[derive(clap::Args, Debug)]
+struct VcdArgs {
+    /// Add 2 seconds to track indexes
+    #[arg(long)]
+    gap_plus: bool,
+
+    /// Subtract 2 seconds from track indexes
+    #[arg(long)]
+    gap_minus: bool,
+
+    /// Show the VCD header that would be written, without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// With --dry-run, save the 1 MB header to FILE for comparison
+    #[arg(long, value_name = "FILE", requires = "dry_run")]
+    dump_header: Option<PathBuf>,
+
+    /// What to do when the VCD already exists
+    #[arg(long, value_enum, default_value_t = Overwrite::Never)]
+    overwrite: Overwrite,
+
+    #[command(flatten)]
+    names: NameArgs,
+}
+
+/// Options for the VCD filename
+#[derive(clap::Args, Debug)]
+struct NameArgs {
+    /// VCD filename template: {id}, {title}, {region}, {disc}, {cue_stem}, {db_title}
+    #[arg(long, value_name = "TEMPLATE", default_value = naming::DEFAULT_TEMPLATE)]
+    name_template: String,
+
+    /// Title database for {title} and {db_title} (SERIAL;TITLE lines)
+    #[arg(long, value_name = "FILE")]
+    db: Option<PathBuf>,
+
+    /// Also remove this regex from titles (repeatable)
+    #[arg(long = "strip", value_name = "REGEX")]
+    strip_patterns: Vec<String>,
+
+    /// Transliterate titles to ASCII (accents dropped, kana romanized)
+    #[arg(long)]
+    ascii: bool,
+
+    /// Replace characters FAT32/exFAT reject in titles, and ": " with " - "
+    #[arg(long)]
+    safe_chars: bool,
+
+    /// Longest VCD filename (OPL lists up to 64); titles are shortened to
+    /// fit, the serial is kept
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(16..=255)
+    )]
+    max_name_length: Option<u16>,
+}
+
+impl NameArgs {
+    /// VCD filename for a disc, extension included
+    fn vcd_filename(&self, game_id: Option<&str>, cue_path: &Path) -> Result<String> {
+        let mut template = naming::NameTemplate::parse(&self.name_template)?
+            .with_strip_patterns(&self.strip_patterns)?
+            .with_ascii(self.ascii)
+            .with_safe_chars(self.safe_chars);
+        if let Some(max_len) = self.max_name_length {
+            template = template.with_max_len(usize::from(max_len));
+        }
+        let db = self.db.as_deref().map(TitleDb::load).transpose()?;
+
+        let fields = naming::NameFields {
+            id: game_id.map(str::to_string),
+            cue_stem: file_stem(cue_path)?.to_string(),
+            db_title: game_id
+                .zip(db.as_ref())
+                .and_then(|(id, db)| db.game_title(id))
+                .map(str::to_string),
+        };
+        template.filename(&fields)
+    }
+}
+
+impl VcdArgs {
+    fn validate(&self) -> Result<()> {
+        if self.gap_plus && self.gap_minus {
+            bail!("Cannot use both --gap-plus and --gap-minus");
+        }
+        Ok(())
+    }
+
+    /// Gap adjustment in seconds (+2, -2 or 0)
+    fn gap_seconds(&self) -> i32 {
+        if self.gap_plus {
+            2
+        } else if self.gap_minus {
+            -2
+        } else {
+            0
+        }
+    }
+}
+
+/// Header edits of `patch-header`
+#[derive(clap::Args, Debug)]
+struct HeaderEditArgs {
+    /// Move tracks after the first 2 seconds later
+    #[arg(long)]
+    gap_plus: bool,
+
+    /// Move tracks after the first 2 seconds earlier
+    #[arg(long)]
+    gap_minus: bool,
+
+    /// Set the track count of the TOC
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=99))]
+    track_count: Option<u8>,
+
+    /// Set the lead-out to an absolute disc position (MM:SS:FF)
+    #[arg(long, value_name = "MSF", conflicts_with = "fix_leadout")]
+    leadout: Option<Msf>,
+
+    /// Recompute the lead-out and sector counts from the file size
+    #[arg(long)]
+    fix_leadout: bool,
+
+    /// Rebuild the whole header from a CUE, as `auto` would
+    #[arg(long, value_name = "FILE.cue")]
+    cue: Option<PathBuf>,
+
+    /// Accept common CUE mistakes with a warning (see `lint`)
+    #[arg(long, requires = "cue")]
+    lenient: bool,
+
+    /// Restore the header saved before the first edit
+    #[arg(
+        long,
+        conflicts_with_all = ["gap_plus", "gap_minus", "track_count", "leadout", "fix_leadout", "cue"]
+    )]
+    undo: bool,
+}
+
+impl HeaderEditArgs {
+    fn is_empty(&self) -> bool {
+        !(self.gap_plus
+            || self.gap_minus
+            || self.track_count.is_some()
+            || self.leadout.is_some()
+            || self.fix_leadout
+            || self.cue.is_some()
+            || self.undo)
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Combine and convert to VCD (complete process)
     Auto {
-        /// Input CUE file
-        #[arg(value_name = "INPUT.cue")]
+        /// Input CUE file (or a BIN/IMG, analyzed when it has no CUE)
+        #[arg(value_name = "INPUT")]
         input: PathBuf,
 
         /// Output directory (default: ./psx-vcd-output/)
         #[arg(short, long, value_name = "DIR")]
         output: Option<PathBuf>,
 
-        /// Add 2 seconds to track indexes
-        #[arg(long)]
-        gap_plus: bool,
+        #[command(flatten)]
+        vcd_opts: VcdArgs,
+
+        #[command(flatten)]
+        combine_opts: CombineArgs,
 
-        /// Subtract 2 seconds from track indexes
+        /// Verify EDC/ECC of every data sector before converting
         #[arg(long)]
-        gap_minus: bool,
+        verify: bool,
 
         /// Display detailed CUE information
         #[arg(short, long)]
@@ -61,6 +270,13 @@ enum Commands {
         #[arg(short, long, value_name = "FILE")]
         filename: Option<String>,
 
+        #[command(flatten)]
+        combine_opts: CombineArgs,
+
+        /// What to do when the BIN or CUE already exists
+        #[arg(long, value_enum, default_value_t = Overwrite::Never)]
+        overwrite: Overwrite,
+
         /// Display detailed CUE information
         #[arg(short, long)]
         verbose: bool,
@@ -80,17 +296,16 @@ enum Commands {
         #[arg(short, long, value_name = "DIR")]
         output: Option<PathBuf>,
 
-        /// Output VCD filename
-        #[arg(short, long, value_name = "FILE")]
+        /// Output VCD filename (instead of --name-template)
+        #[arg(short, long, value_name = "FILE", conflicts_with = "name_template")]
         filename: Option<String>,
 
-        /// Add 2 seconds to track indexes
-        #[arg(long)]
-        gap_plus: bool,
+        #[command(flatten)]
+        vcd_opts: VcdArgs,
 
-        /// Subtract 2 seconds from track indexes
+        /// Accept common CUE mistakes with a warning (see `lint`)
         #[arg(long)]
-        gap_minus: bool,
+        lenient: bool,
     },
 
     /// Detect PSX Game ID
@@ -107,41 +322,331 @@ enum Commands {
         #[arg(short, long)]
         debug: bool,
     },
+
+    /// Verify sync, headers and EDC/ECC of every data sector
+    VerifySectors {
+        /// Input CUE file
+        #[arg(value_name = "INPUT.cue")]
+        input: PathBuf,
+
+        /// Accept common CUE mistakes with a warning (see `lint`)
+        #[arg(long)]
+        lenient: bool,
+    },
+
+    /// Generate a CUE for a BIN by analyzing its sectors
+    MakeCue {
+        /// Input BIN or IMG file
+        #[arg(value_name = "INPUT.bin")]
+        input: PathBuf,
+
+        /// Output CUE file (default: next to the BIN)
+        #[arg(short, long, value_name = "FILE.cue")]
+        output: Option<PathBuf>,
+
+        /// What to do when the CUE already exists
+        #[arg(long, value_enum, default_value_t = Overwrite::Never)]
+        overwrite: Overwrite,
+
+        /// Same as --overwrite always
+        #[arg(long, conflicts_with = "overwrite")]
+        force: bool,
+    },
+
+    /// Check a CUE sheet and report every problem with its line and column
+    Lint {
+        /// Input CUE file
+        #[arg(value_name = "INPUT.cue")]
+        input: PathBuf,
+
+        /// Report recoverable mistakes as warnings instead of errors
+        #[arg(long)]
+        lenient: bool,
+    },
+
+    /// Compare two VCDs, or a VCD with the one a CUE would produce
+    Diff {
+        /// First VCD or CUE file
+        #[arg(value_name = "LEFT")]
+        left: PathBuf,
+
+        /// Second VCD or CUE file
+        #[arg(value_name = "RIGHT")]
+        right: PathBuf,
+
+        /// Add 2 seconds to track indexes of a CUE side
+        #[arg(long)]
+        gap_plus: bool,
+
+        /// Subtract 2 seconds from track indexes of a CUE side
+        #[arg(long)]
+        gap_minus: bool,
+
+        /// Accept common CUE mistakes with a warning (see `lint`)
+        #[arg(long)]
+        lenient: bool,
+    },
+
+    /// Edit the header of an existing VCD in place
+    PatchHeader {
+        /// VCD file to edit
+        #[arg(value_name = "INPUT.VCD")]
+        input: PathBuf,
+
+        #[command(flatten)]
+        edits: HeaderEditArgs,
+    },
+
+    /// Generate a synthetic disc image for testing
+    #[command(hide = true)]
+    Synth {
+        /// Output directory
+        #[arg(value_name = "DIR")]
+        output: PathBuf,
+
+        /// Base name of the CUE and BIN files
+        #[arg(long, default_value = "Synthetic")]
+        name: String,
+
+        /// Serial written to SYSTEM.CNF
+        #[arg(long, default_value = "SLUS_000.01")]
+        serial: String,
+
+        /// Length of the data track in sectors
+        #[arg(long, default_value_t = 300)]
+        data_sectors: u32,
+
+        /// Audio track, repeatable (silence:SECTORS or tone:SECTORS)
+        #[arg(long, value_name = "KIND:SECTORS")]
+        audio: Vec<synth::AudioTrackSpec>,
+
+        /// Write one BIN per track
+        #[arg(long)]
+        multi_file: bool,
+    },
+
+    /// Extract CDDA audio tracks to WAV or FLAC
+    RipAudio {
+        /// Input CUE, BIN (with a CUE of the same name) or VCD file
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        #[command(flatten)]
+        rip_opts: RipArgs,
+    },
+
+    /// Convert a PS1 memory card to POPSTARTER VMC format (or back to raw)
+    Vmc {
+        /// Input memory card (.mcr, .mcd, .srm, .gme, .VMP or .VMC)
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+
+        #[command(flatten)]
+        vmc_opts: VmcArgs,
+    },
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Options of the rip-audio mode
+#[derive(clap::Args, Debug)]
+struct RipArgs {
+    /// Output directory (default: ./psx-vcd-output/<name>_audio/)
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// Audio file format
+    #[arg(short = 'f', long, value_enum, default_value_t = RipFormat::Wav)]
+    audio_format: RipFormat,
 
-    println!("PSX to VCD Converter");
-    println!("====================\n");
+    /// Pregap handling for INDEX 00 audio
+    #[arg(long, value_enum, default_value_t = PregapMode::Skip)]
+    pregap: PregapMode,
 
-    match args.command {
+    /// Title database for track names (SERIAL;TITLE and SERIAL;TRACK;TITLE lines)
+    #[arg(long, value_name = "FILE")]
+    db: Option<PathBuf>,
+
+    /// What to do when a track file already exists
+    #[arg(long, value_enum, default_value_t = Overwrite::Never)]
+    overwrite: Overwrite,
+
+    /// Accept common CUE mistakes with a warning (see `lint`)
+    #[arg(long)]
+    lenient: bool,
+}
+
+/// Options of the VMC mode
+#[derive(clap::Args, Debug)]
+struct VmcArgs {
+    /// Game CUE file (VMC is placed next to the VCD auto mode would create
+    /// with the same naming options)
+    #[arg(short, long, value_name = "FILE.cue", conflicts_with = "vcd")]
+    cue: Option<PathBuf>,
+
+    /// Existing VCD file (VMC is placed in its POPS folder)
+    #[arg(long, value_name = "FILE.VCD")]
+    vcd: Option<PathBuf>,
+
+    /// Memory card slot (0 or 1)
+    #[arg(short, long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=1))]
+    slot: u8,
+
+    /// Output directory (default: ./psx-vcd-output/ next to the CUE)
+    #[arg(short, long, value_name = "DIR")]
+    output: Option<PathBuf>,
+
+    /// Convert a VMC back to a raw .mcr memory card
+    #[arg(long)]
+    to_raw: bool,
+
+    /// What to do when the memory card file already exists
+    #[arg(long, value_enum, default_value_t = Overwrite::Never)]
+    overwrite: Overwrite,
+
+    /// Accept common CUE mistakes with a warning (see `lint`)
+    #[arg(long, requires = "cue")]
+    lenient: bool,
+
+    #[command(flatten)]
+    names: NameArgs,
+}
+
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let json = args.format == OutputFormat::Json;
+    report::set_machine_output(json);
+
+    status!("PSX to VCD Converter");
+    status!("====================\n");
+
+    // Destructors do not run when the process is interrupted
+    let _ = ctrlc::set_handler(|| {
+        progress::clear_bar();
+        output::remove_partial_files();
+        eprintln!("\n[!] Interrupted, partial output removed");
+        std::process::exit(130);
+    });
+
+    let mut report = RunReport::new(matches.subcommand_name().unwrap_or_default());
+    let result = run(args.command, &mut report);
+    let exit_code = match &result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            error::exit_code(err)
+        }
+    };
+
+    if json {
+        report.success = result.is_ok();
+        report.exit_code = exit_code;
+        report.error = result.err().map(|err| error_message(&err));
+        match serde_json::to_string_pretty(&report) {
+            Ok(document) => println!("{}", document),
+            Err(err) => eprintln!("Error: failed to serialize report: {}", err),
+        }
+    }
+    ExitCode::from(exit_code)
+}
+
+/// Error and its causes on one line, skipping causes the message already shows
+fn error_message(err: &anyhow::Error) -> String {
+    let mut message = err.to_string();
+    for cause in err.chain().skip(1) {
+        let cause = cause.to_string();
+        if !message.contains(&cause) {
+            message = format!("{}: {}", message, cause);
+        }
+    }
+    message
+}
+
+fn run(command: Commands, report: &mut RunReport) -> Result<()> {
+    match command {
         Commands::Auto {
             input,
             output,
-            gap_plus,
-            gap_minus,
+            vcd_opts,
+            combine_opts,
+            verify,
             verbose,
-        } => run_auto_mode(input, output, gap_plus, gap_minus, verbose),
+        } => run_auto_mode(
+            input,
+            output,
+            vcd_opts,
+            combine_opts,
+            verify,
+            verbose,
+            report,
+        ),
         Commands::Combine {
             input,
             output,
             filename,
+            combine_opts,
+            overwrite,
             verbose,
-        } => run_combine_mode(input, output, filename, verbose),
+        } => run_combine_mode(
+            input,
+            output,
+            filename,
+            combine_opts,
+            overwrite,
+            verbose,
+            report,
+        ),
         Commands::Convert {
             input,
             cue,
             output,
             filename,
-            gap_plus,
-            gap_minus,
-        } => run_convert_mode(input, cue, output, filename, gap_plus, gap_minus),
+            vcd_opts,
+            lenient,
+        } => run_convert_mode(input, cue, output, filename, vcd_opts, lenient, report),
         Commands::Detect {
             input,
             verbose,
             debug,
-        } => run_detect_mode(input, verbose, debug),
+        } => run_detect_mode(input, verbose, debug, report),
+        Commands::VerifySectors { input, lenient } => {
+            run_verify_sectors_mode(input, lenient, report)
+        }
+        Commands::MakeCue {
+            input,
+            output,
+            overwrite,
+            force,
+        } => {
+            let overwrite = if force { Overwrite::Always } else { overwrite };
+            run_make_cue_mode(input, output, overwrite, report)
+        }
+        Commands::Lint { input, lenient } => run_lint_mode(input, lenient, report),
+        Commands::Diff {
+            left,
+            right,
+            gap_plus,
+            gap_minus,
+            lenient,
+        } => run_diff_mode(left, right, gap_plus, gap_minus, lenient, report),
+        Commands::PatchHeader { input, edits } => run_patch_header_mode(input, edits, report),
+        Commands::Synth {
+            output,
+            name,
+            serial,
+            data_sectors,
+            audio,
+            multi_file,
+        } => {
+            let disc = synth::SyntheticDisc {
+                serial,
+                data_sectors,
+                audio_tracks: audio,
+                multi_file,
+            };
+            run_synth_mode(&disc, &output, &name, report)
+        }
+        Commands::RipAudio { input, rip_opts } => run_rip_audio_mode(input, rip_opts, report),
+        Commands::Vmc { input, vmc_opts } => run_vmc_mode(input, vmc_opts, report),
     }
 }
 
@@ -149,74 +654,130 @@ fn main() -> Result<()> {
 fn run_auto_mode(
     input: PathBuf,
     output: Option<PathBuf>,
-    gap_plus: bool,
-    gap_minus: bool,
+    vcd_opts: VcdArgs,
+    combine_opts: CombineArgs,
+    verify: bool,
     verbose: bool,
+    report: &mut RunReport,
 ) -> Result<()> {
-    validate_cue_input(&input)?;
-    validate_gap_flags(gap_plus, gap_minus)?;
+    vcd_opts.validate()?;
+    report.input = Some(input.clone());
+    report.gap_adjustment = vcd_opts.gap_seconds();
+    let mut patches = load_patches(&combine_opts.patches)?;
 
-    println!("[*] Parsing CUE file: {}", input.display());
-    let mut cue_sheet = CueSheet::parse(&input)?;
+    let (input, mut cue_sheet) = if is_image(&input) {
+        load_bare_bin(input, combine_opts.lenient, vcd_opts.dry_run, report)?
+    } else {
+        validate_cue_input(&input)?;
+        status!("[*] Parsing CUE file: {}", input.display());
+        let cue_sheet = load_cue(&input, combine_opts.lenient, report)?;
+        (input, cue_sheet)
+    };
 
     let cue_dir = input
         .parent()
         .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
 
-    cue_sheet.load_file_sizes(cue_dir)?;
-    cue_sheet.validate_mode2()?;
+    resolve_files(&mut cue_sheet, &input, combine_opts.fix_cue, report)?;
+    report.cue_sheet = Some(cue_sheet.clone());
+    cue_sheet.validate_mode2(true)?;
+    check_track_types(&cue_sheet, &input, report)?;
 
     if verbose {
         cue_sheet.print_info();
     } else {
-        println!("[+] Found {} track(s)", cue_sheet.get_total_tracks());
+        status!("[+] Found {} track(s)", cue_sheet.get_total_tracks());
     }
 
     // Detect Game ID before combining (from first BIN)
     let first_bin = cue_dir.join(&cue_sheet.files[0].filename);
-    let game_id = detect_and_print_game_id(&first_bin)?;
+    let game_id = detect_and_print_game_id(&first_bin, report)?;
+    check_libcrypt(
+        game_id.as_deref(),
+        &input,
+        &mut patches,
+        !vcd_opts.dry_run,
+        report,
+    )?;
+
+    if verify && vcd_opts.dry_run {
+        report.warn("--verify reads every sector and is skipped in a dry run");
+    } else if verify {
+        status!("\n[*] Verifying data sectors");
+        let verify_report = verify::verify_cue(&cue_sheet, cue_dir)?;
+        print_verify_report(&verify_report, report);
+        if !verify_report.is_clean() {
+            report.warn("Continuing with corrupt sectors: the VCD will contain them as-is");
+        }
+    }
 
     // Determine output directory
     let output_dir = output.unwrap_or_else(|| cue_dir.join("psx-vcd-output"));
-    std::fs::create_dir_all(&output_dir)?;
-    println!("[*] Output directory: {}\n", output_dir.display());
 
     let game_name = input
         .file_stem()
         .and_then(|s| s.to_str())
         .ok_or_else(|| anyhow::anyhow!("Invalid input filename"))?;
 
-    let clean_name = clean_game_name(game_name);
+    let clean_name = naming::clean_title(game_name);
+    let vcd_name = vcd_opts.names.vcd_filename(game_id.as_deref(), &input)?;
 
-    // Step 1: Combine BINs
-    println!("[*] Step 1: Combining BIN files");
-    let combined_bin = output_dir.join(format!("{}_combined.bin", clean_name));
-    let combine_info = BinCombiner::combine(&mut cue_sheet, cue_dir, &combined_bin)?;
-    println!(
+    if vcd_opts.dry_run {
+        if !patches.is_empty() {
+            report.warn(
+                "Patches are not applied in a dry run; the plan assumes they keep the image size",
+            );
+        }
+        status!("\n[*] Planning combined BIN (file sizes only)");
+        let plan = BinCombiner::new(patches, combine_opts.repair).plan(&mut cue_sheet)?;
+        let final_output = output_dir.join(&vcd_name);
+        let vcd_size = vcd::VCD_HEADER_SIZE as u64 + plan.total_bytes;
+        let outputs = [(final_output.as_path(), vcd_size)];
+        if let Err(err) = preflight(&output_dir, &outputs, plan.total_bytes, report) {
+            report.warn(err.to_string());
+        }
+        return print_plan(
+            &final_output,
+            plan.total_bytes,
+            &cue_sheet,
+            &vcd_opts,
+            report,
+        );
+    }
+
+    let final_output = output_dir.join(&vcd_name);
+    let replace = check_overwrite(&final_output, vcd_opts.overwrite)?;
+
+    // The combined BIN and the VCD exist side by side until the end
+    let bin_size = BinCombiner::new(Vec::new(), combine_opts.repair).combined_size(&cue_sheet);
+    let vcd_size = vcd::VCD_HEADER_SIZE as u64 + bin_size;
+    preflight(&output_dir, &[(&final_output, vcd_size)], bin_size, report)?;
+
+    std::fs::create_dir_all(&output_dir)?;
+    status!("[*] Output directory: {}\n", output_dir.display());
+
+    // Step 1: Combine BINs (an intermediate file, removed when done)
+    status!("[*] Step 1: Combining BIN files");
+    let combined_bin = PartialFile::new(&output_dir.join(format!("{}_combined.bin", clean_name)));
+    let combine_info = BinCombiner::new(patches, combine_opts.repair)
+        .with_progress(Box::new(ConsoleProgress::new()))
+        .combine(&mut cue_sheet, cue_dir, combined_bin.path())?;
+    status!(
         "[+] Combined {} track(s) -> {:.2} MB\n",
         combine_info.track_count,
         combine_info.total_bytes as f64 / (1024.0 * 1024.0)
     );
 
     // Step 2: Convert to VCD
-    println!("[*] Step 2: Converting to VCD format");
-    let temp_vcd = output_dir.join(format!("{}.VCD", clean_name));
-    let converter = VcdConverter::new(gap_plus, gap_minus);
-    converter.convert_to_vcd(&combined_bin, &temp_vcd, &cue_sheet)?;
-
-    // Rename with Game ID if detected
-    let final_output = if let Some(id) = game_id {
-        let renamed_vcd = output_dir.join(format!("{}.{}.VCD", id, clean_name));
-        std::fs::rename(&temp_vcd, &renamed_vcd)?;
-        renamed_vcd
-    } else {
-        temp_vcd
-    };
-
-    // Clean up temporary file
-    let _ = std::fs::remove_file(&combined_bin);
-
-    print_success(&final_output, gap_plus, gap_minus)?;
+    status!("[*] Step 2: Converting to VCD format");
+    let vcd = PartialFile::new(&final_output);
+    let converter = VcdConverter::new(vcd_opts.gap_plus, vcd_opts.gap_minus)
+        .with_progress(Box::new(ConsoleProgress::new()));
+    converter.convert_to_vcd(combined_bin.path(), vcd.path(), &cue_sheet)?;
+    drop(combined_bin);
+    let final_output = vcd.persist(replace)?;
+
+    print_success(&final_output, &vcd_opts, report)?;
     Ok(())
 }
 
@@ -225,28 +786,35 @@ fn run_combine_mode(
     input: PathBuf,
     output: Option<PathBuf>,
     filename: Option<String>,
+    combine_opts: CombineArgs,
+    overwrite: Overwrite,
     verbose: bool,
+    report: &mut RunReport,
 ) -> Result<()> {
     validate_cue_input(&input)?;
+    report.input = Some(input.clone());
+    let patches = load_patches(&combine_opts.patches)?;
 
-    println!("[*] Parsing CUE file: {}", input.display());
-    let mut cue_sheet = CueSheet::parse(&input)?;
+    status!("[*] Parsing CUE file: {}", input.display());
+    let mut cue_sheet = load_cue(&input, combine_opts.lenient, report)?;
 
     let cue_dir = input
         .parent()
         .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
 
-    cue_sheet.load_file_sizes(cue_dir)?;
-    cue_sheet.validate_mode2()?;
+    resolve_files(&mut cue_sheet, &input, combine_opts.fix_cue, report)?;
+    report.cue_sheet = Some(cue_sheet.clone());
+    cue_sheet.validate_mode2(true)?;
+    check_track_types(&cue_sheet, &input, report)?;
 
     if verbose {
         cue_sheet.print_info();
     } else {
-        println!("[+] Found {} track(s)", cue_sheet.get_total_tracks());
+        status!("[+] Found {} track(s)", cue_sheet.get_total_tracks());
     }
 
     let first_bin = cue_dir.join(&cue_sheet.files[0].filename);
-    let game_id = detect_and_print_game_id(&first_bin)?;
+    let game_id = detect_and_print_game_id(&first_bin, report)?;
 
     let output_dir = output.unwrap_or_else(|| cue_dir.join("psx-vcd-output"));
     std::fs::create_dir_all(&output_dir)?;
@@ -256,20 +824,33 @@ fn run_combine_mode(
         .and_then(|s| s.to_str())
         .ok_or_else(|| anyhow::anyhow!("Invalid input filename"))?;
 
-    let clean_name = clean_game_name(game_name);
+    let clean_name = naming::clean_title(game_name);
     let output_filename = filename.unwrap_or_else(|| format!("{}_combined.bin", clean_name));
     let combined_bin = output_dir.join(&output_filename);
+    let output_cue = combined_bin.with_extension("cue");
+    let replace_bin = check_overwrite(&combined_bin, overwrite)?;
+    let replace_cue = check_overwrite(&output_cue, overwrite)?;
+
+    let bin_size = BinCombiner::new(Vec::new(), combine_opts.repair).combined_size(&cue_sheet);
+    let outputs = [
+        (combined_bin.as_path(), bin_size),
+        (output_cue.as_path(), 0),
+    ];
+    preflight(&output_dir, &outputs, 0, report)?;
 
-    println!("\n[*] Combining BIN files");
-    let combine_info = BinCombiner::combine(&mut cue_sheet, cue_dir, &combined_bin)?;
+    status!("\n[*] Combining BIN files");
+    let partial_bin = PartialFile::new(&combined_bin);
+    let combine_info = BinCombiner::new(patches, combine_opts.repair)
+        .with_progress(Box::new(ConsoleProgress::new()))
+        .combine(&mut cue_sheet, cue_dir, partial_bin.path())?;
 
     // Generate new CUE file for the combined BIN
-    println!("\n[*] Generating new CUE file...");
-    let output_cue = combined_bin.with_extension("cue");
+    status!("\n[*] Generating new CUE file...");
+    let partial_cue = PartialFile::new(&output_cue);
 
     use std::io::Write;
     let mut cue_file =
-        std::fs::File::create(&output_cue).context("Failed to create output CUE file")?;
+        std::fs::File::create(partial_cue.path()).context("Failed to create output CUE file")?;
 
     writeln!(
         cue_file,
@@ -281,30 +862,43 @@ fn run_combine_mode(
         for track in &file.tracks {
             writeln!(cue_file, "  TRACK {:02} {}", track.number, track.track_type)?;
 
-            if let Some(idx00) = track.index00_msf {
+            // Disc positions where the combiner set them, as in the header
+            let (index00, index01) = match track.disc {
+                Some(disc) => (Some(disc.index00), disc.index01),
+                None => (
+                    track.index00.map(Lba::to_relative_msf),
+                    track.index01.to_relative_msf(),
+                ),
+            };
+            if let Some(idx00) = index00 {
                 writeln!(cue_file, "    INDEX 00 {}", idx00)?;
             }
-            writeln!(cue_file, "    INDEX 01 {}", track.index01_msf)?;
+            writeln!(cue_file, "    INDEX 01 {}", index01)?;
         }
     }
 
     cue_file.flush()?;
-
-    println!("\n[+] BIN and CUE files created successfully!");
-    println!("    BIN: {}", combined_bin.display());
-    println!("    CUE: {}", output_cue.display());
-    println!(
+    drop(cue_file);
+    // Both files or neither
+    output::persist_all(vec![(partial_bin, replace_bin), (partial_cue, replace_cue)])?;
+    report.add_output("bin", &combined_bin)?;
+    report.add_output("cue", &output_cue)?;
+
+    status!("\n[+] BIN and CUE files created successfully!");
+    status!("    BIN: {}", combined_bin.display());
+    status!("    CUE: {}", output_cue.display());
+    status!(
         "    Size: {:.2} MB",
         combine_info.total_bytes as f64 / (1024.0 * 1024.0)
     );
-    println!("    Tracks: {}", combine_info.track_count);
+    status!("    Tracks: {}", combine_info.track_count);
     if let Some(id) = game_id {
-        println!("    Game ID: {}", id);
+        status!("    Game ID: {}", id);
     }
-    println!();
-    println!("[i] You can now use this CUE with cue2pops:");
-    println!("    cue2pops \"{}\"", output_cue.display());
-    println!();
+    status!();
+    status!("[i] You can now use this CUE with cue2pops:");
+    status!("    cue2pops \"{}\"", output_cue.display());
+    status!();
 
     Ok(())
 }
@@ -315,25 +909,25 @@ fn run_convert_mode(
     cue: PathBuf,
     output: Option<PathBuf>,
     filename: Option<String>,
-    gap_plus: bool,
-    gap_minus: bool,
+    vcd_opts: VcdArgs,
+    lenient: bool,
+    report: &mut RunReport,
 ) -> Result<()> {
     validate_bin_input(&input)?;
     validate_cue_input(&cue)?;
-    validate_gap_flags(gap_plus, gap_minus)?;
+    vcd_opts.validate()?;
+    report.input = Some(input.clone());
+    report.gap_adjustment = vcd_opts.gap_seconds();
 
-    let game_id = detect_and_print_game_id(&input)?;
-
-    println!("\n[*] Parsing CUE file: {}", cue.display());
-    let mut cue_sheet = CueSheet::parse(&cue)?;
-
-    let cue_dir = cue
-        .parent()
-        .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
+    let game_id = detect_and_print_game_id(&input, report)?;
 
-    cue_sheet.load_file_sizes(cue_dir)?;
-    cue_sheet.validate_mode2()?;
-    println!("[+] Found {} track(s)", cue_sheet.get_total_tracks());
+    status!("\n[*] Parsing CUE file: {}", cue.display());
+    let mut cue_sheet = load_cue(&cue, lenient, report)?;
+    resolve_files(&mut cue_sheet, &cue, false, report)?;
+    report.cue_sheet = Some(cue_sheet.clone());
+    cue_sheet.validate_mode2(false)?;
+    check_track_types(&cue_sheet, &cue, report)?;
+    status!("[+] Found {} track(s)", cue_sheet.get_total_tracks());
 
     let output_dir = output.unwrap_or_else(|| {
         input
@@ -341,40 +935,49 @@ fn run_convert_mode(
             .unwrap_or(std::path::Path::new("."))
             .to_path_buf()
     });
-    std::fs::create_dir_all(&output_dir)?;
-
-    let game_name = input
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| anyhow::anyhow!("Invalid input filename"))?;
 
-    let clean_name = clean_game_name(game_name);
-    let output_filename = filename.unwrap_or_else(|| format!("{}.VCD", clean_name));
-    let temp_vcd = output_dir.join(&output_filename);
+    let final_output = match filename {
+        Some(filename) => output_dir.join(filename),
+        None => output_dir.join(vcd_opts.names.vcd_filename(game_id.as_deref(), &input)?),
+    };
 
-    println!("\n[*] Converting to VCD format");
-    let converter = VcdConverter::new(gap_plus, gap_minus);
-    converter.convert_to_vcd(&input, &temp_vcd, &cue_sheet)?;
+    if vcd_opts.dry_run {
+        let bin_size = std::fs::metadata(&input)?.len();
+        let vcd_size = vcd::VCD_HEADER_SIZE as u64 + bin_size;
+        if let Err(err) = preflight(&output_dir, &[(&final_output, vcd_size)], 0, report) {
+            report.warn(err.to_string());
+        }
+        return print_plan(&final_output, bin_size, &cue_sheet, &vcd_opts, report);
+    }
+    let replace = check_overwrite(&final_output, vcd_opts.overwrite)?;
+    let vcd_size = vcd::VCD_HEADER_SIZE as u64 + std::fs::metadata(&input)?.len();
+    preflight(&output_dir, &[(&final_output, vcd_size)], 0, report)?;
+    std::fs::create_dir_all(&output_dir)?;
 
-    let final_output = if let Some(id) = game_id {
-        let renamed_vcd = output_dir.join(format!("{}.{}.VCD", id, clean_name));
-        std::fs::rename(&temp_vcd, &renamed_vcd)?;
-        renamed_vcd
-    } else {
-        temp_vcd
-    };
+    status!("\n[*] Converting to VCD format");
+    let vcd = PartialFile::new(&final_output);
+    let converter = VcdConverter::new(vcd_opts.gap_plus, vcd_opts.gap_minus)
+        .with_progress(Box::new(ConsoleProgress::new()));
+    converter.convert_to_vcd(&input, vcd.path(), &cue_sheet)?;
+    let final_output = vcd.persist(replace)?;
 
-    print_success(&final_output, gap_plus, gap_minus)?;
+    print_success(&final_output, &vcd_opts, report)?;
     Ok(())
 }
 
 /// Detect mode: Game ID detection only
-fn run_detect_mode(input: PathBuf, verbose: bool, debug: bool) -> Result<()> {
+fn run_detect_mode(
+    input: PathBuf,
+    verbose: bool,
+    debug: bool,
+    report: &mut RunReport,
+) -> Result<()> {
+    report.input = Some(input.clone());
     let bin_path = if let Some(ext) = input.extension() {
         let ext_str = ext.to_string_lossy().to_lowercase();
 
         if ext_str == "cue" {
-            println!("[*] Parsing CUE file: {}", input.display());
+            status!("[*] Parsing CUE file: {}", input.display());
             let cue_sheet = CueSheet::parse(&input)?;
 
             let cue_dir = input
@@ -386,7 +989,8 @@ fn run_detect_mode(input: PathBuf, verbose: bool, debug: bool) -> Result<()> {
             }
 
             let first_bin = cue_dir.join(&cue_sheet.files[0].filename);
-            println!("    Reading from: {}\n", cue_sheet.files[0].filename);
+            status!("    Reading from: {}\n", cue_sheet.files[0].filename);
+            report.cue_sheet = Some(cue_sheet);
             first_bin
         } else if ext_str == "bin" {
             input.clone()
@@ -405,7 +1009,7 @@ fn run_detect_mode(input: PathBuf, verbose: bool, debug: bool) -> Result<()> {
         use std::fs::File;
         use std::io::Read;
 
-        println!("[*] Debug mode: Searching for PSX patterns in BIN...\n");
+        status!("[*] Debug mode: Searching for PSX patterns in BIN...\n");
 
         let mut file = File::open(&bin_path)?;
         let mut buffer = vec![0u8; 150 * 1024];
@@ -417,48 +1021,69 @@ fn run_detect_mode(input: PathBuf, verbose: bool, debug: bool) -> Result<()> {
         use regex::Regex;
         let pattern = Regex::new(r"[A-Z]{4}[_\- ]\d{3}[\.\d]{3}")?;
 
-        println!("Found potential IDs:");
-        println!("----------------------------");
-        let mut found_any = false;
-        for caps in pattern.find_iter(&search_str) {
-            println!("  {}", caps.as_str());
-            found_any = true;
+        status!("Found potential IDs:");
+        status!("----------------------------");
+        let candidates: Vec<&str> = pattern.find_iter(&search_str).map(|m| m.as_str()).collect();
+        for candidate in &candidates {
+            status!("  {}", candidate);
         }
 
-        if !found_any {
-            println!("  (none found)");
+        if candidates.is_empty() {
+            status!("  (none found)");
         }
-        println!("----------------------------\n");
+        status!("----------------------------\n");
+        report.detail("candidates", &candidates);
     }
 
-    println!("[*] Detecting Game ID...");
-    match detect_game_id(&bin_path)? {
+    status!("[*] Detecting Game ID...");
+    let found = utils::detect_game_id_match_at(&bin_path, 0)?;
+    if let Some(found) = &found {
+        report.game_id = Some(game_id_report(found, &bin_path));
+    }
+    match found.map(|m| m.id) {
         Some(game_id) => {
             if verbose {
-                println!("\n[+] Game ID found!");
-                println!("----------------------------");
-                println!("    Game ID: {}", game_id);
-                println!("    Region:  {}", get_region(&game_id));
-                println!(
+                status!("\n[+] Game ID found!");
+                status!("----------------------------");
+                status!("    Game ID: {}", game_id);
+                status!("    Region:  {}", get_region(&game_id));
+                status!(
                     "    BIN:     {}",
                     bin_path.file_name().unwrap().to_string_lossy()
                 );
-                println!("----------------------------\n");
+
+                let lc = libcrypt::detect(Some(&game_id), &input);
+                for warning in &lc.warnings {
+                    report.warn(warning.as_str());
+                }
+                report.detail("libcrypt", lc.is_protected());
+                if lc.is_protected() {
+                    status!("    LibCrypt: Yes ({})", lc.describe());
+                    if let Some(crack) = &lc.crack {
+                        status!(
+                            "    Crack:   {}",
+                            crack.file_name().unwrap().to_string_lossy()
+                        );
+                    }
+                } else {
+                    status!("    LibCrypt: No");
+                }
+                status!("----------------------------\n");
             } else {
-                println!("{}", game_id);
+                status!("{}", game_id);
             }
         }
         None => {
             if verbose {
-                println!("\n[-] No Game ID found");
-                println!("    The BIN file may be:");
-                println!("    - Corrupted or incomplete");
-                println!("    - Not a valid PSX game disc");
-                println!("    - Using a non-standard format");
-                println!("\n[i] Try running with --debug to see what's in the file:\n");
-                println!("    psx-vcd detect {} --debug\n", input.display());
+                status!("\n[-] No Game ID found");
+                status!("    The BIN file may be:");
+                status!("    - Corrupted or incomplete");
+                status!("    - Not a valid PSX game disc");
+                status!("    - Using a non-standard format");
+                status!("\n[i] Try running with --debug to see what's in the file:\n");
+                status!("    psx-vcd detect {} --debug\n", input.display());
             } else {
-                println!("NOT_FOUND");
+                status!("NOT_FOUND");
             }
         }
     }
@@ -466,140 +1091,1024 @@ fn run_detect_mode(input: PathBuf, verbose: bool, debug: bool) -> Result<()> {
     Ok(())
 }
 
-// Helper functions
+/// Verify-sectors mode: EDC/ECC check of every data sector
+fn run_verify_sectors_mode(input: PathBuf, lenient: bool, report: &mut RunReport) -> Result<()> {
+    validate_cue_input(&input)?;
+    report.input = Some(input.clone());
+
+    status!("[*] Parsing CUE file: {}", input.display());
+    let mut cue_sheet = load_cue(&input, lenient, report)?;
+
+    let cue_dir = input
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
+
+    resolve_files(&mut cue_sheet, &input, false, report)?;
+    report.cue_sheet = Some(cue_sheet.clone());
+    check_track_types(&cue_sheet, &input, report)?;
+    status!("[+] Found {} track(s)\n", cue_sheet.get_total_tracks());
+
+    status!("[*] Verifying data sectors");
+    let verify_report = verify::verify_cue(&cue_sheet, cue_dir)?;
+    print_verify_report(&verify_report, report);
+
+    if !verify_report.is_clean() {
+        bail!("{} corrupt sector(s) found", verify_report.corrupt.len());
+    }
+    Ok(())
+}
+
+/// Make-cue mode: guess the track layout of a bare BIN
+fn run_make_cue_mode(
+    input: PathBuf,
+    output: Option<PathBuf>,
+    overwrite: Overwrite,
+    report: &mut RunReport,
+) -> Result<()> {
+    validate_bin_input(&input)?;
+    report.input = Some(input.clone());
 
-fn detect_and_print_game_id(bin_path: &Path) -> Result<Option<String>> {
-    println!("\n[*] Detecting Game ID...");
-    let game_id = detect_game_id(bin_path)?;
+    let cue_path = output.unwrap_or_else(|| input.with_extension("cue"));
+    let replace = check_overwrite(&cue_path, overwrite)?;
 
-    if let Some(ref id) = game_id {
-        println!("[+] Game ID: {} ({})", id, get_region(id));
+    status!("[*] Analyzing BIN: {}", input.display());
+    let mut scan = scan::scan_bin(&input)?;
+    print_scan_report(&scan, report);
+
+    // FILE names are relative to the CUE
+    let bin_dir = canonical_parent(&input)?;
+    let cue_dir = canonical_parent(&cue_path)?;
+    if bin_dir != cue_dir {
+        let bin_path = std::fs::canonicalize(&input)?;
+        scan.cue_sheet.files[0].filename = bin_path.to_string_lossy().into_owned();
+    }
+
+    let partial = PartialFile::new(&cue_path);
+    std::fs::write(partial.path(), scan.cue_sheet.to_cue_text())
+        .with_context(|| format!("Failed to write: {}", cue_path.display()))?;
+    partial.persist(replace)?;
+    report.cue_sheet = Some(scan.cue_sheet);
+    report.add_output("cue", &cue_path)?;
+    status!("\n[+] CUE created: {}\n", cue_path.display());
+    Ok(())
+}
+
+/// Synth mode: write a synthetic disc image for tests
+fn run_synth_mode(
+    disc: &synth::SyntheticDisc,
+    output: &Path,
+    name: &str,
+    report: &mut RunReport,
+) -> Result<()> {
+    let cue_path = disc.write(output, name)?;
+    report.add_output("cue", &cue_path)?;
+    status!(
+        "[+] Synthetic disc {} ({} track(s)): {}",
+        disc.serial,
+        disc.audio_tracks.len() + 1,
+        cue_path.display()
+    );
+    Ok(())
+}
+
+/// Lint mode: report every CUE diagnostic without converting
+fn run_lint_mode(input: PathBuf, lenient: bool, report: &mut RunReport) -> Result<()> {
+    validate_cue_input(&input)?;
+    report.input = Some(input.clone());
+
+    status!("[*] Checking CUE file: {}\n", input.display());
+    let diagnostics = CueSheet::lint(&input, lenient)?;
+
+    for diagnostic in &diagnostics {
+        let separator = if diagnostic.line > 0 { ":" } else { ": " };
+        status!("{}{}{}", input.display(), separator, diagnostic);
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == cue::Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    report.detail("diagnostics", &diagnostics);
+
+    if diagnostics.is_empty() {
+        status!("[+] No problems found\n");
     } else {
-        println!("[!] Game ID not found (non-standard or corrupted)");
+        status!("\n[i] {} error(s), {} warning(s)\n", errors, warnings);
     }
 
-    Ok(game_id)
+    if errors > 0 {
+        return Err(error::Error::InvalidCue(format!(
+            "{} error(s) in {}",
+            errors,
+            input.display()
+        ))
+        .into());
+    }
+    Ok(())
 }
 
-fn get_region(game_id: &str) -> &'static str {
-    if game_id.starts_with("SLUS") || game_id.starts_with("SCUS") {
-        "USA"
-    } else if game_id.starts_with("SLES") || game_id.starts_with("SCES") {
-        "Europe"
-    } else if game_id.starts_with("SLPS")
-        || game_id.starts_with("SCPS")
-        || game_id.starts_with("SLPM")
-    {
-        "Japan"
-    } else if game_id.starts_with("SCED") || game_id.starts_with("SLED") {
-        "Europe (Demo)"
+/// Diff mode: explain the differences between two VCD images
+fn run_diff_mode(
+    left: PathBuf,
+    right: PathBuf,
+    gap_plus: bool,
+    gap_minus: bool,
+    lenient: bool,
+    report: &mut RunReport,
+) -> Result<()> {
+    if gap_plus && gap_minus {
+        bail!("Cannot use both --gap-plus and --gap-minus");
+    }
+    report.input = Some(left.clone());
+    let converter = VcdConverter::new(gap_plus, gap_minus);
+
+    let mut left_side = open_diff_side(&left, &converter, lenient, report)?;
+    let mut right_side = open_diff_side(&right, &converter, lenient, report)?;
+
+    status!("\n[*] Comparing headers and payloads");
+    let diff = diff::compare(&mut left_side, &mut right_side)?;
+    report.detail("header", &diff.header);
+    report.detail("payload", &diff.payload);
+
+    status!("\n    {:<22} {:<7} {:<18} RIGHT", "FIELD", "OFFSET", "LEFT");
+    for field in &diff.header.fields {
+        status!(
+            "  {} {:<22} 0x{:04X}  {:<18} {}",
+            if field.differs() { "!" } else { " " },
+            field.name,
+            field.offset,
+            field.left,
+            field.right
+        );
+    }
+    let differing_fields = diff.header.differing().count();
+    if let Some(offset) = diff.header.first_other_offset {
+        status!(
+            "[!] {} other header byte(s) differ, first at 0x{:05X}",
+            diff.header.other_bytes,
+            offset
+        );
+    }
+    if diff.header.is_identical() {
+        status!("[+] Headers are identical");
     } else {
-        "Unknown"
+        status!("[!] {} header field(s) differ", differing_fields);
+    }
+
+    let payload = &diff.payload;
+    status!(
+        "\n[*] Payload: {} sector(s) vs {} sector(s)",
+        payload.left_sectors,
+        payload.right_sectors
+    );
+    if payload.left_sectors != payload.right_sectors {
+        status!("[!] Payload sizes differ");
     }
+    match (payload.first_difference, payload.first_difference_msf()) {
+        (Some(lba), Some(msf)) => {
+            status!(
+                "[!] {} differing sector(s), first at LBA {} (MSF {})",
+                payload.differing,
+                lba,
+                msf
+            );
+            for track in &payload.tracks {
+                status!(
+                    "    Track {:02}: {} of {} sector(s) differ",
+                    track.number,
+                    track.differing,
+                    track.sectors
+                );
+            }
+        }
+        _ => status!("[+] All compared sectors are identical"),
+    }
+    status!();
+
+    if !diff.is_identical() {
+        bail!("{} and {} differ", left.display(), right.display());
+    }
+    status!("[+] Images are identical\n");
+    Ok(())
 }
 
-fn validate_cue_input(path: &Path) -> Result<()> {
-    if !path.exists() {
-        bail!("Input file does not exist: {}", path.display());
+/// Patch-header mode: edit the first 1MB of a VCD without reconverting
+fn run_patch_header_mode(
+    input: PathBuf,
+    edits: HeaderEditArgs,
+    report: &mut RunReport,
+) -> Result<()> {
+    if !input.is_file() {
+        bail!("Input file does not exist: {}", input.display());
     }
-    if path.extension().and_then(|s| s.to_str()) != Some("cue") {
-        bail!("Input must be a .cue file");
+    report.input = Some(input.clone());
+
+    if edits.undo {
+        let backup = vcd::restore_header(&input)?;
+        report.set_header(&VcdHeader::read(&input)?);
+        status!("[+] Original header restored from {}\n", backup.display());
+        return Ok(());
+    }
+    if edits.is_empty() {
+        bail!("Nothing to change: use --gap-plus, --gap-minus, --track-count, --leadout, --fix-leadout, --cue or --undo");
+    }
+    if edits.gap_plus && edits.gap_minus {
+        bail!("Cannot use both --gap-plus and --gap-minus");
+    }
+
+    status!("[*] Reading VCD header: {}", input.display());
+    let original = VcdHeader::read_raw(&input)?;
+    let payload_bytes = std::fs::metadata(&input)?.len() - vcd::VCD_HEADER_SIZE as u64;
+    let payload_sectors = u32::try_from(payload_bytes / SECTOR_SIZE as u64).unwrap_or(u32::MAX);
+    let converter = VcdConverter::new(edits.gap_plus, edits.gap_minus);
+    report.gap_adjustment = if edits.gap_plus {
+        2
+    } else if edits.gap_minus {
+        -2
+    } else {
+        0
+    };
+
+    let mut header = match &edits.cue {
+        Some(cue) => {
+            validate_cue_input(cue)?;
+            status!("[*] Parsing CUE file: {}", cue.display());
+            let mut cue_sheet = load_cue(cue, edits.lenient, report)?;
+            resolve_files(&mut cue_sheet, cue, false, report)?;
+            cue_sheet.validate_mode2(true)?;
+            report.cue_sheet = Some(cue_sheet.clone());
+
+            let plan = BinCombiner::new(Vec::new(), false).plan(&mut cue_sheet)?;
+            if plan.total_bytes != payload_bytes {
+                report.warn(format!(
+                    "The CUE describes {} bytes of disc data but the VCD holds {}",
+                    plan.total_bytes, payload_bytes
+                ));
+            }
+            converter.create_vcd_header(payload_bytes, &cue_sheet)?
+        }
+        None => {
+            let mut header = original.clone();
+            if edits.gap_plus || edits.gap_minus {
+                converter.regap_header(&mut header)?;
+            }
+            header
+        }
+    };
+
+    if let Some(count) = edits.track_count {
+        vcd::set_track_count(&mut header, count)?;
     }
+    if let Some(leadout) = edits.leadout {
+        vcd::set_leadout(&mut header, leadout);
+    }
+    if edits.fix_leadout {
+        vcd::set_total_sectors(&mut header, payload_sectors)?;
+    }
+
+    // Refuse to write a header POPS could not read back
+    report.set_header(&VcdHeader::parse(&header)?);
+
+    let changes = diff::compare_headers(&original, &header);
+    if changes.is_identical() {
+        status!("[i] Header already up to date, nothing written\n");
+        return Ok(());
+    }
+    for field in changes.differing() {
+        status!("    {:<22} {} -> {}", field.name, field.left, field.right);
+    }
+    report.detail("changes", changes.differing().collect::<Vec<_>>());
+
+    let backup = vcd::write_header_in_place(&input, &header)?;
+    report.add_output("backup", &backup)?;
+    status!("\n[+] Header updated: {}", input.display());
+    status!("    Original header saved as {}", backup.display());
+    status!("[i] Run with --undo to restore it\n");
     Ok(())
 }
 
-fn validate_bin_input(path: &Path) -> Result<()> {
+/// Open a VCD, or build the VCD a CUE would convert to
+fn open_diff_side(
+    path: &Path,
+    converter: &VcdConverter,
+    lenient: bool,
+    report: &mut RunReport,
+) -> Result<diff::DiffSide> {
     if !path.exists() {
         bail!("Input file does not exist: {}", path.display());
     }
-    if path.extension().and_then(|s| s.to_str()) != Some("bin") {
-        bail!("Input must be a .bin file");
+    if !has_extension(path, "cue") {
+        status!("[*] Reading VCD: {}", path.display());
+        return diff::DiffSide::vcd(path);
+    }
+
+    status!("[*] Parsing CUE file: {}", path.display());
+    let mut cue_sheet = load_cue(path, lenient, report)?;
+    resolve_files(&mut cue_sheet, path, false, report)?;
+    cue_sheet.validate_mode2(true)?;
+    diff::DiffSide::cue(path, &cue_sheet, converter)
+}
+
+/// Rip-audio mode: extract CDDA tracks from a CUE/BIN or VCD
+fn run_rip_audio_mode(input: PathBuf, rip_opts: RipArgs, report: &mut RunReport) -> Result<()> {
+    let RipArgs {
+        output,
+        audio_format: format,
+        pregap,
+        db,
+        overwrite,
+        lenient,
+    } = rip_opts;
+    if !input.exists() {
+        bail!("Input file does not exist: {}", input.display());
+    }
+    report.input = Some(input.clone());
+
+    let db = db.as_deref().map(TitleDb::load).transpose()?;
+    let ext = input
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let (layout, game_id) = if ext == "vcd" {
+        status!("[*] Reading VCD header: {}", input.display());
+        let header = VcdHeader::read(&input)?;
+        report.set_header(&header);
+        let game_id = utils::detect_game_id_match_at(&input, vcd::VCD_HEADER_SIZE as u64)?;
+        (
+            RipLayout::from_vcd(&input, &header)?,
+            game_id.map(|m| (m, input.clone())),
+        )
+    } else {
+        let cue = match ext.as_str() {
+            "cue" => input.clone(),
+            "bin" => {
+                let cue = input.with_extension("cue");
+                if !cue.exists() {
+                    bail!(
+                        "No CUE found for {} (expected {})",
+                        input.display(),
+                        cue.display()
+                    );
+                }
+                cue
+            }
+            _ => bail!("Input must be a .cue, .bin or .vcd file"),
+        };
+
+        status!("[*] Parsing CUE file: {}", cue.display());
+        let mut cue_sheet = load_cue(&cue, lenient, report)?;
+        let cue_dir = cue
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
+        resolve_files(&mut cue_sheet, &cue, false, report)?;
+
+        let first_bin = cue_dir.join(&cue_sheet.files[0].filename);
+        let game_id = utils::detect_game_id_match_at(&first_bin, 0)?;
+        let layout = RipLayout::from_cue(&cue_sheet, cue_dir);
+        report.cue_sheet = Some(cue_sheet);
+        (layout, game_id.map(|m| (m, first_bin)))
+    };
+
+    let game_id = game_id.map(|(found, source)| {
+        status!("[+] Game ID: {} ({})", found.id, get_region(&found.id));
+        report.game_id = Some(game_id_report(&found, &source));
+        found.id
+    });
+
+    let audio_tracks: Vec<usize> = (0..layout.tracks.len())
+        .filter(|&idx| layout.tracks[idx].is_audio)
+        .collect();
+    if audio_tracks.is_empty() {
+        status!("[i] No audio tracks found\n");
+        return Ok(());
     }
+    status!("[+] Found {} audio track(s)", audio_tracks.len());
+
+    let output_dir = output.unwrap_or_else(|| {
+        let stem = input
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        input
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("psx-vcd-output")
+            .join(format!("{}_audio", naming::clean_title(&stem)))
+    });
+    std::fs::create_dir_all(&output_dir)?;
+    status!("[*] Output directory: {}\n", output_dir.display());
+
+    // Every name is checked before the first track is written
+    let mut outputs = Vec::with_capacity(audio_tracks.len());
+    for idx in audio_tracks {
+        let number = layout.tracks[idx].number;
+        let filename = rip::track_filename(number, game_id.as_deref(), db.as_ref(), format);
+        let replace = check_overwrite(&output_dir.join(&filename), overwrite)?;
+        outputs.push((idx, filename, replace));
+    }
+
+    for (idx, filename, replace) in outputs {
+        let track = &layout.tracks[idx];
+        status!(
+            "  Track {:02}: {} -> {}",
+            track.number,
+            rip::duration(layout.track_length(idx, pregap))
+                .map_or_else(|| "over 99:59:74".to_string(), |msf| msf.to_string()),
+            filename
+        );
+        let path = output_dir.join(&filename);
+        layout.write_track(idx, pregap, format, &path, replace)?;
+        report.add_output(format.extension(), &path)?;
+    }
+
+    status!("\n[+] Audio tracks extracted to {}\n", output_dir.display());
     Ok(())
 }
 
-fn validate_gap_flags(gap_plus: bool, gap_minus: bool) -> Result<()> {
-    if gap_plus && gap_minus {
-        bail!("Cannot use both --gap-plus and --gap-minus");
+/// VMC mode: memory card conversion for POPSTARTER
+fn run_vmc_mode(input: PathBuf, vmc_opts: VmcArgs, report: &mut RunReport) -> Result<()> {
+    let VmcArgs {
+        cue,
+        vcd,
+        slot,
+        output,
+        to_raw,
+        overwrite,
+        lenient,
+        names,
+    } = vmc_opts;
+    if !input.exists() {
+        bail!("Input file does not exist: {}", input.display());
     }
+    report.input = Some(input.clone());
+
+    status!("[*] Reading memory card: {}", input.display());
+    let card = MemoryCard::load(&input)?;
+    status!(
+        "[+] {} memory card, {} save(s)",
+        card.source_format,
+        card.save_count()
+    );
+
+    if to_raw {
+        let output_dir = output.unwrap_or_else(|| {
+            input
+                .parent()
+                .unwrap_or(std::path::Path::new("."))
+                .to_path_buf()
+        });
+        std::fs::create_dir_all(&output_dir)?;
+
+        let stem = input
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid input filename"))?;
+        let raw_path = output_dir.join(format!("{}.mcr", stem));
+
+        write_card(&card, &raw_path, overwrite)?;
+        report.add_output("mcr", &raw_path)?;
+        status!("\n[+] Raw memory card created: {}\n", raw_path.display());
+        return Ok(());
+    }
+
+    // The VMC lives in POPS/<VCD name without extension>/SLOTn.VMC
+    let (pops_dir, vcd_stem) = if let Some(vcd) = vcd {
+        let stem = vcd
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid VCD filename"))?
+            .to_string();
+        let dir = output.unwrap_or_else(|| {
+            vcd.parent()
+                .unwrap_or(std::path::Path::new("."))
+                .to_path_buf()
+        });
+        (dir, stem)
+    } else if let Some(cue) = cue {
+        validate_cue_input(&cue)?;
+        status!("[*] Parsing CUE file: {}", cue.display());
+        let mut cue_sheet = load_cue(&cue, lenient, report)?;
+        resolve_files(&mut cue_sheet, &cue, false, report)?;
+        let cue_dir = cue
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
+
+        let first_bin = cue_dir.join(&cue_sheet.files[0].filename);
+        let game_id = detect_and_print_game_id(&first_bin, report)?;
+
+        let vcd_name = names.vcd_filename(game_id.as_deref(), &cue)?;
+
+        let dir = output.unwrap_or_else(|| cue_dir.join("psx-vcd-output"));
+        (dir, vcd_name.trim_end_matches(".VCD").to_string())
+    } else {
+        bail!("Specify the game with --cue or --vcd (or use --to-raw)");
+    };
+
+    let game_dir = pops_dir.join(&vcd_stem);
+    std::fs::create_dir_all(&game_dir)?;
+    let vmc_path = game_dir.join(format!("SLOT{}.VMC", slot));
+
+    write_card(&card, &vmc_path, overwrite)?;
+    report.add_output("vmc", &vmc_path)?;
+
+    status!("\n[+] VMC created successfully!");
+    status!("    Output: {}", vmc_path.display());
+    status!("\n[i] Copy the {} folder to your POPS folder\n", vcd_stem);
+
     Ok(())
 }
 
-fn print_success(output: &PathBuf, gap_plus: bool, gap_minus: bool) -> Result<()> {
-    let final_size = std::fs::metadata(output)?.len();
-    println!("\n[+] Conversion completed successfully!");
-    println!("    Output: {}", output.display());
-    println!("    Size: {:.2} MB", final_size as f64 / (1024.0 * 1024.0));
+/// Save a memory card without truncating an existing one in place
+///
+/// The card is already in memory, so its output may be the input file.
+fn write_card(card: &MemoryCard, path: &Path, overwrite: Overwrite) -> Result<()> {
+    let replace = check_overwrite(path, overwrite)?;
+    let partial = PartialFile::new(path);
+    card.write_raw(partial.path())?;
+    partial.persist(replace)?;
+    Ok(())
+}
+
+// Helper functions
 
-    if gap_plus {
-        println!("    Applied: gap++ (+2 seconds adjustment)");
-    } else if gap_minus {
-        println!("    Applied: gap-- (-2 seconds adjustment)");
+/// Use the CUE next to a BIN, or guess one by analyzing the BIN
+///
+/// Returns the path standing in for the CUE (used for names and sidecar
+/// files) together with the sheet. Analyzing reads the whole image, so a
+/// dry run without a CUE is refused.
+fn load_bare_bin(
+    bin_path: PathBuf,
+    lenient: bool,
+    dry_run: bool,
+    report: &mut RunReport,
+) -> Result<(PathBuf, CueSheet)> {
+    if !bin_path.exists() {
+        bail!("Input file does not exist: {}", bin_path.display());
     }
 
-    println!("\n[i] Ready for POPSTARTER/OPL!");
-    println!(
-        "    Copy {} to your POPS folder\n",
-        output.file_name().unwrap().to_string_lossy()
+    let cue_path = bin_path.with_extension("cue");
+    if cue_path.exists() {
+        status!("[*] Parsing CUE file: {}", cue_path.display());
+        let cue_sheet = load_cue(&cue_path, lenient, report)?;
+        return Ok((cue_path, cue_sheet));
+    }
+
+    if dry_run {
+        bail!(
+            "No CUE found for {}: a dry run does not read the image to guess one. \
+             Run `psx-vcd make-cue {}` first",
+            bin_path.display(),
+            bin_path.display()
+        );
+    }
+
+    status!("[*] No CUE found, analyzing BIN: {}", bin_path.display());
+    let scan = scan::scan_bin(&bin_path)?;
+    print_scan_report(&scan, report);
+    Ok((bin_path, scan.cue_sheet))
+}
+
+fn print_scan_report(scan: &scan::ScanReport, report: &mut RunReport) {
+    let tracks = &scan.cue_sheet.files[0].tracks;
+    status!(
+        "[+] {} data and {} audio sector(s), {} track(s)",
+        scan.data_sectors,
+        scan.audio_sectors,
+        tracks.len()
     );
+    for track in tracks {
+        match track.index00 {
+            Some(idx00) => status!(
+                "    TRACK {:02} {:<10} INDEX 00 {}  INDEX 01 {}",
+                track.number,
+                track.track_type.to_string(),
+                idx00.to_relative_msf(),
+                track.index01.to_relative_msf()
+            ),
+            None => status!(
+                "    TRACK {:02} {:<10} INDEX 01 {}",
+                track.number,
+                track.track_type.to_string(),
+                track.index01.to_relative_msf()
+            ),
+        }
+    }
+
+    status!("[i] Confidence: {}", scan.confidence);
+    report.detail("scan_confidence", scan.confidence.to_string());
+    for note in &scan.notes {
+        report.warn(note.as_str());
+    }
+}
+
+/// Parse a CUE sheet, printing any warnings found along the way
+fn load_cue(path: &Path, lenient: bool, report: &mut RunReport) -> Result<CueSheet> {
+    let (cue_sheet, warnings) = CueSheet::parse_with_diagnostics(path, lenient)?;
+    for warning in &warnings {
+        report.warn(warning.to_string());
+    }
+    Ok(cue_sheet)
+}
+
+/// Load file sizes, reporting FILE entries matched to differently named files
+fn resolve_files(
+    cue_sheet: &mut CueSheet,
+    cue_path: &Path,
+    fix_cue: bool,
+    report: &mut RunReport,
+) -> Result<()> {
+    let cue_dir = cue_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
+    let substitutions = cue_sheet.load_file_sizes(cue_dir)?;
 
+    for substitution in &substitutions {
+        report.warn(format!("FILE {}", substitution));
+    }
+    if fix_cue && !substitutions.is_empty() {
+        let backup = resolve::rewrite_cue(cue_path, &substitutions)?;
+        status!(
+            "[+] CUE rewritten with resolved names (original saved as {})",
+            backup.display()
+        );
+    } else if !substitutions.is_empty() {
+        status!("[i] Use --fix-cue to write the resolved names back to the CUE");
+    }
     Ok(())
 }
 
-fn clean_game_name(name: &str) -> String {
-    let mut clean = name.to_string();
-
-    let patterns_to_remove = [
-        r"\(USA\)",
-        r"\(Europe\)",
-        r"\(Japan\)",
-        r"\(World\)",
-        r"\(En,Fr,De,Es,It\)",
-        r"\(En\)",
-        r"\(Fr\)",
-        r"\(De\)",
-        r"\(Es\)",
-        r"\(It\)",
-        r"\(Ja\)",
-        r"\(Disc \d+\)",
-        r"\(Disc [A-Z]\)",
-        r"\(CD \d+\)",
-        r"\(CD [A-Z]\)",
-        r"\(Rev \d+\)",
-        r"\(v\d+\.\d+\)",
-        r"\[!\]",
-        r"\[b\]",
-        r"\[a\]",
-        r"\[h\d*\]",
-        r"\[f\d*\]",
-        r"\[t\d*\]",
-        r"\[o\d*\]",
-        r"\[T[+-].*?\]",
-        r"\(Track \d+\)",
-        r"\(Demo\)",
-        r"\(Beta\)",
-        r"\(Proto\)",
-        r"\(Sample\)",
-        r"\(Promo\)",
-        r"\(Unl\)",
-        r"\[SLUS[-_]\d+\.\d+\]",
-        r"\[SLES[-_]\d+\.\d+\]",
-        r"\[SCUS[-_]\d+\.\d+\]",
-        r"\[SCES[-_]\d+\.\d+\]",
-    ];
+fn detect_and_print_game_id(bin_path: &Path, report: &mut RunReport) -> Result<Option<String>> {
+    status!("\n[*] Detecting Game ID...");
+    let Some(found) = utils::detect_game_id_match_at(bin_path, 0)? else {
+        report.warn("Game ID not found (non-standard or corrupted)");
+        return Ok(None);
+    };
+
+    status!("[+] Game ID: {} ({})", found.id, get_region(&found.id));
+    report.game_id = Some(game_id_report(&found, bin_path));
+    Ok(Some(found.id))
+}
 
-    for pattern in &patterns_to_remove {
-        if let Ok(re) = regex::Regex::new(pattern) {
-            clean = re.replace_all(&clean, "").to_string();
+fn game_id_report(found: &utils::GameIdMatch, source: &Path) -> GameIdReport {
+    GameIdReport {
+        id: found.id.clone(),
+        region: get_region(&found.id).to_string(),
+        method: found.method.to_string(),
+        source: source.to_path_buf(),
+    }
+}
+
+fn load_patches(paths: &[PathBuf]) -> Result<Vec<Patch>> {
+    let mut patches = Vec::new();
+    for path in paths {
+        let patch = Patch::load(path)?;
+        status!("[+] Patch: {} ({})", patch.name(), patch.format);
+        patches.push(patch);
+    }
+    Ok(patches)
+}
+
+/// Report LibCrypt protection and offer to apply a local PPF crack
+fn check_libcrypt(
+    game_id: Option<&str>,
+    cue_path: &Path,
+    patches: &mut Vec<Patch>,
+    interactive: bool,
+    report: &mut RunReport,
+) -> Result<()> {
+    let info = libcrypt::detect(game_id, cue_path);
+    for warning in &info.warnings {
+        report.warn(warning.as_str());
+    }
+    if !info.is_protected() {
+        return Ok(());
+    }
+
+    report.warn(format!(
+        "LibCrypt protection detected ({})",
+        info.describe()
+    ));
+
+    let Some(crack) = info.crack else {
+        if patches.is_empty() {
+            status!("    POPS needs a patched executable: supply a crack with --patch <FILE.ppf>");
         }
+        return Ok(());
+    };
+
+    let crack_name = crack.file_name().unwrap().to_string_lossy().to_string();
+    if patches.iter().any(|p| p.name() == crack_name) {
+        return Ok(());
+    }
+
+    status!("    Found PPF crack: {}", crack_name);
+    // The prompt would end up in the JSON document on stdout
+    if !interactive || !std::io::stdin().is_terminal() || report::machine_output() {
+        status!(
+            "[i] Re-run with --patch \"{}\" to apply it",
+            crack.display()
+        );
+        return Ok(());
+    }
+
+    print!("    Apply it? [y/N] ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        let patch = Patch::load(&crack)?;
+        status!("[+] Patch: {} ({})", patch.name(), patch.format);
+        patches.push(patch);
+    }
+
+    Ok(())
+}
+
+/// Check the output filesystem before writing anything
+///
+/// `outputs` are the files to write with their sizes; `scratch` is the size
+/// of intermediate files that exist alongside them. Missing space and files
+/// too large for FAT32 are errors, awkward filenames are warnings.
+fn preflight(
+    output_dir: &Path,
+    outputs: &[(&Path, u64)],
+    scratch: u64,
+    report: &mut RunReport,
+) -> Result<()> {
+    let volume = preflight::OutputVolume::probe(output_dir);
+    if let (Some(fs_type), Some(available)) = (&volume.fs_type, volume.available) {
+        status!(
+            "[*] Output filesystem: {} ({:.2} MB free)",
+            fs_type,
+            available as f64 / (1024.0 * 1024.0)
+        );
+    }
+
+    for (path, size) in outputs {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        for warning in preflight::filename_warnings(&name) {
+            report.warn(warning);
+        }
+        volume.check_file_size(path, *size)?;
+    }
+
+    let needed = outputs.iter().map(|(_, size)| size).sum::<u64>() + scratch;
+    volume.check_space(output_dir, needed)?;
+    Ok(())
+}
+
+/// Decide up front whether an existing output file may be replaced
+///
+/// Returns `true` when `target` exists and the policy allows replacing it.
+fn check_overwrite(target: &Path, overwrite: Overwrite) -> Result<bool> {
+    if !target.exists() {
+        return Ok(false);
+    }
+
+    let refuse = || {
+        anyhow::anyhow!(
+            "{} already exists; pass --overwrite always to replace it, or --overwrite ask to be asked",
+            target.display()
+        )
+    };
+    match overwrite {
+        Overwrite::Always => Ok(true),
+        Overwrite::Never => Err(refuse()),
+        // The prompt would end up in the JSON document on stdout
+        Overwrite::Ask if !std::io::stdin().is_terminal() || report::machine_output() => {
+            Err(refuse())
+        }
+        Overwrite::Ask => {
+            print!(
+                "[?] {} already exists. Replace it? [y/N] ",
+                target.display()
+            );
+            std::io::Write::flush(&mut std::io::stdout())?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+
+            if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                Ok(true)
+            } else {
+                Err(refuse())
+            }
+        }
+    }
+}
+
+/// Report CUE track types that disagree with the sectors, before any output
+fn check_track_types(cue_sheet: &CueSheet, cue_path: &Path, report: &mut RunReport) -> Result<()> {
+    let cue_dir = cue_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine CUE directory"))?;
+    let issues = verify::check_track_types(cue_sheet, cue_dir)?;
+
+    for issue in &issues {
+        report.warn(issue.to_string());
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == cue::Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(error::Error::InvalidCue(format!(
+            "{} track type(s) do not match the sector contents",
+            errors
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+fn print_verify_report(verify_report: &verify::VerifyReport, report: &mut RunReport) {
+    const MAX_LISTED: usize = 50;
+
+    for track in &verify_report.skipped_tracks {
+        report.warn(format!(
+            "Track {:02}: not a 2352-byte data track, skipped",
+            track
+        ));
+    }
+
+    report.detail("sectors_checked", verify_report.sectors_checked);
+    report.detail(
+        "corrupt_sectors",
+        verify_report
+            .corrupt
+            .iter()
+            .map(|sector| {
+                serde_json::json!({
+                    "lba": sector.lba.get(),
+                    "msf": sector.msf(),
+                    "track": sector.track,
+                    "error": sector.error.to_string(),
+                })
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    status!(
+        "\n[*] Checked {} data sector(s)",
+        verify_report.sectors_checked
+    );
+    if verify_report.is_clean() {
+        status!("[+] All data sectors verified OK\n");
+        return;
     }
 
-    clean = clean.split_whitespace().collect::<Vec<_>>().join(" ");
-    clean = clean.trim().to_string();
+    status!("[!] {} corrupt sector(s):", verify_report.corrupt.len());
+    for sector in verify_report.corrupt.iter().take(MAX_LISTED) {
+        status!(
+            "    LBA {:>6} (MSF {}) Track {:02}: {}",
+            sector.lba,
+            sector.msf(),
+            sector.track,
+            sector.error
+        );
+    }
+    if verify_report.corrupt.len() > MAX_LISTED {
+        status!(
+            "    ... and {} more",
+            verify_report.corrupt.len() - MAX_LISTED
+        );
+    }
 
-    if clean.is_empty() {
-        clean = "game".to_string();
+    let mut tracks: Vec<u8> = verify_report.corrupt.iter().map(|c| c.track).collect();
+    tracks.dedup();
+    for track in tracks {
+        status!(
+            "    Track {:02}: {} corrupt sector(s)",
+            track,
+            verify_report.corrupt_in_track(track)
+        );
     }
+    status!();
+}
+
+fn get_region(game_id: &str) -> &'static str {
+    naming::region(game_id).unwrap_or("Unknown")
+}
 
-    clean
+fn file_stem(path: &Path) -> Result<&str> {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid input filename"))
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().eq_ignore_ascii_case(ext))
+        .unwrap_or(false)
+}
+
+fn validate_cue_input(path: &Path) -> Result<()> {
+    if !path.exists() {
+        bail!("Input file does not exist: {}", path.display());
+    }
+    if path.extension().and_then(|s| s.to_str()) != Some("cue") {
+        bail!("Input must be a .cue file");
+    }
+    Ok(())
+}
+
+fn validate_bin_input(path: &Path) -> Result<()> {
+    if !path.exists() {
+        bail!("Input file does not exist: {}", path.display());
+    }
+    if !is_image(path) {
+        bail!("Input must be a .bin or .img file");
+    }
+    Ok(())
+}
+
+/// Raw disc image extensions
+fn is_image(path: &Path) -> bool {
+    has_extension(path, "bin") || has_extension(path, "img")
+}
+
+/// Directory holding `path`, resolved so "game.bin" and "./game.bin" agree
+fn canonical_parent(path: &Path) -> Result<PathBuf> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    dir.canonicalize()
+        .with_context(|| format!("Cannot access directory: {}", dir.display()))
+}
+
+/// Dry run: build the header a conversion would write and show it
+///
+/// Only the header is computed; with `--dump-header` it is saved as-is so it
+/// can be compared with the first megabyte of an existing VCD.
+fn print_plan(
+    output: &Path,
+    bin_size: u64,
+    cue_sheet: &CueSheet,
+    vcd_opts: &VcdArgs,
+    report: &mut RunReport,
+) -> Result<()> {
+    let converter = VcdConverter::new(vcd_opts.gap_plus, vcd_opts.gap_minus);
+    let header = converter.create_vcd_header(bin_size, cue_sheet)?;
+    report.set_header(&VcdHeader::parse(&header)?);
+
+    let vcd_size = vcd::VCD_HEADER_SIZE as u64 + bin_size;
+    let sectors = bin_size / SECTOR_SIZE as u64;
+    report.detail(
+        "planned_output",
+        serde_json::json!({
+            "path": output,
+            "bytes": vcd_size,
+            "sectors": sectors,
+        }),
+    );
+
+    status!("[+] Dry run: nothing was written");
+    status!("    Output: {}", output.display());
+    status!(
+        "    Size: {:.2} MB ({} sectors)",
+        vcd_size as f64 / (1024.0 * 1024.0),
+        sectors
+    );
+    if vcd_opts.gap_plus {
+        status!("    Applied: gap++ (+2 seconds adjustment)");
+    } else if vcd_opts.gap_minus {
+        status!("    Applied: gap-- (-2 seconds adjustment)");
+    }
+
+    if let Some(path) = &vcd_opts.dump_header {
+        std::fs::write(path, &header)
+            .with_context(|| format!("Failed to write: {}", path.display()))?;
+        report.add_output("header", path)?;
+        status!("    Header: {}", path.display());
+    }
+    status!();
+    Ok(())
+}
+
+fn print_success(output: &PathBuf, vcd_opts: &VcdArgs, report: &mut RunReport) -> Result<()> {
+    report.add_output("vcd", output)?;
+    report.set_header(&VcdHeader::read(output)?);
+
+    let final_size = std::fs::metadata(output)?.len();
+    status!("\n[+] Conversion completed successfully!");
+    status!("    Output: {}", output.display());
+    status!("    Size: {:.2} MB", final_size as f64 / (1024.0 * 1024.0));
+
+    if vcd_opts.gap_plus {
+        status!("    Applied: gap++ (+2 seconds adjustment)");
+    } else if vcd_opts.gap_minus {
+        status!("    Applied: gap-- (-2 seconds adjustment)");
+    }
+
+    status!("\n[i] Ready for POPSTARTER/OPL!");
+    status!(
+        "    Copy {} to your POPS folder\n",
+        output.file_name().unwrap().to_string_lossy()
+    );
+
+    Ok(())
 }
@@ -0,0 +1,573 @@
+// src/naming.rs
+//! VCD filenames from templates
+//!
+//! A template such as `{id}.{title}` (the default) is filled in from the
+//! detected serial, the CUE filename and an optional title database.
+//! Placeholders that have no value are left empty, together with the
+//! brackets around them and the separators that would lead nowhere, so
+//! `{id}.{title}` gives `Title.VCD` for a disc without a serial. Other
+//! literal text is kept. By default titles keep their characters and
+//! length, so the default template gives the names earlier versions did.
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Template used when none is given
+pub const DEFAULT_TEMPLATE: &str = "{id}.{title}";
+
+/// Extension added to every rendered name
+pub const VCD_EXTENSION: &str = ".VCD";
+
+/// Dump tags removed from titles: regions, languages, disc numbers,
+/// revisions, GoodTools flags and bracketed serials
+const DEFAULT_STRIP_PATTERNS: &[&str] = &[
+    r"\(USA\)",
+    r"\(Europe\)",
+    r"\(Japan\)",
+    r"\(World\)",
+    r"\(En,Fr,De,Es,It\)",
+    r"\(En\)",
+    r"\(Fr\)",
+    r"\(De\)",
+    r"\(Es\)",
+    r"\(It\)",
+    r"\(Ja\)",
+    r"\(Disc \d+\)",
+    r"\(Disc [A-Z]\)",
+    r"\(CD \d+\)",
+    r"\(CD [A-Z]\)",
+    r"\(Rev \d+\)",
+    r"\(v\d+\.\d+\)",
+    r"\[!\]",
+    r"\[b\]",
+    r"\[a\]",
+    r"\[h\d*\]",
+    r"\[f\d*\]",
+    r"\[t\d*\]",
+    r"\[o\d*\]",
+    r"\[T[+-].*?\]",
+    r"\(Track \d+\)",
+    r"\(Demo\)",
+    r"\(Beta\)",
+    r"\(Proto\)",
+    r"\(Sample\)",
+    r"\(Promo\)",
+    r"\(Unl\)",
+    r"\[SLUS[-_]\d+\.\d+\]",
+    r"\[SLES[-_]\d+\.\d+\]",
+    r"\[SCUS[-_]\d+\.\d+\]",
+    r"\[SCES[-_]\d+\.\d+\]",
+];
+
+/// Characters FAT32/exFAT reject, replaced in field values on request
+const RESERVED: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+fn default_strip() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        DEFAULT_STRIP_PATTERNS
+            .iter()
+            .map(|pattern| Regex::new(pattern).expect("valid default strip pattern"))
+            .collect()
+    })
+}
+
+/// Game name from a CUE or BIN filename, without dump tags
+///
+/// Returns `game` when nothing is left.
+pub fn clean_title(name: &str) -> String {
+    let clean = strip(name, default_strip());
+    if clean.is_empty() {
+        "game".to_string()
+    } else {
+        clean
+    }
+}
+
+/// Region of a serial, from its prefix
+pub fn region(game_id: &str) -> Option<&'static str> {
+    match game_id.get(..4)? {
+        "SLUS" | "SCUS" => Some("USA"),
+        "SLES" | "SCES" => Some("Europe"),
+        "SLPS" | "SCPS" | "SLPM" => Some("Japan"),
+        "SCED" | "SLED" => Some("Europe (Demo)"),
+        _ => None,
+    }
+}
+
+/// Disc number or letter from a `(Disc 2)` / `(CD B)` tag in a filename
+pub fn disc_number(name: &str) -> Option<String> {
+    static DISC: OnceLock<Regex> = OnceLock::new();
+    let disc = DISC.get_or_init(|| {
+        Regex::new(r"(?i)\((?:disc|cd)\s*([0-9]+|[a-z])(?:\s+of\s+[0-9]+)?\)")
+            .expect("valid disc pattern")
+    });
+    disc.captures(name).map(|caps| caps[1].to_uppercase())
+}
+
+/// Everything a name can be built from
+#[derive(Debug, Clone, Default)]
+pub struct NameFields {
+    /// Detected serial, e.g. `SLUS_005.94`
+    pub id: Option<String>,
+    /// CUE (or BIN) filename without its extension
+    pub cue_stem: String,
+    /// Title from the title database
+    pub db_title: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Title,
+    Region,
+    Disc,
+    CueStem,
+    DbTitle,
+}
+
+impl Field {
+    const ALL: [(&'static str, Field); 6] = [
+        ("id", Field::Id),
+        ("title", Field::Title),
+        ("region", Field::Region),
+        ("disc", Field::Disc),
+        ("cue_stem", Field::CueStem),
+        ("db_title", Field::DbTitle),
+    ];
+
+    /// Free text that may be shortened to meet the length limit
+    fn is_truncatable(self) -> bool {
+        matches!(self, Field::Title | Field::CueStem | Field::DbTitle)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// Parsed name template with its cleanup options
+///
+/// Placeholders: `{id}`, `{title}` (database title, else the cleaned CUE
+/// name), `{region}`, `{disc}`, `{cue_stem}` and `{db_title}`. The
+/// `.VCD` extension is added to the result.
+#[derive(Debug, Clone)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+    strip: Vec<Regex>,
+    ascii: bool,
+    safe_chars: bool,
+    max_len: Option<usize>,
+}
+
+impl Default for NameTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).expect("valid default template")
+    }
+}
+
+impl NameTemplate {
+    /// Parse a template, rejecting unknown or unclosed placeholders
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .with_context(|| format!("Unclosed placeholder in name template: {}", template))?;
+            let name = &rest[open + 1..open + close];
+            let field = Field::ALL
+                .iter()
+                .find(|(known, _)| *known == name)
+                .map(|(_, field)| *field)
+                .with_context(|| {
+                    format!(
+                        "Unknown placeholder {{{}}} in name template (use {})",
+                        name,
+                        Field::ALL
+                            .iter()
+                            .map(|(known, _)| format!("{{{}}}", known))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                })?;
+            segments.push(Segment::Field(field));
+            rest = &rest[open + close + 1..];
+        }
+        if rest.contains('}') {
+            bail!("Unmatched '}}' in name template: {}", template);
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        if !segments.iter().any(|s| matches!(s, Segment::Field(_))) {
+            bail!(
+                "Name template has no placeholder, every VCD would get the same name: {}",
+                template
+            );
+        }
+
+        Ok(Self {
+            segments,
+            strip: Vec::new(),
+            ascii: false,
+            safe_chars: false,
+            max_len: None,
+        })
+    }
+
+    /// Also remove these regular expressions from titles
+    pub fn with_strip_patterns(mut self, patterns: &[String]) -> Result<Self> {
+        for pattern in patterns {
+            let re = Regex::new(pattern)
+                .with_context(|| format!("Invalid strip pattern: {}", pattern))?;
+            self.strip.push(re);
+        }
+        Ok(self)
+    }
+
+    /// Transliterate titles to ASCII (accents dropped, kana romanized)
+    pub fn with_ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
+    }
+
+    /// Replace the characters FAT32/exFAT reject with spaces, and `: `
+    /// with ` - `
+    pub fn with_safe_chars(mut self, safe_chars: bool) -> Self {
+        self.safe_chars = safe_chars;
+        self
+    }
+
+    /// Longest filename, extension included
+    ///
+    /// Longer names are cut down by shortening the titles; the serial,
+    /// region, disc number and literal text are kept. Names are not
+    /// shortened unless a limit is set.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Render the VCD filename, extension included
+    pub fn filename(&self, fields: &NameFields) -> Result<String> {
+        let mut values = self.values(fields);
+
+        loop {
+            let name = self.render(&values);
+            let length = name.chars().count();
+            if name.is_empty() {
+                bail!("Name template gives an empty name for this disc");
+            }
+            let Some(max_len) = self.max_len else {
+                return Ok(format!("{}{}", name, VCD_EXTENSION));
+            };
+            let limit = max_len.saturating_sub(VCD_EXTENSION.len());
+            if length <= limit {
+                return Ok(format!("{}{}", name, VCD_EXTENSION));
+            }
+
+            // Shorten the longest title until the name fits
+            let Some((_, value)) = values
+                .iter_mut()
+                .filter(|(field, value)| field.is_truncatable() && !value.is_empty())
+                .max_by_key(|(_, value)| value.chars().count())
+            else {
+                bail!(
+                    "Name \"{}\" is {} characters long; the limit of {} cannot be met without cutting the serial",
+                    name,
+                    length + VCD_EXTENSION.len(),
+                    max_len
+                );
+            };
+            let keep = value.chars().count().saturating_sub(length - limit);
+            *value = shorten(value, keep);
+        }
+    }
+
+    /// Cleaned value of every placeholder used in the template
+    fn values(&self, fields: &NameFields) -> Vec<(Field, String)> {
+        let id = fields.id.clone().unwrap_or_default();
+        let db_title = fields
+            .db_title
+            .as_deref()
+            .map(|title| self.title(title))
+            .unwrap_or_default();
+
+        let mut values: Vec<(Field, String)> = Vec::new();
+        for segment in &self.segments {
+            let Segment::Field(field) = *segment else {
+                continue;
+            };
+            if values.iter().any(|(known, _)| *known == field) {
+                continue;
+            }
+            let value = match field {
+                Field::Id => id.clone(),
+                Field::Title if !db_title.is_empty() => db_title.clone(),
+                Field::Title => match self.title(&fields.cue_stem) {
+                    title if title.is_empty() => "game".to_string(),
+                    title => title,
+                },
+                Field::Region => region(&id).unwrap_or_default().to_string(),
+                Field::Disc => disc_number(&fields.cue_stem).unwrap_or_default(),
+                Field::CueStem => self.text(&fields.cue_stem),
+                Field::DbTitle => db_title.clone(),
+            };
+            values.push((field, value));
+        }
+        values
+    }
+
+    /// Title without dump tags or user patterns
+    fn title(&self, name: &str) -> String {
+        let stripped = strip(name, default_strip());
+        self.text(&strip(&stripped, &self.strip))
+    }
+
+    /// Field value as it goes into the filename
+    ///
+    /// Path separators are always replaced, since they would put the VCD
+    /// in another directory.
+    fn text(&self, value: &str) -> String {
+        let value = if self.ascii {
+            any_ascii::any_ascii(value)
+        } else {
+            value.to_string()
+        };
+        let value = if self.safe_chars {
+            value.replace(": ", " - ")
+        } else {
+            value
+        };
+        let value: String = value
+            .chars()
+            .map(|c| {
+                let reserved = RESERVED.contains(&c) || c.is_control();
+                if std::path::is_separator(c) || (self.safe_chars && reserved) {
+                    ' '
+                } else {
+                    c
+                }
+            })
+            .collect();
+        value.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Fill in the template, dropping what surrounds empty fields
+    ///
+    /// Brackets around an empty field go, and so do the separators between
+    /// it and the text before it (or after it, when nothing comes before).
+    /// Names without empty fields are the plain concatenation.
+    fn render(&self, values: &[(Field, String)]) -> String {
+        const SEPARATORS: &[char] = &[' ', '.', '-', '_'];
+
+        // Field `i` sits between `literals[i]` and `literals[i + 1]`
+        let mut literals = vec![String::new()];
+        let mut fields: Vec<&str> = Vec::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => literals.last_mut().unwrap().push_str(text),
+                Segment::Field(field) => {
+                    let value = values.iter().find(|(known, _)| known == field);
+                    fields.push(value.map(|(_, v)| v.as_str()).unwrap_or_default());
+                    literals.push(String::new());
+                }
+            }
+        }
+
+        for i in 0..fields.len() {
+            if !fields[i].is_empty() {
+                continue;
+            }
+            let (before, after) = literals.split_at_mut(i + 1);
+            let (prev, next) = (&mut before[i], &mut after[0]);
+            for (open, close) in [('(', ')'), ('[', ']')] {
+                if prev.trim_end().ends_with(open) && next.trim_start().starts_with(close) {
+                    *prev = prev.trim_end().strip_suffix(open).unwrap().to_string();
+                    *next = next.trim_start().strip_prefix(close).unwrap().to_string();
+                    break;
+                }
+            }
+
+            let has_text = |text: &str| text.chars().any(|c| !SEPARATORS.contains(&c));
+            let text_before =
+                fields[..i].iter().any(|v| !v.is_empty()) || before.iter().any(|l| has_text(l));
+            let prev = &mut before[i];
+            *prev = prev.trim_end_matches(SEPARATORS).to_string();
+            if !text_before {
+                *next = next.trim_start_matches(SEPARATORS).to_string();
+            }
+        }
+
+        let mut name = literals[0].clone();
+        for (value, literal) in fields.iter().zip(&literals[1..]) {
+            name.push_str(value);
+            name.push_str(literal);
+        }
+        name
+    }
+}
+
+/// Remove every match of `patterns` and collapse whitespace
+fn strip(name: &str, patterns: &[Regex]) -> String {
+    let mut clean = name.to_string();
+    for re in patterns {
+        clean = re.replace_all(&clean, "").to_string();
+    }
+    clean.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// First `keep` characters of `text`, cut at a word boundary when that
+/// keeps at least half of them
+fn shorten(text: &str, keep: usize) -> String {
+    let cut: String = text.chars().take(keep).collect();
+    let whole_words = text[cut.len()..].starts_with(' ');
+    let cut = match cut.rfind(' ') {
+        Some(space) if !whole_words && space >= cut.len() / 2 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    cut.trim_end_matches(|c: char| " -.,_;&".contains(c))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(id: Option<&str>, cue_stem: &str, db_title: Option<&str>) -> NameFields {
+        NameFields {
+            id: id.map(str::to_string),
+            cue_stem: cue_stem.to_string(),
+            db_title: db_title.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_default_template_matches_legacy_names() {
+        let template = NameTemplate::default();
+        assert_eq!(
+            template
+                .filename(&fields(
+                    Some("SLUS_005.94"),
+                    "Metal Gear Solid (USA) (Disc 1)",
+                    None
+                ))
+                .unwrap(),
+            "SLUS_005.94.Metal Gear Solid.VCD"
+        );
+        assert_eq!(
+            template
+                .filename(&fields(None, "Homebrew [!]", None))
+                .unwrap(),
+            "Homebrew.VCD"
+        );
+        assert_eq!(clean_title("(USA) [!]"), "game");
+
+        // Long titles and reserved characters stay as they were
+        for stem in [
+            "Final Fantasy Chronicles: Final Fantasy IV & Chrono Trigger - Collector's Edition (USA)",
+            "Game () [Beta 2] v1.",
+            "What?  Really* (Japan)",
+        ] {
+            assert_eq!(
+                template
+                    .filename(&fields(Some("SLUS_012.34"), stem, None))
+                    .unwrap(),
+                format!("SLUS_012.34.{}.VCD", clean_title(stem))
+            );
+            assert_eq!(
+                template.filename(&fields(None, stem, None)).unwrap(),
+                format!("{}.VCD", clean_title(stem))
+            );
+        }
+    }
+
+    #[test]
+    fn test_template_fields() {
+        let template = NameTemplate::parse("{id} {title} [{region}] ({disc})")
+            .unwrap()
+            .with_safe_chars(true);
+        let name = template
+            .filename(&fields(
+                Some("SCES_015.64"),
+                "Ape Escape (Europe) (Disc 2)",
+                Some("Ape Escape: Special Edition"),
+            ))
+            .unwrap();
+        assert_eq!(
+            name,
+            "SCES_015.64 Ape Escape - Special Edition [Europe] (2).VCD"
+        );
+
+        // Missing fields leave no empty brackets or stray separators
+        let name = template.filename(&fields(None, "Demo", None)).unwrap();
+        assert_eq!(name, "Demo.VCD");
+
+        let template = NameTemplate::parse("{cue_stem} - {db_title}").unwrap();
+        let name = template
+            .filename(&fields(None, "Game (USA)", None))
+            .unwrap();
+        assert_eq!(name, "Game (USA).VCD");
+
+        assert!(NameTemplate::parse("{id}.{name}").is_err());
+        assert!(NameTemplate::parse("{id").is_err());
+        assert!(NameTemplate::parse("game").is_err());
+    }
+
+    #[test]
+    fn test_strip_and_ascii() {
+        let template = NameTemplate::default()
+            .with_strip_patterns(&[r"(?i)\s*-\s*Greatest Hits".to_string()])
+            .unwrap()
+            .with_ascii(true);
+        let name = template
+            .filename(&fields(
+                Some("SLPS_012.34"),
+                "Pokémon Straße - Greatest Hits (Japan)",
+                None,
+            ))
+            .unwrap();
+        assert_eq!(name, "SLPS_012.34.Pokemon Strasse.VCD");
+
+        let name = template
+            .filename(&fields(Some("SLPS_000.01"), "ぼくのなつやすみ", None))
+            .unwrap();
+        assert_eq!(name, "SLPS_000.01.bokunonatsuyasumi.VCD");
+
+        assert!(NameTemplate::default()
+            .with_strip_patterns(&["(".to_string()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_truncation_keeps_serial() {
+        let template = NameTemplate::parse("{id}.{title} ({region})")
+            .unwrap()
+            .with_max_len(48);
+        let name = template
+            .filename(&fields(
+                Some("SLUS_012.34"),
+                "The Extraordinarily Long Adventures of Somebody",
+                None,
+            ))
+            .unwrap();
+        assert_eq!(name, "SLUS_012.34.The Extraordinarily Long (USA).VCD");
+        assert!(name.chars().count() <= 48);
+
+        let err = NameTemplate::parse("{id} - {region} - {region}")
+            .unwrap()
+            .with_max_len(20)
+            .filename(&fields(Some("SLUS_012.34"), "Game", None))
+            .unwrap_err();
+        assert!(err.to_string().contains("serial"));
+    }
+}
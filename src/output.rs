@@ -0,0 +1,292 @@
+// src/output.rs
+//! Atomic output files
+//!
+//! Outputs are written to a hidden `.partial` file next to their final
+//! name and renamed into place once complete, so a failed or interrupted
+//! run never leaves a truncated VCD behind. Partial files that were not
+//! persisted are removed when their [`PartialFile`] is dropped, or by
+//! [`remove_partial_files`] from a Ctrl-C handler.
+
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::{Error, Result};
+
+/// Partial files that exist right now
+static PARTIAL_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Output file being written under a temporary name
+///
+/// Write to [`path`](Self::path), then [`persist`](Self::persist) it. The
+/// temporary file is deleted if the guard is dropped first.
+#[derive(Debug)]
+pub struct PartialFile {
+    path: PathBuf,
+    target: PathBuf,
+}
+
+impl PartialFile {
+    /// Reserve a temporary name for `target` in the same directory
+    ///
+    /// Nothing is created yet; a stale partial file from an earlier run
+    /// is removed.
+    pub fn new(target: &Path) -> Self {
+        let name = target
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let path = target.with_file_name(format!(".{}.partial", name));
+        let _ = std::fs::remove_file(&path);
+
+        lock().push(path.clone());
+        Self {
+            path,
+            target: target.to_path_buf(),
+        }
+    }
+
+    /// Where the data is written until it is persisted
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Final name of the file
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    /// Flush the data to disk and rename it to the final name
+    ///
+    /// An existing file of that name is replaced only when `replace` is
+    /// set; otherwise the partial file is removed and an error returned.
+    pub fn persist(self, replace: bool) -> Result<PathBuf> {
+        OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.sync_all())
+            .map_err(|e| Error::io(&self.path, e))?;
+        if replace {
+            std::fs::rename(&self.path, &self.target)
+        } else {
+            rename_no_replace(&self.path, &self.target)
+        }
+        .map_err(|e| Error::io(&self.target, e))?;
+        sync_parent(&self.target);
+
+        Ok(self.target.clone())
+    }
+}
+
+/// Persist several files as one unit
+///
+/// Each file comes with its `replace` flag. If one of them cannot be
+/// persisted, the ones already in place are removed again and the files
+/// they replaced are restored, so either all of them are saved or none.
+pub fn persist_all(files: Vec<(PartialFile, bool)>) -> Result<Vec<PathBuf>> {
+    // Targets in place, with the file each one replaced moved aside
+    let mut persisted: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+    let mut failure = None;
+
+    for (file, replace) in files {
+        let target = file.target.clone();
+        let backup = if replace && target.exists() {
+            let backup = file.path.with_extension("old");
+            if let Err(e) = std::fs::rename(&target, &backup) {
+                failure = Some(Error::io(&target, e));
+                break;
+            }
+            Some(backup)
+        } else {
+            None
+        };
+
+        match file.persist(false) {
+            Ok(_) => persisted.push((target, backup)),
+            Err(err) => {
+                if let Some(backup) = backup {
+                    let _ = std::fs::rename(backup, &target);
+                }
+                failure = Some(err);
+                break;
+            }
+        }
+    }
+
+    match failure {
+        Some(err) => {
+            for (target, backup) in persisted.into_iter().rev() {
+                let _ = std::fs::remove_file(&target);
+                if let Some(backup) = backup {
+                    let _ = std::fs::rename(backup, &target);
+                }
+            }
+            Err(err)
+        }
+        None => Ok(persisted
+            .into_iter()
+            .map(|(target, backup)| {
+                if let Some(backup) = backup {
+                    let _ = std::fs::remove_file(backup);
+                }
+                target
+            })
+            .collect()),
+    }
+}
+
+/// Rename `path` to `target`, failing if `target` exists
+///
+/// The check and the rename happen in one step, so a file created in the
+/// meantime is never replaced.
+fn rename_no_replace(path: &Path, target: &Path) -> std::io::Result<()> {
+    match std::fs::hard_link(path, target) {
+        Ok(()) => return std::fs::remove_file(path),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(e),
+        // No hard links on this filesystem (FAT32, exFAT)
+        Err(_) => {}
+    }
+
+    // Claim the name with an empty file, then rename over it
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(target)?;
+    std::fs::rename(path, target).map_err(|e| {
+        let _ = std::fs::remove_file(target);
+        e
+    })
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        // Gone already when persisted
+        let _ = std::fs::remove_file(&self.path);
+        lock().retain(|path| *path != self.path);
+    }
+}
+
+/// Remove every partial file that has not been persisted
+///
+/// Meant for signal handlers, where the guards' destructors do not run.
+pub fn remove_partial_files() {
+    for path in lock().drain(..) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Vec<PathBuf>> {
+    PARTIAL_FILES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Make a rename durable by syncing the directory that holds it
+fn sync_parent(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_file_persist_and_cleanup() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("GAME.VCD");
+
+        // Dropped before persisting: nothing is left behind
+        let partial = PartialFile::new(&target);
+        std::fs::write(partial.path(), b"truncated").unwrap();
+        let partial_path = partial.path().to_path_buf();
+        drop(partial);
+        assert!(!partial_path.exists());
+        assert!(!target.exists());
+
+        let partial = PartialFile::new(&target);
+        std::fs::write(partial.path(), b"first").unwrap();
+        assert_eq!(partial.persist(false).unwrap(), target);
+        assert_eq!(std::fs::read(&target).unwrap(), b"first");
+
+        // An existing file is kept unless replacing is allowed
+        let partial = PartialFile::new(&target);
+        std::fs::write(partial.path(), b"second").unwrap();
+        let err = partial.persist(false).unwrap_err();
+        assert!(
+            matches!(err, Error::Io { ref source, .. } if source.kind() == ErrorKind::AlreadyExists)
+        );
+        assert_eq!(std::fs::read(&target).unwrap(), b"first");
+        assert!(!partial_path.exists());
+
+        let partial = PartialFile::new(&target);
+        std::fs::write(partial.path(), b"second").unwrap();
+        partial.persist(true).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"second");
+
+        // Unpersisted files are removed on request, as on Ctrl-C
+        let partial = PartialFile::new(&target);
+        std::fs::write(partial.path(), b"interrupted").unwrap();
+        remove_partial_files();
+        assert!(!partial.path().exists());
+        drop(partial);
+
+        let leftovers = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn test_persist_all_is_all_or_nothing() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-pair-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bin = dir.join("game.bin");
+        let cue = dir.join("game.cue");
+        std::fs::write(&bin, b"old bin").unwrap();
+        std::fs::write(&cue, b"old cue").unwrap();
+        let stage = |target: &Path, data: &[u8]| {
+            let partial = PartialFile::new(target);
+            std::fs::write(partial.path(), data).unwrap();
+            partial
+        };
+
+        // The CUE may not be replaced: the BIN that replaced the old one
+        // is taken back
+        let err = persist_all(vec![
+            (stage(&bin, b"new bin"), true),
+            (stage(&cue, b"new cue"), false),
+        ])
+        .unwrap_err();
+        assert!(
+            matches!(err, Error::Io { ref source, .. } if source.kind() == ErrorKind::AlreadyExists)
+        );
+        assert_eq!(std::fs::read(&bin).unwrap(), b"old bin");
+        assert_eq!(std::fs::read(&cue).unwrap(), b"old cue");
+
+        let saved = persist_all(vec![
+            (stage(&bin, b"new bin"), true),
+            (stage(&cue, b"new cue"), true),
+        ])
+        .unwrap();
+        assert_eq!(saved, [bin.clone(), cue.clone()]);
+        assert_eq!(std::fs::read(&bin).unwrap(), b"new bin");
+        assert_eq!(std::fs::read(&cue).unwrap(), b"new cue");
+
+        let leftovers = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(leftovers, 2);
+    }
+}
@@ -0,0 +1,1116 @@
+// src/patch.rs
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::status;
+
+const BUFFER_SIZE: usize = 1024 * 1024; // 1MB buffer
+
+// PPF validation block: 1024 bytes read from a fixed image offset
+const PPF_BLOCK_SIZE: usize = 1024;
+const PPF_BLOCK_OFFSET_BIN: u64 = 0x9320;
+const PPF_BLOCK_OFFSET_GI: u64 = 0x80A0;
+const PPF_DIZ_BEGIN: &[u8] = b"@BEGIN_FILE_ID.DIZ";
+
+// VCDIFF (xdelta3) instruction types
+const VCD_NOOP: u8 = 0;
+const VCD_ADD: u8 = 1;
+const VCD_RUN: u8 = 2;
+const VCD_COPY: u8 = 3;
+
+/// Supported patch formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ppf1,
+    Ppf2,
+    Ppf3,
+    Ips,
+    Bps,
+    Xdelta,
+}
+
+impl PatchFormat {
+    /// Detect the patch format from its magic bytes
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"PPF10") {
+            Some(PatchFormat::Ppf1)
+        } else if data.starts_with(b"PPF20") {
+            Some(PatchFormat::Ppf2)
+        } else if data.starts_with(b"PPF30") {
+            Some(PatchFormat::Ppf3)
+        } else if data.starts_with(b"PATCH") {
+            Some(PatchFormat::Ips)
+        } else if data.starts_with(b"BPS1") {
+            Some(PatchFormat::Bps)
+        } else if data.starts_with(&[0xD6, 0xC3, 0xC4]) {
+            Some(PatchFormat::Xdelta)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for PatchFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            PatchFormat::Ppf1 => "PPF1",
+            PatchFormat::Ppf2 => "PPF2",
+            PatchFormat::Ppf3 => "PPF3",
+            PatchFormat::Ips => "IPS",
+            PatchFormat::Bps => "BPS",
+            PatchFormat::Xdelta => "xdelta3",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A patch file loaded into memory, ready to be applied to a disc image
+#[derive(Debug, Clone)]
+pub struct Patch {
+    pub path: PathBuf,
+    pub format: PatchFormat,
+    data: Vec<u8>,
+}
+
+/// Single PPF record (offset + replacement bytes + optional undo bytes)
+struct PpfRecord<'a> {
+    offset: u64,
+    data: &'a [u8],
+    undo: Option<&'a [u8]>,
+}
+
+/// Parsed PPF patch contents
+struct PpfContents<'a> {
+    expected_size: Option<u64>,
+    block_check: Option<(u64, &'a [u8])>,
+    records: Vec<PpfRecord<'a>>,
+}
+
+impl Patch {
+    /// Load a patch file and detect its format
+    pub fn load(path: &Path) -> crate::error::Result<Self> {
+        let data = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+
+        let format = PatchFormat::detect(&data).ok_or_else(|| Error::Patch {
+            path: path.to_path_buf(),
+            message: "unknown patch format".to_string(),
+        })?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            data,
+        })
+    }
+
+    /// Patch filename for display
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// Apply the patch to a disc image
+    ///
+    /// PPF and IPS patches are applied in place. BPS and xdelta3 patches
+    /// rebuild the image into a temporary file which replaces the original
+    /// only after all checksums have been verified. Read and write
+    /// failures are reported on the image.
+    pub fn apply(&self, image: &Path) -> crate::error::Result<()> {
+        status!("  Applying {} patch: {}", self.format, self.name());
+
+        match self.format {
+            PatchFormat::Ppf1 | PatchFormat::Ppf2 | PatchFormat::Ppf3 => self.apply_ppf(image),
+            PatchFormat::Ips => self.apply_ips(image),
+            PatchFormat::Bps => self.apply_bps(image),
+            PatchFormat::Xdelta => self.apply_xdelta(image),
+        }
+        .map_err(|e| {
+            Error::classify(image, e, |message| Error::Patch {
+                path: self.path.clone(),
+                message,
+            })
+        })
+    }
+
+    /// Parse PPF1/2/3 header, validation block and records
+    fn parse_ppf(&self) -> Result<PpfContents<'_>> {
+        let data = &self.data;
+        if data.len() < 56 {
+            bail!("PPF file is truncated");
+        }
+
+        let mut expected_size = None;
+        let mut block_check = None;
+        let mut undo = false;
+
+        let (records_start, offset_size) = match self.format {
+            PatchFormat::Ppf1 => (56, 4),
+            PatchFormat::Ppf2 => {
+                if data.len() < 60 + PPF_BLOCK_SIZE {
+                    bail!("PPF file is truncated");
+                }
+                expected_size = Some(read_le(&data[56..60]));
+                block_check = Some((PPF_BLOCK_OFFSET_BIN, &data[60..60 + PPF_BLOCK_SIZE]));
+                (60 + PPF_BLOCK_SIZE, 4)
+            }
+            _ => {
+                if data.len() < 60 {
+                    bail!("PPF file is truncated");
+                }
+                let block_offset = if data[56] == 1 {
+                    PPF_BLOCK_OFFSET_GI
+                } else {
+                    PPF_BLOCK_OFFSET_BIN
+                };
+                undo = data[58] == 1;
+
+                if data[57] == 1 {
+                    if data.len() < 60 + PPF_BLOCK_SIZE {
+                        bail!("PPF file is truncated");
+                    }
+                    block_check = Some((block_offset, &data[60..60 + PPF_BLOCK_SIZE]));
+                    (60 + PPF_BLOCK_SIZE, 8)
+                } else {
+                    (60, 8)
+                }
+            }
+        };
+
+        // PPF2/3 may carry a FILE_ID.DIZ description after the records
+        let records_end = if self.format != PatchFormat::Ppf1 {
+            data.windows(PPF_DIZ_BEGIN.len())
+                .rposition(|w| w == PPF_DIZ_BEGIN)
+                .filter(|&pos| pos >= records_start && has_diz_trailer(data, self.format))
+                .unwrap_or(data.len())
+        } else {
+            data.len()
+        };
+
+        let mut records = Vec::new();
+        let mut pos = records_start;
+        while pos < records_end {
+            if pos + offset_size + 1 > records_end {
+                bail!("PPF record at patch offset {} is truncated", pos);
+            }
+            let offset = read_le(&data[pos..pos + offset_size]);
+            let len = data[pos + offset_size] as usize;
+            pos += offset_size + 1;
+
+            let undo_len = if undo { len } else { 0 };
+            if pos + len + undo_len > records_end {
+                bail!("PPF record at image offset {} is truncated", offset);
+            }
+            let record_data = &data[pos..pos + len];
+            let record_undo = undo.then(|| &data[pos + len..pos + len + undo_len]);
+            pos += len + undo_len;
+
+            records.push(PpfRecord {
+                offset,
+                data: record_data,
+                undo: record_undo,
+            });
+        }
+
+        Ok(PpfContents {
+            expected_size,
+            block_check,
+            records,
+        })
+    }
+
+    /// Apply a PPF1/2/3 patch in place
+    fn apply_ppf(&self, image: &Path) -> Result<()> {
+        let contents = self.parse_ppf()?;
+        let mut file = OpenOptions::new().read(true).write(true).open(image)?;
+        let image_size = file.metadata()?.len();
+
+        if let Some(expected) = contents.expected_size {
+            if expected != image_size {
+                status!(
+                    "    [!] PPF expects a {} byte image, found {} bytes",
+                    expected,
+                    image_size
+                );
+            }
+        }
+
+        // Validation block: refuse to patch a different image
+        if let Some((offset, expected)) = contents.block_check {
+            let mut block = vec![0u8; PPF_BLOCK_SIZE];
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut block)
+                .context("Image is too small for PPF block check")?;
+            if block != expected {
+                bail!("PPF block check failed: patch was made for a different image");
+            }
+            status!("    Block check: OK");
+        }
+
+        // Verify every record before writing anything
+        let mut already_applied = 0usize;
+        let mut current = Vec::new();
+        for record in &contents.records {
+            current.resize(record.data.len(), 0);
+            file.seek(SeekFrom::Start(record.offset))?;
+            let available = read_fully(&mut file, &mut current)?;
+
+            if available == current.len() && current == record.data {
+                already_applied += 1;
+            } else if let Some(undo) = record.undo {
+                if current[..available] != undo[..available] {
+                    bail!(
+                        "Image data at offset {} does not match PPF undo data: \
+                         patch was made for a different image",
+                        record.offset
+                    );
+                }
+            }
+        }
+
+        if !contents.records.is_empty() && already_applied == contents.records.len() {
+            status!("    [!] Patch is already applied, skipping");
+            return Ok(());
+        }
+
+        for record in &contents.records {
+            file.seek(SeekFrom::Start(record.offset))?;
+            file.write_all(record.data)?;
+        }
+        file.flush()?;
+
+        status!("    Applied {} record(s)", contents.records.len());
+        Ok(())
+    }
+
+    /// Apply an IPS patch in place
+    fn apply_ips(&self, image: &Path) -> Result<()> {
+        let data = &self.data;
+        let mut file = OpenOptions::new().read(true).write(true).open(image)?;
+
+        let mut pos = 5;
+        let mut record_count = 0usize;
+        loop {
+            if pos + 3 > data.len() {
+                bail!("IPS patch is missing EOF marker");
+            }
+            if &data[pos..pos + 3] == b"EOF" {
+                pos += 3;
+                break;
+            }
+            if pos + 5 > data.len() {
+                bail!("IPS record at patch offset {} is truncated", pos);
+            }
+
+            let offset = read_be(&data[pos..pos + 3]);
+            let size = read_be(&data[pos + 3..pos + 5]) as usize;
+            pos += 5;
+
+            file.seek(SeekFrom::Start(offset))?;
+            if size == 0 {
+                // RLE record: 2-byte count + fill byte
+                if pos + 3 > data.len() {
+                    bail!("IPS RLE record at patch offset {} is truncated", pos);
+                }
+                let count = read_be(&data[pos..pos + 2]) as usize;
+                file.write_all(&vec![data[pos + 2]; count])?;
+                pos += 3;
+            } else {
+                if pos + size > data.len() {
+                    bail!("IPS record at patch offset {} is truncated", pos);
+                }
+                file.write_all(&data[pos..pos + size])?;
+                pos += size;
+            }
+            record_count += 1;
+        }
+
+        // Optional truncation extension
+        if pos + 3 <= data.len() {
+            let new_size = read_be(&data[pos..pos + 3]);
+            file.set_len(new_size)?;
+        }
+        file.flush()?;
+
+        status!("    Applied {} record(s)", record_count);
+        Ok(())
+    }
+
+    /// Apply a BPS patch (source CRC32 is checked before anything is written)
+    fn apply_bps(&self, image: &Path) -> Result<()> {
+        let data = &self.data;
+        if data.len() < 4 + 12 {
+            bail!("BPS patch is truncated");
+        }
+
+        let footer = data.len() - 12;
+        let source_crc = read_le(&data[footer..footer + 4]) as u32;
+        let target_crc = read_le(&data[footer + 4..footer + 8]) as u32;
+        let patch_crc = read_le(&data[footer + 8..]) as u32;
+        if crc32(0, &data[..footer + 8]) != patch_crc {
+            bail!("BPS patch is corrupted (checksum mismatch)");
+        }
+
+        let mut reader = ByteReader::new(&data[..footer]);
+        reader.skip(4)?;
+        let source_size = reader.bps_varint()?;
+        let target_size = reader.bps_varint()?;
+        let metadata_size = reader.bps_varint()?;
+        reader.skip(metadata_size as usize)?;
+
+        let mut source = File::open(image)?;
+        if source.metadata()?.len() != source_size {
+            bail!(
+                "BPS patch expects a {} byte image, found {} bytes",
+                source_size,
+                source.metadata()?.len()
+            );
+        }
+        if file_crc32(&mut source)? != source_crc {
+            bail!("BPS source checksum mismatch: patch was made for a different image");
+        }
+        status!("    Source checksum: OK");
+
+        let temp_path = temp_patch_path(image);
+        let result = (|| -> Result<()> {
+            let mut target = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&temp_path)?;
+
+            let mut output_offset = 0u64;
+            let mut source_relative = 0i64;
+            let mut target_relative = 0i64;
+
+            while !reader.is_empty() {
+                let action = reader.bps_varint()?;
+                let length = (action >> 2) + 1;
+
+                match action & 3 {
+                    // SourceRead
+                    0 => copy_range(
+                        &mut source,
+                        output_offset,
+                        &mut target,
+                        output_offset,
+                        length,
+                    )?,
+                    // TargetRead
+                    1 => {
+                        target.seek(SeekFrom::Start(output_offset))?;
+                        target.write_all(reader.bytes(length as usize)?)?;
+                    }
+                    // SourceCopy
+                    2 => {
+                        source_relative += reader.bps_signed()?;
+                        let from = checked_offset(source_relative)?;
+                        copy_range(&mut source, from, &mut target, output_offset, length)?;
+                        source_relative += length as i64;
+                    }
+                    // TargetCopy
+                    _ => {
+                        target_relative += reader.bps_signed()?;
+                        let from = checked_offset(target_relative)?;
+                        if from >= output_offset {
+                            bail!("BPS TargetCopy reads past the written data");
+                        }
+                        copy_within(&mut target, from, output_offset, length)?;
+                        target_relative += length as i64;
+                    }
+                }
+
+                output_offset += length;
+            }
+
+            if output_offset != target_size {
+                bail!(
+                    "BPS patch produced {} bytes, expected {}",
+                    output_offset,
+                    target_size
+                );
+            }
+            target.flush()?;
+            if file_crc32(&mut target)? != target_crc {
+                bail!("BPS target checksum mismatch");
+            }
+            Ok(())
+        })();
+
+        finish_rebuild(result, &temp_path, image)
+    }
+
+    /// Apply an xdelta3 (VCDIFF) patch
+    ///
+    /// Only uncompressed deltas with the default code table are supported.
+    /// The decoded target replaces the whole combined image, so the patch
+    /// must keep its size and only read source data inside it; a delta made
+    /// against a single track would otherwise drop the other tracks. When
+    /// the patch carries per-window Adler-32 checksums they are verified,
+    /// which catches patches made against a different base image.
+    fn apply_xdelta(&self, image: &Path) -> Result<()> {
+        let mut reader = ByteReader::new(&self.data);
+        reader.skip(3)?;
+        let version = reader.u8()?;
+        if version != 0 {
+            bail!("Unsupported VCDIFF version: {}", version);
+        }
+
+        let header_indicator = reader.u8()?;
+        if header_indicator & 0x01 != 0 {
+            bail!("xdelta3 secondary compression is not supported (recreate with -S none)");
+        }
+        if header_indicator & 0x02 != 0 {
+            bail!("Custom VCDIFF code tables are not supported");
+        }
+        if header_indicator & 0x04 != 0 {
+            let app_header_len = reader.vcd_varint()?;
+            reader.skip(app_header_len as usize)?;
+        }
+
+        let mut source = File::open(image)?;
+        let image_size = source.metadata()?.len();
+        let (target_size, source_end) = vcdiff_extent(reader.clone())?;
+        if source_end > image_size {
+            bail!(
+                "xdelta3 patch reads source data up to byte {}, but the image has {} bytes: \
+                 it was made for a different image",
+                source_end,
+                image_size
+            );
+        }
+        if target_size != image_size {
+            bail!(
+                "xdelta3 patch turns the {} byte image into {} bytes: it was made for a \
+                 single track or a different dump, not for the combined BIN",
+                image_size,
+                target_size
+            );
+        }
+
+        let code_table = default_code_table();
+        let temp_path = temp_patch_path(image);
+
+        let result = (|| -> Result<()> {
+            let mut target = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&temp_path)?;
+
+            let mut target_offset = 0u64;
+            let mut window_count = 0usize;
+            let mut verified = 0usize;
+
+            while !reader.is_empty() {
+                let window_indicator = reader.u8()?;
+                if window_indicator & 0x03 == 0x03 {
+                    bail!("Invalid VCDIFF window indicator");
+                }
+
+                let mut segment = Vec::new();
+                if window_indicator & 0x03 != 0 {
+                    let segment_len = reader.vcd_varint()? as usize;
+                    let segment_pos = reader.vcd_varint()?;
+                    segment.resize(segment_len, 0);
+
+                    let segment_file = if window_indicator & 0x01 != 0 {
+                        &mut source
+                    } else {
+                        &mut target
+                    };
+                    segment_file.seek(SeekFrom::Start(segment_pos))?;
+                    segment_file
+                        .read_exact(&mut segment)
+                        .context("VCDIFF source segment is outside the image")?;
+                }
+
+                let _delta_len = reader.vcd_varint()?;
+                let window_len = reader.vcd_varint()? as usize;
+                if reader.u8()? != 0 {
+                    bail!("Compressed VCDIFF sections are not supported");
+                }
+                let data_len = reader.vcd_varint()? as usize;
+                let inst_len = reader.vcd_varint()? as usize;
+                let addr_len = reader.vcd_varint()? as usize;
+                let checksum = if window_indicator & 0x04 != 0 {
+                    Some(read_be(reader.bytes(4)?) as u32)
+                } else {
+                    None
+                };
+
+                let sections = VcdiffSections {
+                    data: ByteReader::new(reader.bytes(data_len)?),
+                    inst: ByteReader::new(reader.bytes(inst_len)?),
+                    addr: ByteReader::new(reader.bytes(addr_len)?),
+                };
+                let window = decode_vcdiff_window(&code_table, &segment, sections, window_len)?;
+
+                if let Some(expected) = checksum {
+                    if adler32(&window) != expected {
+                        bail!(
+                            "xdelta3 checksum mismatch in window {}: \
+                             patch was made for a different image",
+                            window_count
+                        );
+                    }
+                    verified += 1;
+                }
+
+                target.seek(SeekFrom::Start(target_offset))?;
+                target.write_all(&window)?;
+                target_offset += window.len() as u64;
+                window_count += 1;
+            }
+
+            target.flush()?;
+            status!(
+                "    Decoded {} window(s), {} checksum(s) verified",
+                window_count,
+                verified
+            );
+            Ok(())
+        })();
+
+        finish_rebuild(result, &temp_path, image)
+    }
+}
+
+/// PPF2 ends with ".DIZ" + u32 length, PPF3 with ".DIZ" + u16 length
+fn has_diz_trailer(data: &[u8], format: PatchFormat) -> bool {
+    let len_size = if format == PatchFormat::Ppf2 { 4 } else { 2 };
+    data.len() >= 4 + len_size && &data[data.len() - len_size - 4..data.len() - len_size] == b".DIZ"
+}
+
+/// Instruction, data and address sections of a VCDIFF window
+struct VcdiffSections<'a> {
+    data: ByteReader<'a>,
+    inst: ByteReader<'a>,
+    addr: ByteReader<'a>,
+}
+
+/// One half of a VCDIFF code table entry
+#[derive(Debug, Clone, Copy)]
+struct VcdInst {
+    kind: u8,
+    size: u8,
+    mode: u8,
+}
+
+/// Build the RFC 3284 default code table
+fn default_code_table() -> Vec<[VcdInst; 2]> {
+    let inst = |kind, size, mode| VcdInst { kind, size, mode };
+    let noop = inst(VCD_NOOP, 0, 0);
+    let mut table = Vec::with_capacity(256);
+
+    table.push([inst(VCD_RUN, 0, 0), noop]);
+    for size in 0..=17 {
+        table.push([inst(VCD_ADD, size, 0), noop]);
+    }
+    for mode in 0..9 {
+        table.push([inst(VCD_COPY, 0, mode), noop]);
+        for size in 4..=18 {
+            table.push([inst(VCD_COPY, size, mode), noop]);
+        }
+    }
+    for mode in 0..6 {
+        for add_size in 1..=4 {
+            for copy_size in 4..=6 {
+                table.push([inst(VCD_ADD, add_size, 0), inst(VCD_COPY, copy_size, mode)]);
+            }
+        }
+    }
+    for mode in 6..9 {
+        for add_size in 1..=4 {
+            table.push([inst(VCD_ADD, add_size, 0), inst(VCD_COPY, 4, mode)]);
+        }
+    }
+    for mode in 0..9 {
+        table.push([inst(VCD_COPY, 4, mode), inst(VCD_ADD, 1, 0)]);
+    }
+
+    table
+}
+
+/// VCDIFF address cache (4 NEAR slots, 3 SAME blocks)
+struct AddressCache {
+    near: [u64; 4],
+    next_near: usize,
+    same: [u64; 3 * 256],
+}
+
+impl AddressCache {
+    fn new() -> Self {
+        Self {
+            near: [0; 4],
+            next_near: 0,
+            same: [0; 3 * 256],
+        }
+    }
+
+    fn decode(&mut self, here: u64, mode: u8, addr: &mut ByteReader) -> Result<u64> {
+        let address = match mode {
+            0 => addr.vcd_varint()?,
+            1 => here
+                .checked_sub(addr.vcd_varint()?)
+                .ok_or_else(|| anyhow::anyhow!("Invalid VCDIFF HERE address"))?,
+            2..=5 => self.near[mode as usize - 2] + addr.vcd_varint()?,
+            6..=8 => self.same[(mode as usize - 6) * 256 + addr.u8()? as usize],
+            _ => bail!("Invalid VCDIFF address mode: {}", mode),
+        };
+
+        if address >= here {
+            bail!(
+                "VCDIFF COPY address {} is past the current position",
+                address
+            );
+        }
+
+        self.near[self.next_near] = address;
+        self.next_near = (self.next_near + 1) % self.near.len();
+        self.same[(address % self.same.len() as u64) as usize] = address;
+
+        Ok(address)
+    }
+}
+
+/// Target size and end of the source data read by a VCDIFF delta
+///
+/// Only the window headers are parsed; `reader` starts at the first window.
+fn vcdiff_extent(mut reader: ByteReader) -> Result<(u64, u64)> {
+    let mut target_size = 0u64;
+    let mut source_end = 0u64;
+
+    while !reader.is_empty() {
+        let window_indicator = reader.u8()?;
+        if window_indicator & 0x03 == 0x03 {
+            bail!("Invalid VCDIFF window indicator");
+        }
+        if window_indicator & 0x03 != 0 {
+            let segment_len = reader.vcd_varint()?;
+            let segment_pos = reader.vcd_varint()?;
+            if window_indicator & 0x01 != 0 {
+                source_end = source_end.max(segment_pos.saturating_add(segment_len));
+            }
+        }
+
+        let delta_len = reader.vcd_varint()?;
+        let mut delta = ByteReader::new(reader.bytes(delta_len as usize)?);
+        target_size = target_size.saturating_add(delta.vcd_varint()?);
+    }
+
+    Ok((target_size, source_end))
+}
+
+/// Decode a single VCDIFF target window
+fn decode_vcdiff_window(
+    code_table: &[[VcdInst; 2]],
+    segment: &[u8],
+    mut sections: VcdiffSections,
+    window_len: usize,
+) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(window_len);
+    let mut cache = AddressCache::new();
+
+    while !sections.inst.is_empty() {
+        let code = sections.inst.u8()?;
+
+        for inst in code_table[code as usize] {
+            if inst.kind == VCD_NOOP {
+                continue;
+            }
+            let size = if inst.size == 0 {
+                sections.inst.vcd_varint()? as usize
+            } else {
+                inst.size as usize
+            };
+            if out.len() + size > window_len {
+                bail!("VCDIFF window overflows its declared size");
+            }
+
+            match inst.kind {
+                VCD_ADD => out.extend_from_slice(sections.data.bytes(size)?),
+                VCD_RUN => {
+                    let byte = sections.data.u8()?;
+                    out.resize(out.len() + size, byte);
+                }
+                _ => {
+                    let here = (segment.len() + out.len()) as u64;
+                    let address = cache.decode(here, inst.mode, &mut sections.addr)? as usize;
+
+                    // Byte by byte: copies from the target window may overlap
+                    for i in address..address + size {
+                        let byte = if i < segment.len() {
+                            segment[i]
+                        } else {
+                            out[i - segment.len()]
+                        };
+                        out.push(byte);
+                    }
+                }
+            }
+        }
+    }
+
+    if out.len() != window_len {
+        bail!(
+            "VCDIFF window decoded to {} bytes, expected {}",
+            out.len(),
+            window_len
+        );
+    }
+    Ok(out)
+}
+
+/// Cursor over patch bytes with the varint flavours used by BPS and VCDIFF
+#[derive(Clone)]
+struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| anyhow::anyhow!("Unexpected end of patch data"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            bail!("Unexpected end of patch data");
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    /// BPS variable-length integer (little-endian, with implicit +1 per byte)
+    fn bps_varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        let mut shift = 1u64;
+        loop {
+            let byte = self.u8()?;
+            value = value
+                .checked_add((byte & 0x7F) as u64 * shift)
+                .ok_or_else(|| anyhow::anyhow!("BPS number overflow"))?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_shl(7)
+                .filter(|&s| s != 0)
+                .ok_or_else(|| anyhow::anyhow!("BPS number overflow"))?;
+            value += shift;
+        }
+    }
+
+    /// BPS signed relative offset (sign in the lowest bit)
+    fn bps_signed(&mut self) -> Result<i64> {
+        let raw = self.bps_varint()?;
+        let magnitude = (raw >> 1) as i64;
+        Ok(if raw & 1 != 0 { -magnitude } else { magnitude })
+    }
+
+    /// VCDIFF variable-length integer (big-endian base 128)
+    fn vcd_varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        loop {
+            let byte = self.u8()?;
+            if value > u64::MAX >> 7 {
+                bail!("VCDIFF number overflow");
+            }
+            value = (value << 7) | (byte & 0x7F) as u64;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn checked_offset(offset: i64) -> Result<u64> {
+    u64::try_from(offset).map_err(|_| anyhow::anyhow!("Patch copies from a negative offset"))
+}
+
+/// Read as many bytes as available (short read at end of file)
+fn read_fully(file: &mut File, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let bytes_read = file.read(&mut buffer[filled..])?;
+        if bytes_read == 0 {
+            break;
+        }
+        filled += bytes_read;
+    }
+    Ok(filled)
+}
+
+/// Copy `length` bytes between two files
+fn copy_range(
+    from_file: &mut File,
+    from: u64,
+    to_file: &mut File,
+    to: u64,
+    length: u64,
+) -> Result<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE.min(length as usize)];
+    from_file.seek(SeekFrom::Start(from))?;
+    to_file.seek(SeekFrom::Start(to))?;
+
+    let mut remaining = length;
+    while remaining > 0 {
+        let chunk = (remaining as usize).min(buffer.len());
+        from_file
+            .read_exact(&mut buffer[..chunk])
+            .context("Patch reads past the end of the source image")?;
+        to_file.write_all(&buffer[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    Ok(())
+}
+
+/// Copy bytes forward within one file, honouring overlapping ranges
+fn copy_within(file: &mut File, mut from: u64, mut to: u64, length: u64) -> Result<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut remaining = length;
+
+    while remaining > 0 {
+        // Never read bytes that this copy has not written yet
+        let chunk = remaining.min(to - from).min(BUFFER_SIZE as u64) as usize;
+        file.seek(SeekFrom::Start(from))?;
+        file.read_exact(&mut buffer[..chunk])?;
+        file.seek(SeekFrom::Start(to))?;
+        file.write_all(&buffer[..chunk])?;
+
+        from += chunk as u64;
+        to += chunk as u64;
+        remaining -= chunk as u64;
+    }
+    Ok(())
+}
+
+fn temp_patch_path(image: &Path) -> PathBuf {
+    let mut name = image.as_os_str().to_owned();
+    name.push(".patching");
+    PathBuf::from(name)
+}
+
+/// Replace the image with the rebuilt file, or discard it on error
+fn finish_rebuild(result: Result<()>, temp_path: &Path, image: &Path) -> Result<()> {
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(temp_path);
+        return Err(e);
+    }
+    std::fs::rename(temp_path, image).context("Failed to replace patched image")?;
+    Ok(())
+}
+
+/// CRC-32 (IEEE 802.3), as used by BPS
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn file_crc32(file: &mut File) -> Result<u32> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut crc = 0;
+    file.seek(SeekFrom::Start(0))?;
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        crc = crc32(crc, &buffer[..bytes_read]);
+    }
+    Ok(crc)
+}
+
+/// Adler-32, as used by xdelta3 window checksums
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_image(name: &str, contents: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("psx-vcd-patch-{}-{}.bin", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn patch_from(format: PatchFormat, data: Vec<u8>) -> Patch {
+        Patch {
+            path: PathBuf::from("test.patch"),
+            format,
+            data,
+        }
+    }
+
+    fn ppf3(undo: bool, offset: u64, new: &[u8], old: &[u8]) -> Patch {
+        let mut data = b"PPF30".to_vec();
+        data.push(2);
+        data.extend_from_slice(&[b' '; 50]);
+        data.extend_from_slice(&[0, 0, undo as u8, 0]);
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.push(new.len() as u8);
+        data.extend_from_slice(new);
+        if undo {
+            data.extend_from_slice(old);
+        }
+        patch_from(PatchFormat::Ppf3, data)
+    }
+
+    #[test]
+    fn test_ppf3_undo_validation() {
+        let image = temp_image("ppf3", &[0u8; 64]);
+
+        // Undo data does not match the image: refuse
+        let wrong_base = ppf3(true, 8, b"ABCD", b"WXYZ");
+        assert!(wrong_base.apply(&image).is_err());
+        assert_eq!(std::fs::read(&image).unwrap(), vec![0u8; 64]);
+
+        let patch = ppf3(true, 8, b"ABCD", &[0; 4]);
+        patch.apply(&image).unwrap();
+        assert_eq!(&std::fs::read(&image).unwrap()[8..12], b"ABCD");
+
+        // Second application is detected and skipped
+        patch.apply(&image).unwrap();
+        let _ = std::fs::remove_file(&image);
+    }
+
+    #[test]
+    fn test_ips_with_rle() {
+        let image = temp_image("ips", &[0u8; 32]);
+
+        let mut data = b"PATCH".to_vec();
+        data.extend_from_slice(&[0, 0, 2, 0, 2, 0xAA, 0xBB]);
+        data.extend_from_slice(&[0, 0, 10, 0, 0, 0, 4, 0xCC]);
+        data.extend_from_slice(b"EOF");
+        patch_from(PatchFormat::Ips, data).apply(&image).unwrap();
+
+        let patched = std::fs::read(&image).unwrap();
+        assert_eq!(&patched[2..4], &[0xAA, 0xBB]);
+        assert_eq!(&patched[10..14], &[0xCC; 4]);
+        let _ = std::fs::remove_file(&image);
+    }
+
+    #[test]
+    fn test_bps_source_and_target_copy() {
+        let source = b"HELLO WORLD".to_vec();
+        let image = temp_image("bps", &source);
+
+        // Target: "HELLO HELLO!!!!"
+        let target = b"HELLO HELLO!!!!";
+        let mut data = b"BPS1".to_vec();
+        data.extend_from_slice(&[0x80 | 11, 0x80 | 15, 0x80]);
+        data.push(0x80 | (5 << 2)); // SourceRead 6 bytes
+        data.extend_from_slice(&[0x80 | (4 << 2) | 2, 0x80]); // SourceCopy 5 bytes from 0
+        data.extend_from_slice(&[0x80 | 1, b'!']); // TargetRead 1 byte
+        data.extend_from_slice(&[0x80 | (2 << 2) | 3, 0x80 | (11 << 1)]); // TargetCopy 3
+        data.extend_from_slice(&crc32(0, &source).to_le_bytes());
+        data.extend_from_slice(&crc32(0, target).to_le_bytes());
+        let patch_crc = crc32(0, &data);
+        data.extend_from_slice(&patch_crc.to_le_bytes());
+
+        patch_from(PatchFormat::Bps, data).apply(&image).unwrap();
+        assert_eq!(std::fs::read(&image).unwrap(), target);
+        let _ = std::fs::remove_file(&image);
+    }
+
+    #[test]
+    fn test_xdelta_copy_and_add() {
+        let source = b"ABCDEFGH".to_vec();
+        let image = temp_image("xdelta", &source);
+
+        let target = b"ABCDxyGH";
+        let mut data = vec![0xD6, 0xC3, 0xC4, 0x00, 0x00];
+        data.push(0x01 | 0x04); // VCD_SOURCE | VCD_ADLER32
+        data.extend_from_slice(&[8, 0]); // source segment: 8 bytes at 0
+        data.extend_from_slice(&[17, 8, 0, 2, 4, 2]); // delta, window, sections
+        data.extend_from_slice(&adler32(target).to_be_bytes());
+        data.extend_from_slice(b"xy"); // data section
+        data.extend_from_slice(&[20, 3, 19, 2]); // COPY 4, ADD 2, COPY 2 (explicit size)
+        data.extend_from_slice(&[0, 6]); // addresses 0 and 6
+        patch_from(PatchFormat::Xdelta, data.clone())
+            .apply(&image)
+            .unwrap();
+        assert_eq!(std::fs::read(&image).unwrap(), target);
+
+        // Same patch against a different base image fails the checksum
+        std::fs::write(&image, b"XXXXXXXX").unwrap();
+        assert!(patch_from(PatchFormat::Xdelta, data).apply(&image).is_err());
+        assert_eq!(std::fs::read(&image).unwrap(), b"XXXXXXXX");
+
+        // A delta that shrinks the image, as one made for a single track would
+        std::fs::write(&image, &source).unwrap();
+        let mut data = vec![0xD6, 0xC3, 0xC4, 0x00, 0x00];
+        data.extend_from_slice(&[0x01, 8, 0, 10, 6, 0, 2, 2, 1]);
+        data.extend_from_slice(b"xy");
+        data.extend_from_slice(&[20, 3, 0]);
+        let err = patch_from(PatchFormat::Xdelta, data)
+            .apply(&image)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("into 6 bytes"), "{:#}", err);
+        assert_eq!(std::fs::read(&image).unwrap(), source);
+
+        // A source window past the end of the image
+        let mut data = vec![0xD6, 0xC3, 0xC4, 0x00, 0x00];
+        data.extend_from_slice(&[0x01, 8, 4, 7, 8, 0, 0, 1, 1, 20, 0]);
+        let err = patch_from(PatchFormat::Xdelta, data)
+            .apply(&image)
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("up to byte 12"), "{:#}", err);
+        assert_eq!(err.exit_code(), 9);
+        let _ = std::fs::remove_file(&image);
+    }
+}
@@ -0,0 +1,267 @@
+// src/preflight.rs
+//! Checks of the output location before anything is written
+//!
+//! OPL reads games from USB sticks formatted FAT32 or exFAT. Running out of
+//! space halfway through a conversion, or producing a VCD larger than FAT32
+//! can store, is caught here from the sizes computed up front.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// Largest file FAT32 can store (4 GB - 1 byte)
+pub const FAT32_MAX_FILE_SIZE: u64 = 0xFFFF_FFFF;
+
+/// Longest filename, in UTF-16 code units, on FAT32 and exFAT
+const FAT_MAX_NAME: usize = 255;
+
+/// Longest VCD name OPL's game list is expected to cope with
+pub const OPL_MAX_NAME: usize = 64;
+
+/// Characters FAT32 and exFAT do not allow in filenames
+const FAT_RESERVED: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+
+/// Filesystem holding an output directory
+#[derive(Debug, Clone, Default)]
+pub struct OutputVolume {
+    /// Filesystem type as the kernel names it (`vfat`, `exfat`, `ext4`, ...)
+    pub fs_type: Option<String>,
+    /// Bytes available to unprivileged users
+    pub available: Option<u64>,
+}
+
+impl OutputVolume {
+    /// Look up the filesystem of `dir`, or of its closest existing parent
+    ///
+    /// Either field is `None` when the platform does not tell.
+    pub fn probe(dir: &Path) -> Self {
+        let Some(dir) = existing_ancestor(dir) else {
+            return Self::default();
+        };
+        Self {
+            fs_type: filesystem_type(&dir),
+            available: available_space(&dir),
+        }
+    }
+
+    /// FAT12/16/32, with the 4 GB file size limit
+    pub fn is_fat(&self) -> bool {
+        matches!(self.fs_type.as_deref(), Some("vfat" | "msdos" | "fat"))
+    }
+
+    /// Fail when `needed` bytes do not fit in the available space
+    pub fn check_space(&self, dir: &Path, needed: u64) -> Result<()> {
+        match self.available {
+            Some(available) if available < needed => Err(Error::io(
+                dir,
+                std::io::Error::other(format!(
+                    "not enough free space: {:.2} MB needed, {:.2} MB available",
+                    megabytes(needed),
+                    megabytes(available)
+                )),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail when a file of `size` bytes cannot be stored on this filesystem
+    pub fn check_file_size(&self, path: &Path, size: u64) -> Result<()> {
+        if self.is_fat() && size > FAT32_MAX_FILE_SIZE {
+            return Err(Error::io(
+                path,
+                std::io::Error::other(format!(
+                    "{:.2} MB exceeds the 4 GB file size limit of FAT32",
+                    megabytes(size)
+                )),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Problems with a filename on FAT32/exFAT or in OPL
+///
+/// These are warnings: the file may be written to another filesystem now
+/// and copied to the USB stick later.
+pub fn filename_warnings(name: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    let reserved: String = name
+        .chars()
+        .filter(|c| FAT_RESERVED.contains(c) || c.is_control())
+        .collect();
+    if !reserved.is_empty() {
+        warnings.push(format!(
+            "\"{}\" contains characters FAT32/exFAT do not allow: {}",
+            name,
+            reserved.escape_debug()
+        ));
+    }
+    if name.ends_with(['.', ' ']) {
+        warnings.push(format!(
+            "\"{}\" ends with a dot or space, which FAT32/exFAT drop",
+            name
+        ));
+    }
+    if name.encode_utf16().count() > FAT_MAX_NAME {
+        warnings.push(format!(
+            "\"{}\" is longer than the {} characters FAT32/exFAT allow",
+            name, FAT_MAX_NAME
+        ));
+    } else if name.chars().count() > OPL_MAX_NAME {
+        warnings.push(format!(
+            "\"{}\" is longer than {} characters; OPL may not list it",
+            name, OPL_MAX_NAME
+        ));
+    }
+    if !name.is_ascii() {
+        warnings.push(format!(
+            "\"{}\" has non-ASCII characters, which OPL may not display or load",
+            name
+        ));
+    }
+
+    warnings
+}
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// `path` itself or its closest parent that exists, made absolute
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            }
+        })
+        .find_map(|dir| dir.canonicalize().ok())
+}
+
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    let stat = nix::sys::statvfs::statvfs(dir).ok()?;
+    #[allow(clippy::useless_conversion)] // the field types vary by platform
+    Some(u64::from(stat.blocks_available()) * u64::from(stat.fragment_size()))
+}
+
+#[cfg(not(unix))]
+fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn filesystem_type(dir: &Path) -> Option<String> {
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    mount_fs_type(&mounts, dir)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn filesystem_type(_dir: &Path) -> Option<String> {
+    None
+}
+
+/// Type of the filesystem mounted closest above `dir` in a mounts table
+///
+/// Lines are `device mount-point type options dump pass`, with spaces and
+/// other special characters in the mount point written as octal escapes.
+/// A later mount on the same point hides earlier ones.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn mount_fs_type(mounts: &str, dir: &Path) -> Option<String> {
+    let mut best: Option<(usize, &str)> = None;
+    for line in mounts.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(_), Some(mount_point), Some(fs_type)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+
+        let mount_point = unescape_mount_point(mount_point);
+        let depth = Path::new(&mount_point).components().count();
+        if dir.starts_with(&mount_point) && best.map_or(true, |(d, _)| depth >= d) {
+            best = Some((depth, fs_type));
+        }
+    }
+    best.map(|(_, fs_type)| fs_type.to_string())
+}
+
+/// Decode the `\040`-style octal escapes of /proc/self/mounts
+fn unescape_mount_point(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+            std::str::from_utf8(digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        });
+        match octal {
+            Some(byte) if bytes[i] == b'\\' => {
+                decoded.push(byte);
+                i += 4;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_fs_type() {
+        let mounts = "\
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+tmpfs /tmp tmpfs rw 0 0
+/dev/sdb1 /media/user/OPL\\040USB vfat rw,uid=1000 0 0
+/dev/sdc1 /media/user/OPL exfat rw 0 0
+/dev/sdd1 /media/user/OPL fuseblk rw 0 0
+";
+        let fs = |dir: &str| mount_fs_type(mounts, Path::new(dir));
+        assert_eq!(fs("/home/user/games").as_deref(), Some("ext4"));
+        assert_eq!(fs("/media/user/OPL USB/POPS").as_deref(), Some("vfat"));
+        // Stacked mounts: the last one wins; no match on a name prefix
+        assert_eq!(fs("/media/user/OPL/POPS").as_deref(), Some("fuseblk"));
+        assert_eq!(fs("/media/user/OPLX").as_deref(), Some("ext4"));
+        assert_eq!(fs("/tmp").as_deref(), Some("tmpfs"));
+    }
+
+    #[test]
+    fn test_fat_limits() {
+        let fat = OutputVolume {
+            fs_type: Some("vfat".to_string()),
+            available: Some(1 << 30),
+        };
+        let vcd = Path::new("GAME.VCD");
+        assert!(fat.check_file_size(vcd, FAT32_MAX_FILE_SIZE).is_ok());
+        assert!(fat.check_file_size(vcd, FAT32_MAX_FILE_SIZE + 1).is_err());
+        assert!(fat.check_space(Path::new("."), 1 << 30).is_ok());
+        let err = fat.check_space(Path::new("."), (1 << 30) + 1).unwrap_err();
+        assert_eq!(err.exit_code(), 6);
+
+        let exfat = OutputVolume {
+            fs_type: Some("exfat".to_string()),
+            available: None,
+        };
+        assert!(exfat.check_file_size(vcd, 8 << 30).is_ok());
+        assert!(exfat.check_space(Path::new("."), u64::MAX).is_ok());
+
+        assert!(filename_warnings("SLUS_005.94.Metal Gear Solid.VCD").is_empty());
+        assert_eq!(filename_warnings("SLPS_012.34.Game: Part 2?.VCD").len(), 1);
+        assert_eq!(filename_warnings("SLPS_012.34.ゲーム.VCD").len(), 1);
+        assert_eq!(
+            filename_warnings(&format!("{}.VCD", "A".repeat(70))).len(),
+            1
+        );
+    }
+}
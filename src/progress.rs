@@ -0,0 +1,315 @@
+// src/progress.rs
+//! Progress reporting for long copy operations
+//!
+//! [`BinCombiner`](crate::combiner::BinCombiner) and
+//! [`VcdConverter`](crate::vcd::VcdConverter) report the bytes they have
+//! written through a [`ProgressReporter`]. The default reports nothing;
+//! [`ConsoleProgress`] is what the command-line tool uses.
+
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Whether a [`ConsoleProgress`] bar occupies the current terminal line
+static BAR_DRAWN: AtomicBool = AtomicBool::new(false);
+
+/// Erase an unfinished progress bar so a status line can take its place
+///
+/// Called by [`status!`](crate::status); the bar is redrawn on the next
+/// update.
+pub fn clear_bar() {
+    if BAR_DRAWN.swap(false, Ordering::Relaxed) {
+        eprint!("\r\x1b[K");
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Bytes written between two [`ProgressReporter::update`] calls
+pub const PROGRESS_INTERVAL: u64 = 8 * 1024 * 1024;
+
+/// Receives the progress of long copy operations
+pub trait ProgressReporter {
+    /// A new operation writing `total` bytes starts
+    fn start(&mut self, label: &str, total: u64);
+
+    /// `done` bytes of the current operation have been written
+    fn update(&mut self, done: u64);
+
+    /// The current operation is complete
+    fn finish(&mut self);
+}
+
+/// Reporter that ignores all progress
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn start(&mut self, _label: &str, _total: u64) {}
+    fn update(&mut self, _done: u64) {}
+    fn finish(&mut self) {}
+}
+
+/// Writer that reports the bytes passing through it every
+/// [`PROGRESS_INTERVAL`] bytes
+pub struct ProgressWriter<'a, W> {
+    inner: W,
+    reporter: &'a mut dyn ProgressReporter,
+    written: u64,
+    reported: u64,
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    /// Start an operation of `total` bytes on `reporter`
+    pub fn new(inner: W, reporter: &'a mut dyn ProgressReporter, label: &str, total: u64) -> Self {
+        reporter.start(label, total);
+        Self {
+            inner,
+            reporter,
+            written: 0,
+            reported: 0,
+        }
+    }
+
+    /// Report the final byte count and end the operation
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.inner.flush()?;
+        self.reporter.update(self.written);
+        self.reporter.finish();
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        if self.written - self.reported >= PROGRESS_INTERVAL {
+            self.reported = self.written;
+            self.reporter.update(self.written);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Progress on stderr: a redrawn bar on a terminal, a plain line every few
+/// seconds otherwise (logs, pipes, CI)
+pub struct ConsoleProgress {
+    interactive: bool,
+    label: String,
+    total: u64,
+    done: u64,
+    started: Instant,
+    last_output: Instant,
+}
+
+impl ConsoleProgress {
+    const BAR_WIDTH: usize = 24;
+    const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+    const LINE_INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new() -> Self {
+        Self::with_terminal(std::io::stderr().is_terminal())
+    }
+
+    /// Force bar (`true`) or plain line (`false`) output
+    pub fn with_terminal(interactive: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            interactive,
+            label: String::new(),
+            total: 0,
+            done: 0,
+            started: now,
+            last_output: now,
+        }
+    }
+
+    /// Bytes per second since the operation started
+    fn rate(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.done as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    fn draw_bar(&self) {
+        let line = format_bar(&self.label, self.done, self.total, self.rate());
+        eprint!("\r{}\x1b[K", line);
+        let _ = std::io::stderr().flush();
+        BAR_DRAWN.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Default for ConsoleProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressReporter for ConsoleProgress {
+    fn start(&mut self, label: &str, total: u64) {
+        self.label = label.to_string();
+        self.total = total;
+        self.done = 0;
+        self.started = Instant::now();
+        self.last_output = self.started;
+        if self.interactive {
+            self.draw_bar();
+        }
+    }
+
+    fn update(&mut self, done: u64) {
+        self.done = done;
+        let interval = if self.interactive {
+            Self::REDRAW_INTERVAL
+        } else {
+            Self::LINE_INTERVAL
+        };
+        if self.last_output.elapsed() < interval {
+            return;
+        }
+        self.last_output = Instant::now();
+
+        if self.interactive {
+            self.draw_bar();
+        } else {
+            eprintln!(
+                "  {}",
+                format_status(&self.label, self.done, self.total, self.rate())
+            );
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.interactive {
+            self.draw_bar();
+            eprintln!();
+            BAR_DRAWN.store(false, Ordering::Relaxed);
+        } else if self.started.elapsed() >= Self::LINE_INTERVAL {
+            // Short operations finish before the first line is due
+            eprintln!(
+                "  {}",
+                format_status(&self.label, self.done, self.total, self.rate())
+            );
+        }
+    }
+}
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Remaining time at `rate` bytes per second, as m:ss
+fn format_eta(done: u64, total: u64, rate: f64) -> String {
+    if rate <= 0.0 {
+        return "--:--".to_string();
+    }
+    let seconds = (total.saturating_sub(done) as f64 / rate).round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn percent(done: u64, total: u64) -> u64 {
+    (done.min(total) * 100).checked_div(total).unwrap_or(100)
+}
+
+/// `Combining [#####.....]  50%  350.0/700.0 MB  35.0 MB/s  ETA 0:10`
+fn format_bar(label: &str, done: u64, total: u64, rate: f64) -> String {
+    let filled = if total == 0 {
+        ConsoleProgress::BAR_WIDTH
+    } else {
+        (done.min(total) as f64 / total as f64 * ConsoleProgress::BAR_WIDTH as f64) as usize
+    };
+    format!(
+        "  {} [{}{}] {:>3}%  {:.1}/{:.1} MB  {:.1} MB/s  ETA {}",
+        label,
+        "#".repeat(filled),
+        ".".repeat(ConsoleProgress::BAR_WIDTH - filled),
+        percent(done, total),
+        megabytes(done),
+        megabytes(total),
+        megabytes(rate as u64),
+        format_eta(done, total, rate)
+    )
+}
+
+/// `Combining: 50% (350.0/700.0 MB, 35.0 MB/s, ETA 0:10)`
+fn format_status(label: &str, done: u64, total: u64, rate: f64) -> String {
+    format!(
+        "{}: {}% ({:.1}/{:.1} MB, {:.1} MB/s, ETA {})",
+        label,
+        percent(done, total),
+        megabytes(done),
+        megabytes(total),
+        megabytes(rate as u64),
+        format_eta(done, total, rate)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl ProgressReporter for Recorder {
+        fn start(&mut self, label: &str, total: u64) {
+            self.events.push(format!("start {} {}", label, total));
+        }
+        fn update(&mut self, done: u64) {
+            self.events.push(format!("update {}", done));
+        }
+        fn finish(&mut self) {
+            self.events.push("finish".to_string());
+        }
+    }
+
+    #[test]
+    fn test_progress_writer_reports_every_interval() {
+        let mut recorder = Recorder::default();
+        let total = 2 * PROGRESS_INTERVAL + 100;
+        let mut writer = ProgressWriter::new(Vec::new(), &mut recorder, "Copying", total);
+        let chunk = vec![0u8; 1024 * 1024];
+        let mut remaining = total;
+        while remaining > 0 {
+            let n = remaining.min(chunk.len() as u64) as usize;
+            writer.write_all(&chunk[..n]).unwrap();
+            remaining -= n as u64;
+        }
+        let output = writer.finish().unwrap();
+
+        assert_eq!(output.len() as u64, total);
+        assert_eq!(
+            recorder.events,
+            [
+                format!("start Copying {}", total),
+                format!("update {}", PROGRESS_INTERVAL),
+                format!("update {}", 2 * PROGRESS_INTERVAL),
+                format!("update {}", total),
+                "finish".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_progress_formatting() {
+        let mb = 1024 * 1024;
+        assert_eq!(
+            format_status("Combining", 350 * mb, 700 * mb, 35.0 * mb as f64),
+            "Combining: 50% (350.0/700.0 MB, 35.0 MB/s, ETA 0:10)"
+        );
+        assert_eq!(
+            format_bar("Writing VCD", 0, 0, 0.0),
+            "  Writing VCD [########################] 100%  0.0/0.0 MB  0.0 MB/s  ETA --:--"
+        );
+        assert_eq!(format_eta(0, 3600 * mb, mb as f64), "60:00");
+    }
+}
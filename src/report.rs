@@ -0,0 +1,192 @@
+// src/report.rs
+//! Machine-readable run report (`--format json`)
+//!
+//! Progress and decorative output goes through [`status!`], which prints to
+//! stdout normally and to stderr once machine output is enabled, so stdout
+//! carries nothing but the final JSON document.
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cue::CueSheet;
+use crate::sector::SECTOR_SIZE;
+use crate::vcd::{VcdHeader, VCD_HEADER_SIZE};
+
+static MACHINE_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Route [`status!`] output to stderr (true) or stdout (false)
+pub fn set_machine_output(enabled: bool) {
+    MACHINE_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn machine_output() -> bool {
+    MACHINE_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// `println!` for human-oriented output; goes to stderr in machine mode
+///
+/// Erases an unfinished progress bar first.
+#[macro_export]
+macro_rules! status {
+    () => {{
+        $crate::progress::clear_bar();
+        if $crate::report::machine_output() {
+            eprintln!()
+        } else {
+            println!()
+        }
+    }};
+    ($($arg:tt)*) => {{
+        $crate::progress::clear_bar();
+        if $crate::report::machine_output() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    }};
+}
+
+/// How a Game ID was found
+#[derive(Debug, Clone, Serialize)]
+pub struct GameIdReport {
+    pub id: String,
+    pub region: String,
+    /// Detection method, e.g. "SLUS_XXX.XX pattern"
+    pub method: String,
+    /// File the ID was read from
+    pub source: PathBuf,
+}
+
+/// Track entry as written to a VCD header
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackReport {
+    pub number: u8,
+    pub audio: bool,
+    pub index00: String,
+    pub index01: String,
+}
+
+/// A file written by the run
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputReport {
+    /// "vcd", "bin", "cue", ...
+    pub kind: &'static str,
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// Disc sectors in the file (without the VCD header)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sectors: Option<u64>,
+}
+
+/// One JSON document describing a whole run
+#[derive(Debug, Default, Serialize)]
+pub struct RunReport {
+    pub command: String,
+    pub success: bool,
+    pub exit_code: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cue_sheet: Option<CueSheet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<GameIdReport>,
+    /// Seconds added to track indexes (+2 for --gap-plus, -2 for --gap-minus)
+    pub gap_adjustment: i32,
+    /// Track entries of the written VCD header
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tracks: Vec<TrackReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leadout: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<OutputReport>,
+    pub warnings: Vec<String>,
+    /// Subcommand-specific results
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RunReport {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Print a warning and keep it for the report
+    pub fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        status!("[!] {}", message);
+        self.warnings.push(message);
+    }
+
+    /// Record a written file with its size
+    pub fn add_output(&mut self, kind: &'static str, path: &Path) -> std::io::Result<()> {
+        let bytes = std::fs::metadata(path)?.len();
+        let sectors = match kind {
+            "vcd" => Some(bytes.saturating_sub(VCD_HEADER_SIZE as u64) / SECTOR_SIZE as u64),
+            "bin" => Some(bytes / SECTOR_SIZE as u64),
+            _ => None,
+        };
+        self.outputs.push(OutputReport {
+            kind,
+            path: path.to_path_buf(),
+            bytes,
+            sectors,
+        });
+        Ok(())
+    }
+
+    /// Record the track MSFs and lead-out of a written VCD header
+    pub fn set_header(&mut self, header: &VcdHeader) {
+        self.leadout = Some(header.leadout.to_string());
+        self.tracks = header
+            .tracks
+            .iter()
+            .map(|t| TrackReport {
+                number: t.number,
+                audio: t.is_audio,
+                index00: t.index00.to_string(),
+                index01: t.index01.to_string(),
+            })
+            .collect();
+    }
+
+    pub fn detail(&mut self, key: &str, value: impl Serialize) {
+        if let Ok(value) = serde_json::to_value(value) {
+            self.details.insert(key.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_document() {
+        let path = std::env::temp_dir().join("psx_vcd_report_test.VCD");
+        std::fs::write(&path, vec![0u8; VCD_HEADER_SIZE + 3 * SECTOR_SIZE]).unwrap();
+
+        let mut report = RunReport::new("convert");
+        report.gap_adjustment = 2;
+        report.warn("Track 02: file size is not a multiple of 2352");
+        report.add_output("vcd", &path).unwrap();
+        report.detail("sectors_checked", 3);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["command"], "convert");
+        assert_eq!(json["gap_adjustment"], 2);
+        assert_eq!(json["outputs"][0]["sectors"], 3);
+        assert_eq!(json["warnings"].as_array().unwrap().len(), 1);
+        assert_eq!(json["details"]["sectors_checked"], 3);
+        // Empty sections are left out
+        assert!(json.get("cue_sheet").is_none());
+        assert!(json.get("tracks").is_none());
+
+        std::fs::remove_file(&path).ok();
+    }
+}
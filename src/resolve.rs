@@ -0,0 +1,366 @@
+// src/resolve.rs
+use std::cmp::Ordering;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cue::{file_line_name, CueSheet, FileEntry};
+use crate::error::{Error, Result};
+
+/// Extensions considered when looking for a renamed BINARY file
+const IMAGE_EXTENSIONS: &[&str] = &["bin", "img"];
+
+/// How a missing FILE entry was matched to a file on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Same name with different letter case
+    CaseInsensitive,
+    /// Renamed file picked by track count and size
+    BySize,
+}
+
+/// A FILE entry whose name was replaced by the file actually found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    pub original: String,
+    pub resolved: String,
+    pub resolution: Resolution,
+}
+
+impl std::fmt::Display for Substitution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let how = match self.resolution {
+            Resolution::CaseInsensitive => "case differs",
+            Resolution::BySize => "matched by track count and size",
+        };
+        write!(
+            f,
+            "\"{}\" -> \"{}\" ({})",
+            self.original, self.resolved, how
+        )
+    }
+}
+
+/// Point FILE entries that do not exist at the files actually on disk
+///
+/// Names are first matched ignoring case (CUEs written on Windows often
+/// disagree with the case on disk). Remaining BINARY entries are matched
+/// against unreferenced `.bin`/`.img` files: when there are as many
+/// candidates as missing entries they are paired in natural name order,
+/// otherwise a candidate is used only if it is the single one whose size
+/// fits the entry's tracks. Unresolved entries are left untouched.
+pub fn resolve_filenames(cue_sheet: &mut CueSheet, cue_dir: &Path) -> Vec<Substitution> {
+    let mut substitutions = Vec::new();
+    let mut unresolved = Vec::new();
+
+    for (idx, file) in cue_sheet.files.iter_mut().enumerate() {
+        let path = cue_dir.join(&file.filename);
+        if path.exists() {
+            continue;
+        }
+
+        match case_insensitive_match(&path) {
+            Some(found) => {
+                let resolved = Path::new(&file.filename)
+                    .with_file_name(found)
+                    .to_string_lossy()
+                    .into_owned();
+                substitutions.push(Substitution {
+                    original: std::mem::replace(&mut file.filename, resolved.clone()),
+                    resolved,
+                    resolution: Resolution::CaseInsensitive,
+                });
+            }
+            None if file.file_type.eq_ignore_ascii_case("BINARY") => unresolved.push(idx),
+            None => {}
+        }
+    }
+
+    if unresolved.is_empty() {
+        return substitutions;
+    }
+
+    // Unreferenced disc images in the CUE directory, in natural order
+    let referenced: Vec<String> = cue_sheet
+        .files
+        .iter()
+        .map(|f| f.filename.to_lowercase())
+        .collect();
+    let mut candidates: Vec<(String, u64)> = list_dir(cue_dir)
+        .into_iter()
+        .filter(|(name, _)| {
+            let ext = Path::new(name)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            IMAGE_EXTENSIONS.contains(&ext.as_str()) && !referenced.contains(&name.to_lowercase())
+        })
+        .collect();
+    candidates.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+
+    let pairs: Vec<(usize, usize)> = if candidates.len() == unresolved.len() {
+        unresolved
+            .iter()
+            .copied()
+            .zip(0..candidates.len())
+            .filter(|&(file, candidate)| fits(&cue_sheet.files[file], candidates[candidate].1))
+            .collect()
+    } else {
+        unresolved
+            .iter()
+            .filter_map(|&file| {
+                let mut fitting = (0..candidates.len())
+                    .filter(|&c| fits(&cue_sheet.files[file], candidates[c].1));
+                match (fitting.next(), fitting.next()) {
+                    (Some(candidate), None) => Some((file, candidate)),
+                    _ => None,
+                }
+            })
+            .collect()
+    };
+
+    // Never hand the same file to two entries
+    for &(file, candidate) in &pairs {
+        if pairs.iter().filter(|&&(_, c)| c == candidate).count() > 1 {
+            continue;
+        }
+        let entry = &mut cue_sheet.files[file];
+        let resolved = candidates[candidate].0.clone();
+        substitutions.push(Substitution {
+            original: std::mem::replace(&mut entry.filename, resolved.clone()),
+            resolved,
+            resolution: Resolution::BySize,
+        });
+    }
+
+    substitutions
+}
+
+/// Rewrite the FILE lines of a CUE with resolved names
+///
+/// The original is kept as `<name>.cue.bak`, or `<name>.cue.bak.N` when an
+/// earlier backup exists. Returns the backup path.
+pub fn rewrite_cue(cue_path: &Path, substitutions: &[Substitution]) -> Result<PathBuf> {
+    let text = std::fs::read_to_string(cue_path).map_err(|e| Error::io(cue_path, e))?;
+
+    let mut output = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let replacement = file_line_name(line).and_then(|(name, span)| {
+            substitutions
+                .iter()
+                .find(|s| s.original == name)
+                .map(|s| (s, span))
+        });
+
+        match replacement {
+            Some((s, span)) => {
+                output.push_str(&line[..span.start]);
+                output.push_str(&format!("\"{}\"", s.resolved));
+                output.push_str(&line[span.end..]);
+            }
+            None => output.push_str(line),
+        }
+    }
+
+    let backup = create_backup(cue_path, text.as_bytes())?;
+    std::fs::write(cue_path, output).map_err(|e| Error::io(cue_path, e))?;
+    Ok(backup)
+}
+
+/// Save `contents` under the first free `<path>.bak[.N]` name
+fn create_backup(path: &Path, contents: &[u8]) -> Result<PathBuf> {
+    for n in 0.. {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        if n > 0 {
+            backup.push(format!(".{}", n));
+        }
+        let backup = PathBuf::from(backup);
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup)
+        {
+            Ok(mut file) => {
+                file.write_all(contents)
+                    .and_then(|()| file.sync_all())
+                    .map_err(|e| Error::io(&backup, e))?;
+                return Ok(backup);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(Error::io(&backup, e)),
+        }
+    }
+    unreachable!("backup names are unbounded")
+}
+
+/// The one directory entry matching a path's file name ignoring case
+fn case_insensitive_match(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    let dir = path.parent()?;
+
+    let mut matches = list_dir(dir)
+        .into_iter()
+        .map(|(entry, _)| entry)
+        .filter(|entry| entry.to_lowercase() == name);
+    match (matches.next(), matches.next()) {
+        (Some(found), None) => Some(found),
+        _ => None,
+    }
+}
+
+/// Regular files in a directory with their sizes
+fn list_dir(dir: &Path) -> Vec<(String, u64)> {
+    // A CUE given as a bare file name has an empty parent
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((
+                entry.file_name().to_string_lossy().into_owned(),
+                metadata.len(),
+            ))
+        })
+        .collect()
+}
+
+/// Whether a file of `size` bytes can hold the tracks of a FILE entry
+fn fits(file: &FileEntry, size: u64) -> bool {
+    let (Some(first), Some(last)) = (file.tracks.first(), file.tracks.last()) else {
+        return false;
+    };
+
+    // Bytes up to the start of the last track, which needs at least a sector
+    let mut probe = file.clone();
+    probe.file_size = u64::MAX;
+    let extents = probe.track_extents();
+    let minimum = extents.last().map(|e| e.offset).unwrap_or(0) + last.sector_size() as u64;
+
+    let uniform = file
+        .tracks
+        .iter()
+        .all(|t| t.sector_size() == first.sector_size());
+    size >= minimum && (!uniform || size % first.sector_size() as u64 == 0)
+}
+
+/// Compare names treating runs of digits as numbers ("Track 2" < "Track 10")
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                        digits.push(*c);
+                        chars.next();
+                    }
+                    digits.trim_start_matches('0').to_string()
+                };
+                let (x, y) = (take(&mut a), take(&mut b));
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_ascii_lowercase().cmp(&y.to_ascii_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_case_and_size() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-resolve-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("game (track 1).bin"), vec![0u8; 2352 * 20]).unwrap();
+        std::fs::write(dir.join("Renamed (Track 2).bin"), vec![0u8; 2352 * 10]).unwrap();
+        std::fs::write(dir.join("Renamed (Track 10).bin"), vec![0u8; 2352 * 5]).unwrap();
+
+        let mut text = String::new();
+        for (track, n) in [(1, 1), (2, 2), (3, 10)] {
+            text.push_str(&format!(
+                "FILE \"Game (Track {}).BIN\" BINARY\n  TRACK {:02} {}\n    INDEX 01 00:00:00\n",
+                n,
+                track,
+                if track == 1 { "MODE2/2352" } else { "AUDIO" }
+            ));
+        }
+        let cue_path = dir.join("game.cue");
+        std::fs::write(&cue_path, &text).unwrap();
+        let (mut sheet, _) = CueSheet::parse_text(&text, true);
+
+        let substitutions = resolve_filenames(&mut sheet, &dir);
+        let resolved: Vec<_> = substitutions
+            .iter()
+            .map(|s| (s.resolved.as_str(), s.resolution))
+            .collect();
+        assert_eq!(
+            resolved,
+            [
+                ("game (track 1).bin", Resolution::CaseInsensitive),
+                ("Renamed (Track 2).bin", Resolution::BySize),
+                ("Renamed (Track 10).bin", Resolution::BySize),
+            ]
+        );
+
+        rewrite_cue(&cue_path, &substitutions).unwrap();
+        let rewritten = CueSheet::parse(&cue_path).unwrap();
+        assert_eq!(rewritten.files[2].filename, "Renamed (Track 10).bin");
+        assert!(dir.join("game.cue.bak").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_cue_matches_whole_names() {
+        let dir = std::env::temp_dir().join(format!("psx-vcd-rewrite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cue_path = dir.join("game.cue");
+        let text = "FILE \"data.bin\" BINARY\n  TRACK 01 MODE2/2352\n    INDEX 01 00:00:00\n\
+                    FILE a.bin BINARY\n  TRACK 02 AUDIO\n    INDEX 01 00:00:00\n";
+        std::fs::write(&cue_path, text).unwrap();
+        std::fs::write(dir.join("game.cue.bak"), "earlier backup").unwrap();
+
+        let substitutions = [Substitution {
+            original: "a.bin".to_string(),
+            resolved: "Game (Track 2).bin".to_string(),
+            resolution: Resolution::BySize,
+        }];
+        let backup = rewrite_cue(&cue_path, &substitutions).unwrap();
+
+        let rewritten = std::fs::read_to_string(&cue_path).unwrap();
+        assert!(rewritten.starts_with("FILE \"data.bin\" BINARY\n"));
+        assert!(rewritten.contains("FILE \"Game (Track 2).bin\" BINARY\n"));
+        // An existing backup is left alone
+        assert_eq!(backup, dir.join("game.cue.bak.1"));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), text);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("game.cue.bak")).unwrap(),
+            "earlier backup"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
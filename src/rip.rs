@@ -0,0 +1,395 @@
+// src/rip.rs
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::audio::{self, AudioFormat};
+use crate::cue::CueSheet;
+use crate::error::{Error, Result};
+use crate::flac;
+use crate::output::PartialFile;
+use crate::sector::SECTOR_SIZE;
+use crate::titledb::TitleDb;
+use crate::utils::Msf;
+use crate::vcd::VcdHeader;
+
+/// Output container for ripped tracks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RipFormat {
+    Wav,
+    Flac,
+}
+
+impl RipFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RipFormat::Wav => "wav",
+            RipFormat::Flac => "flac",
+        }
+    }
+}
+
+/// What to do with the INDEX 00 pregap of audio tracks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PregapMode {
+    /// Drop pregaps, every file starts at INDEX 01
+    Skip,
+    /// Keep each track's pregap at the start of its own file
+    Prepend,
+    /// Append the next track's pregap to the end of the file (EAC style)
+    Append,
+}
+
+/// A byte range of disc audio in one source file
+#[derive(Debug, Clone)]
+struct Segment {
+    source: usize,
+    range: Range<u64>,
+}
+
+/// A track cut out of a CUE layout or VCD header
+#[derive(Debug, Clone)]
+pub struct RipTrack {
+    pub number: u8,
+    pub is_audio: bool,
+    pregap: Segment,
+    body: Segment,
+}
+
+/// Source image files and the tracks they contain
+#[derive(Debug, Default)]
+pub struct RipLayout {
+    sources: Vec<(PathBuf, Option<AudioFormat>)>,
+    pub tracks: Vec<RipTrack>,
+}
+
+impl RipLayout {
+    /// Track layout of a CUE sheet (file sizes must be loaded)
+    pub fn from_cue(cue_sheet: &CueSheet, cue_dir: &Path) -> Self {
+        let mut layout = Self::default();
+
+        for file in &cue_sheet.files {
+            let source = layout.sources.len();
+            layout
+                .sources
+                .push((cue_dir.join(&file.filename), file.audio_format));
+
+            for (track, extent) in file.tracks.iter().zip(file.track_extents()) {
+                let pregap_sectors = track.pregap_sectors();
+                let pregap_end = (extent.offset
+                    + pregap_sectors as u64 * track.sector_size() as u64)
+                    .min(extent.offset + extent.length);
+
+                layout.tracks.push(RipTrack {
+                    number: track.number,
+                    is_audio: track.is_audio(),
+                    pregap: Segment {
+                        source,
+                        range: extent.offset..pregap_end,
+                    },
+                    body: Segment {
+                        source,
+                        range: pregap_end..extent.offset + extent.length,
+                    },
+                });
+            }
+        }
+
+        layout
+    }
+
+    /// Track layout from the track entries of a VCD header
+    pub fn from_vcd(vcd_path: &Path, header: &VcdHeader) -> Result<Self> {
+        let vcd_size = std::fs::metadata(vcd_path)
+            .map_err(|e| Error::io(vcd_path, e))?
+            .len();
+        let mut layout = Self::default();
+        layout.sources.push((vcd_path.to_path_buf(), None));
+
+        let positions = header.track_positions();
+        for (idx, (entry, (index00, index01))) in header.tracks.iter().zip(&positions).enumerate() {
+            let end = positions
+                .get(idx + 1)
+                .map_or(header.leadout_position(), |(next, _)| *next);
+            let end = VcdHeader::byte_offset(end).min(vcd_size);
+            let pregap_start = VcdHeader::byte_offset(*index00).min(end);
+            let body_start = VcdHeader::byte_offset(*index01).clamp(pregap_start, end);
+
+            layout.tracks.push(RipTrack {
+                number: entry.number,
+                is_audio: entry.is_audio,
+                pregap: Segment {
+                    source: 0,
+                    range: pregap_start..body_start,
+                },
+                body: Segment {
+                    source: 0,
+                    range: body_start..end,
+                },
+            });
+        }
+
+        Ok(layout)
+    }
+
+    /// Segments making up a ripped track for the chosen pregap handling
+    fn segments(&self, idx: usize, pregap: PregapMode) -> Vec<Segment> {
+        let track = &self.tracks[idx];
+        match pregap {
+            PregapMode::Skip => vec![track.body.clone()],
+            PregapMode::Prepend => vec![track.pregap.clone(), track.body.clone()],
+            PregapMode::Append => {
+                let mut segments = vec![track.body.clone()];
+                if let Some(next) = self.tracks.get(idx + 1).filter(|t| t.is_audio) {
+                    segments.push(next.pregap.clone());
+                }
+                segments
+            }
+        }
+    }
+
+    /// Total length in bytes of a ripped track
+    pub fn track_length(&self, idx: usize, pregap: PregapMode) -> u64 {
+        self.segments(idx, pregap)
+            .iter()
+            .map(|s| s.range.end - s.range.start)
+            .sum()
+    }
+
+    /// Write one track as WAV or FLAC
+    ///
+    /// The file is written under a temporary name and renamed into place;
+    /// an existing file is replaced only when `replace` is set. Failures
+    /// while copying are reported on the output file.
+    pub fn write_track(
+        &self,
+        idx: usize,
+        pregap: PregapMode,
+        format: RipFormat,
+        output_path: &Path,
+        replace: bool,
+    ) -> Result<()> {
+        let length = self.track_length(idx, pregap);
+
+        let segments = self.segments(idx, pregap);
+        let mut reader: Box<dyn Read> = Box::new(std::io::empty());
+        for segment in &segments {
+            reader = Box::new(reader.chain(self.open_segment(segment)?));
+        }
+
+        let partial = PartialFile::new(output_path);
+        let file = File::create(partial.path()).map_err(|e| Error::io(partial.path(), e))?;
+        let mut writer = BufWriter::new(file);
+        let output_err = |e| Error::io(output_path, e);
+
+        match format {
+            RipFormat::Wav => {
+                writer.write_all(&wav_header(length)).map_err(output_err)?;
+                let copied =
+                    std::io::copy(&mut reader.take(length), &mut writer).map_err(output_err)?;
+                if copied != length {
+                    let source = segments.last().map(|s| &self.sources[s.source].0);
+                    return Err(Error::io(
+                        source.map_or(output_path, |p| p.as_path()),
+                        std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            "image ends before the track does",
+                        ),
+                    ));
+                }
+            }
+            RipFormat::Flac => {
+                flac::encode_cdda(&mut reader, &mut writer, length).map_err(|e| {
+                    Error::classify(output_path, e, |message| Error::audio(output_path, message))
+                })?
+            }
+        }
+
+        writer.flush().map_err(output_err)?;
+        drop(writer);
+        partial.persist(replace)?;
+        Ok(())
+    }
+
+    fn open_segment(&self, segment: &Segment) -> Result<Box<dyn Read>> {
+        let (path, audio_format) = &self.sources[segment.source];
+        let length = segment.range.end - segment.range.start;
+
+        match audio_format {
+            // Compressed sources are decoded to CDDA and cut in memory
+            Some(format) => {
+                let size = audio::cdda_size(path, *format)?;
+                let mut pcm = Vec::with_capacity(size as usize);
+                audio::decode_to_cdda(path, *format, &mut pcm, size)?;
+                let end = (segment.range.end as usize).min(pcm.len());
+                let start = (segment.range.start as usize).min(end);
+                Ok(Box::new(Cursor::new(pcm[start..end].to_vec())))
+            }
+            None => {
+                let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+                file.seek(SeekFrom::Start(segment.range.start))
+                    .map_err(|e| Error::io(path, e))?;
+                Ok(Box::new(file.take(length)))
+            }
+        }
+    }
+}
+
+/// Output filename for a ripped track, using database titles when present
+pub fn track_filename(
+    number: u8,
+    game_id: Option<&str>,
+    db: Option<&TitleDb>,
+    format: RipFormat,
+) -> String {
+    let track_title = game_id
+        .zip(db)
+        .and_then(|(id, db)| db.track_title(id, number));
+    let game_title = game_id.zip(db).and_then(|(id, db)| db.game_title(id));
+
+    let stem = match (track_title, game_title) {
+        (Some(title), _) => format!("{:02} - {}", number, title),
+        (None, Some(game)) => format!("{} - Track {:02}", game, number),
+        (None, None) => format!("Track {:02}", number),
+    };
+    let stem: String = stem
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect();
+
+    format!("{}.{}", stem, format.extension())
+}
+
+/// Play time of a CDDA byte length, or None past 99:59:74
+pub fn duration(bytes: u64) -> Option<Msf> {
+    u32::try_from(bytes / SECTOR_SIZE as u64)
+        .ok()
+        .and_then(Msf::checked_from_sectors)
+}
+
+/// 44-byte RIFF header for 44.1 kHz 16-bit stereo PCM
+fn wav_header(data_len: u64) -> [u8; 44] {
+    let data_len = data_len as u32;
+    let mut header = [0u8; 44];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&(36 + data_len).to_le_bytes());
+    header[8..16].copy_from_slice(b"WAVEfmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&1u16.to_le_bytes()); // PCM
+    header[22..24].copy_from_slice(&2u16.to_le_bytes()); // stereo
+    header[24..28].copy_from_slice(&44_100u32.to_le_bytes());
+    header[28..32].copy_from_slice(&(44_100u32 * 4).to_le_bytes());
+    header[32..34].copy_from_slice(&4u16.to_le_bytes());
+    header[34..36].copy_from_slice(&16u16.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combiner::BinCombiner;
+    use crate::cue::{FileEntry, Track, TrackType};
+    use crate::synth::SyntheticDisc;
+    use crate::utils::Lba;
+    use crate::vcd::VcdConverter;
+
+    #[test]
+    fn test_pregap_modes() {
+        let mut file = FileEntry::new("disc.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2352, Lba::ZERO));
+        for (number, index00, index01) in [(2, 100, 102), (3, 200, 205)] {
+            let mut track = Track::new(number, TrackType::Audio, Lba::new(index01).unwrap());
+            track.index00 = Lba::new(index00);
+            file.tracks.push(track);
+        }
+        file.file_size = 300 * SECTOR_SIZE as u64;
+        let mut cue_sheet = CueSheet::new();
+        cue_sheet.files.push(file);
+
+        let layout = RipLayout::from_cue(&cue_sheet, Path::new("."));
+        let sectors = |idx, mode| layout.track_length(idx, mode) / SECTOR_SIZE as u64;
+
+        assert_eq!(sectors(1, PregapMode::Skip), 98);
+        assert_eq!(sectors(1, PregapMode::Prepend), 100);
+        assert_eq!(sectors(1, PregapMode::Append), 103);
+        assert_eq!(sectors(2, PregapMode::Skip), 95);
+        assert_eq!(sectors(2, PregapMode::Append), 95);
+    }
+
+    /// Rip every audio track of `disc` from its CUE and from the VCD
+    /// built from it, in each pregap mode
+    fn assert_vcd_rip_matches_cue(disc: &SyntheticDisc, name: &str) {
+        let dir = std::env::temp_dir().join(format!("rip_vcd_{}_{}", name, std::process::id()));
+        let cue_path = disc.write(&dir, name).unwrap();
+        let mut cue_sheet = CueSheet::parse(&cue_path).unwrap();
+        cue_sheet.load_file_sizes(&dir).unwrap();
+        let cue_layout = RipLayout::from_cue(&cue_sheet, &dir);
+
+        let combined = dir.join("combined.bin");
+        let vcd_path = dir.join(format!("{}.VCD", name));
+        BinCombiner::new(Vec::new(), false)
+            .combine(&mut cue_sheet, &dir, &combined)
+            .unwrap();
+        VcdConverter::new(false, false)
+            .convert_to_vcd(&combined, &vcd_path, &cue_sheet)
+            .unwrap();
+        let header = VcdHeader::read(&vcd_path).unwrap();
+        let vcd_layout = RipLayout::from_vcd(&vcd_path, &header).unwrap();
+
+        assert_eq!(vcd_layout.tracks.len(), cue_layout.tracks.len());
+        for idx in 1..cue_layout.tracks.len() {
+            for mode in [PregapMode::Skip, PregapMode::Prepend, PregapMode::Append] {
+                let rip = |layout: &RipLayout, side: &str| {
+                    let path = dir.join(format!("{}_{:?}_{}.wav", side, mode, idx));
+                    layout
+                        .write_track(idx, mode, RipFormat::Wav, &path, false)
+                        .unwrap();
+                    std::fs::read(path).unwrap()
+                };
+                assert!(
+                    rip(&cue_layout, "cue") == rip(&vcd_layout, "vcd"),
+                    "{}: track {:02} differs with {:?}",
+                    name,
+                    idx + 1,
+                    mode
+                );
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_vcd_rip_matches_cue() {
+        let mut disc = SyntheticDisc::new("SLUS_000.01");
+        disc.audio_tracks = vec!["tone:300".parse().unwrap(), "tone:200".parse().unwrap()];
+        assert_vcd_rip_matches_cue(&disc, "single");
+
+        disc.multi_file = true;
+        assert_vcd_rip_matches_cue(&disc, "multi");
+    }
+
+    #[test]
+    fn test_track_filename() {
+        let db = TitleDb::parse("SLUS-00067;Castle: Night\nSLUS-00067;02;Prologue\n").unwrap();
+        assert_eq!(
+            track_filename(2, Some("SLUS_000.67"), Some(&db), RipFormat::Flac),
+            "02 - Prologue.flac"
+        );
+        assert_eq!(
+            track_filename(3, Some("SLUS_000.67"), Some(&db), RipFormat::Wav),
+            "Castle_ Night - Track 03.wav"
+        );
+        assert_eq!(
+            track_filename(3, None, None, RipFormat::Wav),
+            "Track 03.wav"
+        );
+    }
+}
@@ -0,0 +1,349 @@
+// src/scan.rs
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::cue::{CueSheet, FileEntry, Track, TrackType};
+use crate::sector::{self, SECTOR_SIZE};
+use crate::utils::Lba;
+
+const BUFFER_SIZE: usize = 1024 * 1024; // 1MB buffer
+
+/// Standard 2-second pregap before an audio track
+const PREGAP_SECTORS: u32 = 150;
+
+/// Offset of the mode byte in a data sector header
+const MODE_OFFSET: usize = 15;
+
+/// How a sector looks to the scanner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SectorKind {
+    Mode1,
+    Mode2,
+    Silence,
+    Audio,
+}
+
+impl SectorKind {
+    fn is_data(self) -> bool {
+        matches!(self, SectorKind::Mode1 | SectorKind::Mode2)
+    }
+}
+
+/// How much a generated CUE can be trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Best-guess track layout of a bare BIN
+#[derive(Debug)]
+pub struct ScanReport {
+    pub cue_sheet: CueSheet,
+    pub confidence: Confidence,
+    /// Reasons the confidence was lowered
+    pub notes: Vec<String>,
+    pub data_sectors: u32,
+    pub audio_sectors: u32,
+}
+
+impl ScanReport {
+    fn lower(&mut self, confidence: Confidence, note: String) {
+        self.confidence = self.confidence.min(confidence);
+        self.notes.push(note);
+    }
+}
+
+/// Analyze a BIN without a CUE and guess its tracks
+///
+/// Every 2352-byte sector is classified by its sync pattern and header
+/// mode. Runs of data sectors become data tracks; audio between them is
+/// split wherever at least two seconds of digital silence precede sound,
+/// placing INDEX 00 two seconds before INDEX 01. The MSF stamped in data
+/// sector headers is checked against the sector position to tell whether
+/// the image is a complete disc.
+pub fn scan_bin(bin_path: &Path) -> Result<ScanReport> {
+    let file =
+        File::open(bin_path).with_context(|| format!("Failed to open: {}", bin_path.display()))?;
+    let file_size = file.metadata()?.len();
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, file);
+
+    let mut kinds = Vec::with_capacity((file_size / SECTOR_SIZE as u64) as usize);
+    let mut msf_mismatches = 0u32;
+    let mut first_header = None;
+    let mut sector_buf = vec![0u8; SECTOR_SIZE];
+
+    while read_sector(&mut reader, &mut sector_buf)? {
+        let lba = position(kinds.len() as u32)?;
+        let kind = classify(&sector_buf);
+        if kind.is_data() {
+            let header = [sector_buf[12], sector_buf[13], sector_buf[14]];
+            first_header.get_or_insert((lba, header));
+            if header != lba.to_bcd() {
+                msf_mismatches += 1;
+            }
+        }
+        kinds.push(kind);
+    }
+
+    let data_sectors = kinds.iter().filter(|k| k.is_data()).count() as u32;
+    let mut report = ScanReport {
+        cue_sheet: CueSheet::new(),
+        confidence: Confidence::High,
+        notes: Vec::new(),
+        data_sectors,
+        audio_sectors: kinds.len() as u32 - data_sectors,
+    };
+
+    let filename = bin_path
+        .file_name()
+        .context("Invalid BIN filename")?
+        .to_string_lossy()
+        .into_owned();
+    let mut entry = FileEntry::new(filename, "BINARY".to_string());
+    entry.file_size = file_size;
+
+    if kinds.is_empty() {
+        anyhow::bail!("{} is smaller than one sector", bin_path.display());
+    }
+    if file_size % SECTOR_SIZE as u64 != 0 {
+        report.lower(
+            Confidence::Low,
+            format!(
+                "file size is not a multiple of {} bytes ({} trailing bytes)",
+                SECTOR_SIZE,
+                file_size % SECTOR_SIZE as u64
+            ),
+        );
+    }
+    if data_sectors == 0 {
+        report.lower(
+            Confidence::Low,
+            "no data sectors found: not a PlayStation data image".to_string(),
+        );
+    }
+    if let Some((lba, header)) = first_header {
+        if Lba::from_bcd(header) != Some(lba) {
+            report.lower(
+                Confidence::Low,
+                "data sector headers do not start at 00:02:00: image is not a complete disc"
+                    .to_string(),
+            );
+        } else if msf_mismatches > 0 {
+            let confidence = if msf_mismatches * 100 > data_sectors {
+                Confidence::Low
+            } else {
+                Confidence::Medium
+            };
+            report.lower(
+                confidence,
+                format!(
+                    "{} data sector(s) carry an unexpected header MSF",
+                    msf_mismatches
+                ),
+            );
+        }
+    }
+
+    let mut guessed_audio_splits = 0;
+    let mut run_start = 0usize;
+    while run_start < kinds.len() {
+        let is_data = kinds[run_start].is_data();
+        let run_end = (run_start..kinds.len())
+            .find(|&i| kinds[i].is_data() != is_data)
+            .unwrap_or(kinds.len());
+        let number = (entry.tracks.len() + 1) as u8;
+
+        if is_data {
+            let mode2 = kinds[run_start..run_end]
+                .iter()
+                .filter(|&&k| k == SectorKind::Mode2)
+                .count();
+            let track_type = if mode2 * 2 >= run_end - run_start {
+                TrackType::Mode2_2352
+            } else {
+                TrackType::Mode1_2352
+            };
+            entry
+                .tracks
+                .push(Track::new(number, track_type, position(run_start as u32)?));
+        } else {
+            guessed_audio_splits +=
+                push_audio_tracks(&mut entry, &kinds[run_start..run_end], run_start as u32)?;
+        }
+        run_start = run_end;
+    }
+
+    if guessed_audio_splits > 0 {
+        report.lower(
+            Confidence::Medium,
+            format!(
+                "{} audio track boundary(ies) guessed from silence; tracks without a 2-second gap are merged",
+                guessed_audio_splits
+            ),
+        );
+    }
+    if entry.tracks.len() > 99 {
+        report.lower(
+            Confidence::Low,
+            format!(
+                "{} tracks found, more than a CD can hold",
+                entry.tracks.len()
+            ),
+        );
+        entry.tracks.truncate(99);
+    }
+
+    report.cue_sheet.files.push(entry);
+    Ok(report)
+}
+
+/// Split a run of audio sectors into tracks at 2-second silences
+///
+/// Returns the number of boundaries found inside the run.
+fn push_audio_tracks(entry: &mut FileEntry, kinds: &[SectorKind], start: u32) -> Result<usize> {
+    let mut boundaries = Vec::new();
+
+    // The first track's pregap is the silence it starts with
+    let lead = kinds
+        .iter()
+        .take_while(|&&k| k == SectorKind::Silence)
+        .count() as u32;
+    boundaries.push(lead.min(PREGAP_SECTORS));
+
+    let mut i = lead as usize;
+    while i < kinds.len() {
+        if kinds[i] != SectorKind::Silence {
+            i += 1;
+            continue;
+        }
+        let silence_end = (i..kinds.len())
+            .find(|&j| kinds[j] != SectorKind::Silence)
+            .unwrap_or(kinds.len());
+        if silence_end < kinds.len() && (silence_end - i) as u32 >= PREGAP_SECTORS {
+            boundaries.push(silence_end as u32);
+        }
+        i = silence_end;
+    }
+
+    let splits = boundaries.len() - 1;
+    for (idx, &index01) in boundaries.iter().enumerate() {
+        let number = (entry.tracks.len() + 1) as u8;
+        let mut track = Track::new(number, TrackType::Audio, position(start + index01)?);
+        let index00 = if idx == 0 {
+            0
+        } else {
+            index01 - PREGAP_SECTORS
+        };
+        if index00 < index01 {
+            track.index00 = Some(position(start + index00)?);
+        }
+        entry.tracks.push(track);
+    }
+
+    // A disc starting with audio has no pregap before track 1
+    if start == 0 {
+        let first = &mut entry.tracks[0];
+        first.index00 = None;
+        first.index01 = Lba::ZERO;
+    }
+
+    Ok(splits)
+}
+
+/// Position of a sector, which must lie within 99:59:74 on disc
+fn position(sector: u32) -> Result<Lba> {
+    Lba::new(sector).with_context(|| {
+        format!(
+            "sector {} is past 99:59:74, the end of what a CD can address",
+            sector
+        )
+    })
+}
+
+fn classify(sector: &[u8]) -> SectorKind {
+    if sector::has_sync(sector) {
+        match sector[MODE_OFFSET] {
+            1 => return SectorKind::Mode1,
+            2 => return SectorKind::Mode2,
+            _ => {}
+        }
+    }
+    if sector.iter().all(|&b| b == 0) {
+        SectorKind::Silence
+    } else {
+        SectorKind::Audio
+    }
+}
+
+/// Read a whole sector, returning false at end of file
+fn read_sector<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => return Ok(false),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_data_and_audio() {
+        let mut image = Vec::new();
+        for lba in 0..300u32 {
+            let mut sector = [0u8; SECTOR_SIZE];
+            sector[..12].copy_from_slice(&sector::SYNC_PATTERN);
+            sector[12..15].copy_from_slice(&Lba::new(lba).unwrap().to_bcd());
+            sector[15] = 2;
+            image.extend_from_slice(&sector);
+        }
+        // Track 2: pregap silence then tone; track 3 after a 3-second gap
+        let tone = [0x11u8; SECTOR_SIZE];
+        let silence = [0u8; SECTOR_SIZE];
+        for part in [(&silence, 150), (&tone, 100), (&silence, 225), (&tone, 50)] {
+            for _ in 0..part.1 {
+                image.extend_from_slice(part.0);
+            }
+        }
+
+        let path = std::env::temp_dir().join("psx-vcd-scan.bin");
+        std::fs::write(&path, &image).unwrap();
+        let report = scan_bin(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let tracks = &report.cue_sheet.files[0].tracks;
+        let layout: Vec<_> = tracks
+            .iter()
+            .map(|t| (t.track_type, t.index00.map(Lba::get), t.index01.get()))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (TrackType::Mode2_2352, None, 0),
+                (TrackType::Audio, Some(300), 450),
+                (TrackType::Audio, Some(625), 775),
+            ]
+        );
+        assert_eq!(report.confidence, Confidence::Medium);
+        assert_eq!(report.data_sectors, 300);
+    }
+}
@@ -0,0 +1,364 @@
+// src/sector.rs
+use crate::cue::TrackType;
+use crate::utils::Lba;
+
+/// Raw CD-ROM sector size
+pub const SECTOR_SIZE: usize = 2352;
+
+/// Sync pattern at the start of every data sector
+pub const SYNC_PATTERN: [u8; 12] = [
+    0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00,
+];
+
+// Sector layout offsets
+const HEADER_OFFSET: usize = 12;
+const MODE_OFFSET: usize = 15;
+const SUBHEADER_OFFSET: usize = 16;
+const MODE1_EDC_OFFSET: usize = 0x810;
+const MODE2_FORM1_EDC_OFFSET: usize = 0x818;
+const MODE2_FORM2_EDC_OFFSET: usize = 0x92C;
+const ECC_P_OFFSET: usize = 0x81C;
+const ECC_Q_OFFSET: usize = 0x8C8;
+const USER_DATA_OFFSET: usize = 24;
+
+// Subheader used for MODE1/2048 data upgraded to Mode 2 Form 1 (submode: data)
+const FORM1_DATA_SUBHEADER: [u8; 8] = [0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x08, 0x00];
+
+const EDC_TABLE: [u32; 256] = build_edc_table();
+const ECC_F_TABLE: [u8; 256] = build_ecc_tables().0;
+const ECC_B_TABLE: [u8; 256] = build_ecc_tables().1;
+
+const fn build_edc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut edc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            edc = (edc >> 1) ^ if edc & 1 != 0 { 0xD801_8001 } else { 0 };
+            bit += 1;
+        }
+        table[i] = edc;
+        i += 1;
+    }
+    table
+}
+
+const fn build_ecc_tables() -> ([u8; 256], [u8; 256]) {
+    let mut forward = [0u8; 256];
+    let mut backward = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        let j = (i << 1) ^ if i & 0x80 != 0 { 0x11D } else { 0 };
+        forward[i] = j as u8;
+        backward[i ^ j] = i as u8;
+        i += 1;
+    }
+    (forward, backward)
+}
+
+/// Sector verification failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectorError {
+    /// Sync pattern missing or damaged
+    BadSync,
+    /// Header MSF does not match the sector position
+    BadHeaderMsf { found: [u8; 3] },
+    /// Mode byte is not 1 or 2
+    BadMode(u8),
+    /// The two copies of the Mode 2 subheader differ
+    SubheaderMismatch,
+    /// Error detection code mismatch
+    BadEdc,
+    /// Error correction code mismatch
+    BadEcc,
+}
+
+impl std::fmt::Display for SectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SectorError::BadSync => write!(f, "bad sync pattern"),
+            SectorError::BadHeaderMsf { found } => write!(
+                f,
+                "header MSF {:02X}:{:02X}:{:02X} does not match position",
+                found[0], found[1], found[2]
+            ),
+            SectorError::BadMode(mode) => write!(f, "invalid mode byte {:#04x}", mode),
+            SectorError::SubheaderMismatch => write!(f, "subheader copies differ"),
+            SectorError::BadEdc => write!(f, "EDC mismatch"),
+            SectorError::BadEcc => write!(f, "ECC mismatch"),
+        }
+    }
+}
+
+/// Compute the CD-ROM EDC (CRC-32 variant) over a byte range
+pub fn edc(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |edc, &byte| {
+        (edc >> 8) ^ EDC_TABLE[((edc ^ byte as u32) & 0xFF) as usize]
+    })
+}
+
+/// Compute one Reed-Solomon product code (P or Q parity)
+fn ecc_block(
+    src: &[u8],
+    major_count: usize,
+    minor_count: usize,
+    major_mult: usize,
+    minor_inc: usize,
+    dest: &mut [u8],
+) {
+    let size = major_count * minor_count;
+    for major in 0..major_count {
+        let mut index = (major >> 1) * major_mult + (major & 1);
+        let mut ecc_a = 0u8;
+        let mut ecc_b = 0u8;
+        for _ in 0..minor_count {
+            let temp = src[index];
+            index += minor_inc;
+            if index >= size {
+                index -= size;
+            }
+            ecc_a ^= temp;
+            ecc_b ^= temp;
+            ecc_a = ECC_F_TABLE[ecc_a as usize];
+        }
+        ecc_a = ECC_B_TABLE[(ECC_F_TABLE[ecc_a as usize] ^ ecc_b) as usize];
+        dest[major] = ecc_a;
+        dest[major + major_count] = ecc_a ^ ecc_b;
+    }
+}
+
+/// Compute P and Q parity (276 bytes) for a sector
+///
+/// Mode 2 Form 1 sectors compute ECC with the header address zeroed.
+pub fn compute_ecc(sector: &[u8], zero_address: bool) -> [u8; 276] {
+    let mut buffer = [0u8; SECTOR_SIZE - HEADER_OFFSET];
+    buffer.copy_from_slice(&sector[HEADER_OFFSET..SECTOR_SIZE]);
+    if zero_address {
+        buffer[..4].fill(0);
+    }
+
+    let p_start = ECC_P_OFFSET - HEADER_OFFSET;
+    let q_start = ECC_Q_OFFSET - HEADER_OFFSET;
+
+    let mut parity = [0u8; 276];
+    ecc_block(&buffer, 86, 24, 2, 86, &mut parity[..172]);
+    buffer[p_start..q_start].copy_from_slice(&parity[..172]);
+    ecc_block(&buffer, 52, 43, 86, 88, &mut parity[172..]);
+
+    parity
+}
+
+/// Check whether a sector starts with the data sync pattern
+pub fn has_sync(sector: &[u8]) -> bool {
+    sector.len() >= SYNC_PATTERN.len() && sector[..SYNC_PATTERN.len()] == SYNC_PATTERN
+}
+
+/// Verify a raw 2352-byte data sector located at `lba`
+///
+/// Checks the sync pattern, header MSF and mode, then the Mode 2 subheader
+/// and EDC/ECC (Form 1) or EDC (Form 2, when present). Mode 1 sectors are
+/// checked for EDC and ECC.
+pub fn verify_sector(sector: &[u8], lba: Lba) -> Result<(), SectorError> {
+    if !has_sync(sector) {
+        return Err(SectorError::BadSync);
+    }
+
+    let found = [
+        sector[HEADER_OFFSET],
+        sector[HEADER_OFFSET + 1],
+        sector[HEADER_OFFSET + 2],
+    ];
+    if found != lba.to_bcd() {
+        return Err(SectorError::BadHeaderMsf { found });
+    }
+
+    match sector[MODE_OFFSET] {
+        1 => {
+            check_edc(sector, 0, MODE1_EDC_OFFSET)?;
+            check_ecc(sector, false)
+        }
+        2 => {
+            let subheader = &sector[SUBHEADER_OFFSET..SUBHEADER_OFFSET + 8];
+            if subheader[..4] != subheader[4..] {
+                return Err(SectorError::SubheaderMismatch);
+            }
+
+            if is_form2(sector) {
+                // Form 2 EDC is optional: zero means "not present"
+                if read_edc(sector, MODE2_FORM2_EDC_OFFSET) != 0 {
+                    check_edc(sector, SUBHEADER_OFFSET, MODE2_FORM2_EDC_OFFSET)?;
+                }
+                Ok(())
+            } else {
+                check_edc(sector, SUBHEADER_OFFSET, MODE2_FORM1_EDC_OFFSET)?;
+                check_ecc(sector, true)
+            }
+        }
+        mode => Err(SectorError::BadMode(mode)),
+    }
+}
+
+/// Rebuild a raw 2352-byte sector from a data sector of any track type
+///
+/// Sync, header MSF (from `lba`), EDC and ECC are regenerated; only the
+/// subheader and user data of the input are kept. MODE2/2336 and MODE1/2048
+/// input is expanded to a full Mode 2 sector. Audio sectors are returned
+/// unchanged.
+pub fn rebuild_sector(input: &[u8], track_type: TrackType, lba: Lba) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+
+    match track_type {
+        TrackType::Audio => {
+            sector.copy_from_slice(&input[..SECTOR_SIZE]);
+            return sector;
+        }
+        TrackType::Mode1_2352 => {
+            sector[SUBHEADER_OFFSET..MODE1_EDC_OFFSET]
+                .copy_from_slice(&input[SUBHEADER_OFFSET..MODE1_EDC_OFFSET]);
+        }
+        TrackType::Mode2_2352 => {
+            sector[SUBHEADER_OFFSET..].copy_from_slice(&input[SUBHEADER_OFFSET..SECTOR_SIZE]);
+        }
+        TrackType::Mode2_2336 => {
+            sector[SUBHEADER_OFFSET..].copy_from_slice(&input[..SECTOR_SIZE - SUBHEADER_OFFSET]);
+        }
+        TrackType::Mode1_2048 => {
+            sector[SUBHEADER_OFFSET..USER_DATA_OFFSET].copy_from_slice(&FORM1_DATA_SUBHEADER);
+            sector[USER_DATA_OFFSET..MODE2_FORM1_EDC_OFFSET].copy_from_slice(&input[..2048]);
+        }
+    }
+
+    sector[..SYNC_PATTERN.len()].copy_from_slice(&SYNC_PATTERN);
+    sector[HEADER_OFFSET..MODE_OFFSET].copy_from_slice(&lba.to_bcd());
+
+    if track_type == TrackType::Mode1_2352 {
+        sector[MODE_OFFSET] = 1;
+        write_edc(&mut sector, 0, MODE1_EDC_OFFSET);
+        sector[MODE1_EDC_OFFSET + 4..ECC_P_OFFSET].fill(0);
+        let parity = compute_ecc(&sector, false);
+        sector[ECC_P_OFFSET..].copy_from_slice(&parity);
+    } else {
+        sector[MODE_OFFSET] = 2;
+        if is_form2(&sector) {
+            write_edc(&mut sector, SUBHEADER_OFFSET, MODE2_FORM2_EDC_OFFSET);
+        } else {
+            write_edc(&mut sector, SUBHEADER_OFFSET, MODE2_FORM1_EDC_OFFSET);
+            let parity = compute_ecc(&sector, true);
+            sector[ECC_P_OFFSET..].copy_from_slice(&parity);
+        }
+    }
+
+    sector
+}
+
+fn write_edc(sector: &mut [u8], start: usize, edc_offset: usize) {
+    let value = edc(&sector[start..edc_offset]);
+    sector[edc_offset..edc_offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Mode 2 submode bit 5 selects Form 2
+fn is_form2(sector: &[u8]) -> bool {
+    sector[SUBHEADER_OFFSET + 2] & 0x20 != 0
+}
+
+fn read_edc(sector: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        sector[offset],
+        sector[offset + 1],
+        sector[offset + 2],
+        sector[offset + 3],
+    ])
+}
+
+fn check_edc(sector: &[u8], start: usize, edc_offset: usize) -> Result<(), SectorError> {
+    if edc(&sector[start..edc_offset]) == read_edc(sector, edc_offset) {
+        Ok(())
+    } else {
+        Err(SectorError::BadEdc)
+    }
+}
+
+fn check_ecc(sector: &[u8], zero_address: bool) -> Result<(), SectorError> {
+    if compute_ecc(sector, zero_address)[..] == sector[ECC_P_OFFSET..SECTOR_SIZE] {
+        Ok(())
+    } else {
+        Err(SectorError::BadEcc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form1_sector(lba: u32) -> Vec<u8> {
+        let lba = Lba::new(lba).unwrap();
+        let mut sector = vec![0u8; SECTOR_SIZE];
+        sector[..12].copy_from_slice(&SYNC_PATTERN);
+        sector[12..15].copy_from_slice(&lba.to_bcd());
+        sector[15] = 2;
+        sector[24..2072]
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8);
+
+        let edc_value = edc(&sector[16..0x818]);
+        sector[0x818..0x81C].copy_from_slice(&edc_value.to_le_bytes());
+        let parity = compute_ecc(&sector, true);
+        sector[0x81C..].copy_from_slice(&parity);
+        sector
+    }
+
+    #[test]
+    fn test_verify_form1_sector() {
+        let sector = form1_sector(16);
+        assert_eq!(verify_sector(&sector, Lba::new(16).unwrap()), Ok(()));
+        assert!(matches!(
+            verify_sector(&sector, Lba::new(17).unwrap()),
+            Err(SectorError::BadHeaderMsf { .. })
+        ));
+    }
+
+    #[test]
+    fn test_detect_corruption() {
+        let mut sector = form1_sector(200);
+        sector[1000] ^= 0x01;
+        assert_eq!(
+            verify_sector(&sector, Lba::new(200).unwrap()),
+            Err(SectorError::BadEdc)
+        );
+
+        let mut sector = form1_sector(200);
+        sector[0x8D0] ^= 0x01;
+        assert_eq!(
+            verify_sector(&sector, Lba::new(200).unwrap()),
+            Err(SectorError::BadEcc)
+        );
+
+        let mut sector = form1_sector(200);
+        sector[0] = 0xFF;
+        assert_eq!(
+            verify_sector(&sector, Lba::new(200).unwrap()),
+            Err(SectorError::BadSync)
+        );
+    }
+
+    #[test]
+    fn test_rebuild_sector() {
+        // Zeroed ECC and a wrong header are regenerated
+        let mut damaged = form1_sector(300);
+        damaged[12..15].copy_from_slice(&[0, 0, 0]);
+        damaged[0x81C..].fill(0);
+        let rebuilt = rebuild_sector(&damaged, TrackType::Mode2_2352, Lba::new(300).unwrap());
+        assert_eq!(rebuilt[..], form1_sector(300)[..]);
+
+        // Stripped sectors are expanded to full Mode 2 sectors
+        let stripped = &form1_sector(42)[16..];
+        let rebuilt = rebuild_sector(stripped, TrackType::Mode2_2336, Lba::new(42).unwrap());
+        assert_eq!(verify_sector(&rebuilt, Lba::new(42).unwrap()), Ok(()));
+
+        let rebuilt = rebuild_sector(&[0x55; 2048], TrackType::Mode1_2048, Lba::new(7).unwrap());
+        assert_eq!(verify_sector(&rebuilt, Lba::new(7).unwrap()), Ok(()));
+        assert_eq!(rebuilt[15], 2);
+    }
+}
@@ -0,0 +1,392 @@
+// src/synth.rs
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cue::{CueSheet, FileEntry, Track, TrackType};
+use crate::sector::{self, SECTOR_SIZE};
+use crate::utils::Lba;
+
+/// User data bytes of a Mode 2 Form 1 sector
+const FORM1_DATA_SIZE: usize = 2048;
+
+/// Standard 2-second pregap before each audio track
+const PREGAP_SECTORS: u32 = 150;
+
+// Fixed ISO9660 layout of the synthetic data track
+const PVD_SECTOR: u32 = 16;
+const TERMINATOR_SECTOR: u32 = 17;
+const L_PATH_TABLE_SECTOR: u32 = 18;
+const M_PATH_TABLE_SECTOR: u32 = 19;
+const ROOT_DIR_SECTOR: u32 = 22;
+const SYSTEM_CNF_SECTOR: u32 = 23;
+const EXE_SECTOR: u32 = 24;
+const MIN_DATA_SECTORS: u32 = EXE_SECTOR + 1;
+
+// Mode 2 submode flags
+const SUBMODE_DATA: u8 = 0x08;
+const SUBMODE_EOR: u8 = 0x01;
+const SUBMODE_EOF: u8 = 0x80;
+
+/// Content of a synthetic audio track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioContent {
+    Silence,
+    /// 440 Hz sine on both channels
+    Tone,
+}
+
+/// A synthetic audio track, written as `silence:SECTORS` or `tone:SECTORS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioTrackSpec {
+    pub content: AudioContent,
+    /// Length without the 2-second pregap
+    pub sectors: u32,
+}
+
+impl FromStr for AudioTrackSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (content, sectors) = s
+            .split_once(':')
+            .context("expected silence:SECTORS or tone:SECTORS")?;
+        let content = match content.to_ascii_lowercase().as_str() {
+            "silence" => AudioContent::Silence,
+            "tone" => AudioContent::Tone,
+            other => bail!("unknown audio content '{}' (silence or tone)", other),
+        };
+        let sectors = sectors
+            .parse()
+            .with_context(|| format!("invalid sector count '{}'", sectors))?;
+        if sectors == 0 {
+            bail!("audio track needs at least one sector");
+        }
+        Ok(Self { content, sectors })
+    }
+}
+
+/// Description of a synthetic PlayStation disc image
+///
+/// The data track is a valid MODE2/2352 track (sync, header, subheader,
+/// EDC/ECC) holding a minimal ISO9660 filesystem with `SYSTEM.CNF` and a
+/// stub executable named after the serial. Audio tracks follow with a
+/// silent 2-second pregap.
+#[derive(Debug, Clone)]
+pub struct SyntheticDisc {
+    /// Serial in `SLUS_000.01` form
+    pub serial: String,
+    pub data_sectors: u32,
+    pub audio_tracks: Vec<AudioTrackSpec>,
+    /// One BIN per track instead of a single BIN
+    pub multi_file: bool,
+}
+
+impl SyntheticDisc {
+    pub fn new(serial: &str) -> Self {
+        Self {
+            serial: serial.to_string(),
+            data_sectors: 300,
+            audio_tracks: Vec::new(),
+            multi_file: false,
+        }
+    }
+
+    /// Write the BIN file(s) and CUE into `dir`, returning the CUE path
+    pub fn write(&self, dir: &Path, name: &str) -> Result<PathBuf> {
+        if self.data_sectors < MIN_DATA_SECTORS {
+            bail!(
+                "data track needs at least {} sectors for its filesystem",
+                MIN_DATA_SECTORS
+            );
+        }
+        if self.audio_tracks.len() > 98 {
+            bail!("a disc holds at most 99 tracks");
+        }
+        std::fs::create_dir_all(dir)?;
+
+        let mut cue_sheet = CueSheet::new();
+        let single_name = format!("{}.bin", name);
+        let track_name = |number: u8| format!("{} (Track {}).bin", name, number);
+
+        // Track 01: data
+        let data_name = if self.multi_file {
+            track_name(1)
+        } else {
+            single_name.clone()
+        };
+        let mut writer = create(&dir.join(&data_name))?;
+        for sector in 0..self.data_sectors {
+            let lba = Lba::new(sector).context("data track extends past 99:59:74")?;
+            writer.write_all(&self.data_sector(lba))?;
+        }
+
+        let mut file = FileEntry::new(data_name, "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2352, Lba::ZERO));
+
+        // Audio tracks, each preceded by a silent pregap
+        let mut position = self.data_sectors;
+        let mut sample = 0u64;
+        for (idx, spec) in self.audio_tracks.iter().enumerate() {
+            let number = idx as u8 + 2;
+            if self.multi_file {
+                writer.flush()?;
+                cue_sheet.files.push(file);
+                file = FileEntry::new(track_name(number), "BINARY".to_string());
+                writer = create(&dir.join(&file.filename))?;
+                position = 0;
+            }
+
+            let lba = |sector: u32| {
+                Lba::new(sector).with_context(|| format!("track {} starts past 99:59:74", number))
+            };
+            let mut track = Track::new(number, TrackType::Audio, lba(position + PREGAP_SECTORS)?);
+            track.index00 = Some(lba(position)?);
+            file.tracks.push(track);
+
+            let silence = [0u8; SECTOR_SIZE];
+            for _ in 0..PREGAP_SECTORS {
+                writer.write_all(&silence)?;
+            }
+            for _ in 0..spec.sectors {
+                match spec.content {
+                    AudioContent::Silence => writer.write_all(&silence)?,
+                    AudioContent::Tone => writer.write_all(&tone_sector(&mut sample))?,
+                }
+            }
+            position += PREGAP_SECTORS + spec.sectors;
+        }
+        writer.flush()?;
+        cue_sheet.files.push(file);
+
+        let cue_path = dir.join(format!("{}.cue", name));
+        std::fs::write(&cue_path, cue_sheet.to_cue_text())
+            .with_context(|| format!("Failed to write: {}", cue_path.display()))?;
+        Ok(cue_path)
+    }
+
+    /// Raw sector `lba` of the data track
+    fn data_sector(&self, lba: Lba) -> [u8; SECTOR_SIZE] {
+        let mut data = [0u8; FORM1_DATA_SIZE];
+        let mut submode = SUBMODE_DATA;
+
+        match lba.get() {
+            PVD_SECTOR => self.primary_volume_descriptor(&mut data),
+            TERMINATOR_SECTOR => {
+                data[0] = 0xFF;
+                data[1..6].copy_from_slice(b"CD001");
+                data[6] = 1;
+                submode |= SUBMODE_EOR | SUBMODE_EOF;
+            }
+            L_PATH_TABLE_SECTOR | M_PATH_TABLE_SECTOR => {
+                let big_endian = lba.get() == M_PATH_TABLE_SECTOR;
+                data[0] = 1; // name length
+                let location = if big_endian {
+                    ROOT_DIR_SECTOR.to_be_bytes()
+                } else {
+                    ROOT_DIR_SECTOR.to_le_bytes()
+                };
+                data[2..6].copy_from_slice(&location);
+                data[6..8].copy_from_slice(&if big_endian {
+                    1u16.to_be_bytes()
+                } else {
+                    1u16.to_le_bytes()
+                });
+            }
+            ROOT_DIR_SECTOR => {
+                let mut offset = 0;
+                offset += directory_record(&mut data[offset..], ROOT_DIR_SECTOR, 2048, 0x02, &[0]);
+                offset += directory_record(&mut data[offset..], ROOT_DIR_SECTOR, 2048, 0x02, &[1]);
+                offset += directory_record(
+                    &mut data[offset..],
+                    SYSTEM_CNF_SECTOR,
+                    self.system_cnf().len() as u32,
+                    0,
+                    b"SYSTEM.CNF;1",
+                );
+                let exe_name = format!("{};1", self.serial);
+                directory_record(
+                    &mut data[offset..],
+                    EXE_SECTOR,
+                    FORM1_DATA_SIZE as u32,
+                    0,
+                    exe_name.as_bytes(),
+                );
+                submode |= SUBMODE_EOR | SUBMODE_EOF;
+            }
+            SYSTEM_CNF_SECTOR => {
+                let cnf = self.system_cnf();
+                data[..cnf.len()].copy_from_slice(cnf.as_bytes());
+                submode |= SUBMODE_EOR | SUBMODE_EOF;
+            }
+            EXE_SECTOR => {
+                data[..8].copy_from_slice(b"PS-X EXE");
+                submode |= SUBMODE_EOR | SUBMODE_EOF;
+            }
+            _ => {}
+        }
+
+        let mut raw = [0u8; SECTOR_SIZE];
+        let subheader = [0, 0, submode, 0];
+        raw[16..20].copy_from_slice(&subheader);
+        raw[20..24].copy_from_slice(&subheader);
+        raw[24..24 + FORM1_DATA_SIZE].copy_from_slice(&data);
+        sector::rebuild_sector(&raw, TrackType::Mode2_2352, lba)
+    }
+
+    fn system_cnf(&self) -> String {
+        format!(
+            "BOOT = cdrom:\\{};1\r\nTCB = 4\r\nEVENT = 10\r\nSTACK = 801FFF00\r\n",
+            self.serial
+        )
+    }
+
+    fn primary_volume_descriptor(&self, data: &mut [u8]) {
+        data[0] = 1;
+        data[1..6].copy_from_slice(b"CD001");
+        data[6] = 1;
+        pad(&mut data[8..40], b"PLAYSTATION");
+        pad(&mut data[40..72], b"PSX_VCD_SYNTHETIC");
+        both_endian_u32(&mut data[80..88], self.data_sectors);
+        both_endian_u16(&mut data[120..124], 1); // volume set size
+        both_endian_u16(&mut data[124..128], 1); // volume sequence number
+        both_endian_u16(&mut data[128..132], FORM1_DATA_SIZE as u16);
+        both_endian_u32(&mut data[132..140], 10); // path table size
+        data[140..144].copy_from_slice(&L_PATH_TABLE_SECTOR.to_le_bytes());
+        data[148..152].copy_from_slice(&M_PATH_TABLE_SECTOR.to_be_bytes());
+        directory_record(&mut data[156..190], ROOT_DIR_SECTOR, 2048, 0x02, &[0]);
+        for field in [
+            190..318,
+            318..446,
+            446..574,
+            574..702,
+            702..739,
+            739..776,
+            776..813,
+        ] {
+            data[field].fill(b' ');
+        }
+        for field in [813..829, 830..846, 847..863, 864..880] {
+            data[field].fill(b'0');
+        }
+        data[881] = 1; // file structure version
+    }
+}
+
+/// Write an ISO9660 directory record, returning its length
+fn directory_record(out: &mut [u8], extent: u32, size: u32, flags: u8, name: &[u8]) -> usize {
+    let length = 33 + name.len() + (name.len() + 1) % 2;
+    out[0] = length as u8;
+    both_endian_u32(&mut out[2..10], extent);
+    both_endian_u32(&mut out[10..18], size);
+    out[18..25].copy_from_slice(&[95, 1, 1, 0, 0, 0, 0]); // 1995-01-01
+    out[25] = flags;
+    both_endian_u16(&mut out[28..32], 1);
+    out[32] = name.len() as u8;
+    out[33..33 + name.len()].copy_from_slice(name);
+    length
+}
+
+fn both_endian_u32(out: &mut [u8], value: u32) {
+    out[..4].copy_from_slice(&value.to_le_bytes());
+    out[4..8].copy_from_slice(&value.to_be_bytes());
+}
+
+fn both_endian_u16(out: &mut [u8], value: u16) {
+    out[..2].copy_from_slice(&value.to_le_bytes());
+    out[2..4].copy_from_slice(&value.to_be_bytes());
+}
+
+fn pad(out: &mut [u8], text: &[u8]) {
+    out.fill(b' ');
+    out[..text.len()].copy_from_slice(text);
+}
+
+/// One sector of a 440 Hz stereo sine, continuing from `sample`
+fn tone_sector(sample: &mut u64) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    for frame in sector.chunks_exact_mut(4) {
+        let t = *sample as f64 / 44_100.0;
+        let value = ((t * 440.0 * std::f64::consts::TAU).sin() * 8000.0) as i16;
+        frame[..2].copy_from_slice(&value.to_le_bytes());
+        frame[2..].copy_from_slice(&value.to_le_bytes());
+        *sample += 1;
+    }
+    sector
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create: {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scan, utils, verify};
+
+    #[test]
+    fn test_synthetic_disc_is_valid() {
+        let dir = std::env::temp_dir().join("psx-vcd-synth");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut disc = SyntheticDisc::new("SLUS_012.34");
+        disc.audio_tracks.push("tone:200".parse().unwrap());
+        disc.audio_tracks.push("silence:100".parse().unwrap());
+        let cue_path = disc.write(&dir, "Synthetic").unwrap();
+
+        let mut cue_sheet = CueSheet::parse(&cue_path).unwrap();
+        cue_sheet.load_file_sizes(&dir).unwrap();
+        assert_eq!(cue_sheet.get_total_tracks(), 3);
+
+        let report = verify::verify_cue(&cue_sheet, &dir).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.sectors_checked, 300);
+        assert!(verify::check_track_types(&cue_sheet, &dir)
+            .unwrap()
+            .is_empty());
+
+        let bin = dir.join("Synthetic.bin");
+        assert_eq!(
+            utils::detect_game_id(&bin).unwrap().as_deref(),
+            Some("SLUS_012.34")
+        );
+
+        // The scanner finds the data track and the tone track; the silent
+        // track cannot be told apart from its pregap
+        let scanned = scan::scan_bin(&bin).unwrap();
+        let tracks = &scanned.cue_sheet.files[0].tracks;
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[1].index01, cue_sheet.files[0].tracks[1].index01);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_multi_file_layout() {
+        let dir = std::env::temp_dir().join("psx-vcd-synth-multi");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut disc = SyntheticDisc::new("SCES_000.01");
+        disc.multi_file = true;
+        disc.audio_tracks.push("tone:75".parse().unwrap());
+        let cue_path = disc.write(&dir, "Multi").unwrap();
+
+        let cue_sheet = CueSheet::parse(&cue_path).unwrap();
+        assert_eq!(cue_sheet.files.len(), 2);
+        assert_eq!(cue_sheet.files[1].filename, "Multi (Track 2).bin");
+        assert_eq!(
+            std::fs::metadata(dir.join("Multi (Track 2).bin"))
+                .unwrap()
+                .len(),
+            225 * SECTOR_SIZE as u64
+        );
+        assert!("noise:10".parse::<AudioTrackSpec>().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+// src/titledb.rs
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::utils::normalize_serial;
+
+/// Game and track titles keyed by serial
+///
+/// The database is a plain text file, one entry per line, fields separated
+/// by tabs or semicolons:
+///
+/// ```text
+/// # game title: SERIAL;TITLE
+/// SLUS-00067;Castlevania: Symphony of the Night
+/// # CDDA track title: SERIAL;TRACK;TITLE
+/// SLUS-00067;02;Prologue
+/// ```
+#[derive(Debug, Default)]
+pub struct TitleDb {
+    games: HashMap<String, String>,
+    tracks: HashMap<(String, u8), String>,
+}
+
+impl TitleDb {
+    /// Load a title database file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read title database: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid title database: {}", path.display()))
+    }
+
+    /// Parse database text
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut db = Self::default();
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line
+                .split(['\t', ';'])
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .collect();
+
+            match fields.as_slice() {
+                [serial, track, title] if track.parse::<u8>().is_ok() => {
+                    let track = track.parse().unwrap();
+                    db.tracks
+                        .insert((normalize_serial(serial), track), title.to_string());
+                }
+                [serial, title] => {
+                    db.games.insert(normalize_serial(serial), title.to_string());
+                }
+                _ => bail!("line {}: expected SERIAL, [TRACK,] TITLE", line_no + 1),
+            }
+        }
+
+        Ok(db)
+    }
+
+    /// Game title for a serial (any separator style)
+    pub fn game_title(&self, game_id: &str) -> Option<&str> {
+        self.games
+            .get(&normalize_serial(game_id))
+            .map(String::as_str)
+    }
+
+    /// Title of a CDDA track
+    pub fn track_title(&self, game_id: &str, track: u8) -> Option<&str> {
+        self.tracks
+            .get(&(normalize_serial(game_id), track))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_titles() {
+        let db = TitleDb::parse(
+            "# comment\nSLUS-00067\tCastlevania\nSLUS-00067;02;Prologue\n\nSCES_015.64 ; Ape Escape\n",
+        )
+        .unwrap();
+
+        assert_eq!(db.game_title("SLUS_000.67"), Some("Castlevania"));
+        assert_eq!(db.game_title("SCES-01564"), Some("Ape Escape"));
+        assert_eq!(db.track_title("SLUS_000.67", 2), Some("Prologue"));
+        assert_eq!(db.track_title("SLUS_000.67", 3), None);
+        assert!(TitleDb::parse("SLUS-00067").is_err());
+    }
+}
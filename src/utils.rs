@@ -1,7 +1,7 @@
 use anyhow::Result;
 use regex::Regex;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
 
 /// Msf (Minutes:Seconds:Frames) timestamp structure
@@ -17,6 +17,16 @@ pub struct Msf {
 }
 
 impl Msf {
+    /// Last address a CD (and the BCD fields of a VCD header) can express
+    pub const MAX: Msf = Msf {
+        minutes: 99,
+        seconds: 59,
+        frames: 74,
+    };
+
+    /// Sector count of [`Msf::MAX`]
+    pub const MAX_SECTORS: u32 = (99 * 60 + 59) * 75 + 74;
+
     pub fn new(minutes: u8, seconds: u8, frames: u8) -> Self {
         Self {
             minutes,
@@ -25,34 +35,43 @@ impl Msf {
         }
     }
 
-    /// Parse Msf from string format "MM:SS:FF"
-    pub fn from_str(s: &str) -> Result<Self> {
-        let parts: Vec<&str> = s.split(':').collect();
-        if parts.len() != 3 {
-            anyhow::bail!("Invalid MSF format: {}", s);
-        }
-        Ok(Self {
-            minutes: parts[0].parse()?,
-            seconds: parts[1].parse()?,
-            frames: parts[2].parse()?,
-        })
-    }
-
     /// Convert Msf to sector count (LBA - Logical Block Address)
     pub fn to_sectors(self) -> u32 {
         ((self.minutes as u32 * 60) + self.seconds as u32) * 75 + self.frames as u32
     }
 
     /// Create Msf from sector count
+    ///
+    /// # Panics
+    ///
+    /// Past 99:59:74. Counts that come from input must go through
+    /// [`Msf::checked_from_sectors`] instead.
     pub fn from_sectors(sectors: u32) -> Self {
+        Self::checked_from_sectors(sectors)
+            .unwrap_or_else(|| panic!("{} sectors is past 99:59:74", sectors))
+    }
+
+    /// Create Msf from sector count, or None past 99:59:74
+    pub fn checked_from_sectors(sectors: u32) -> Option<Self> {
+        if sectors > Self::MAX_SECTORS {
+            return None;
+        }
         let frames = sectors % 75;
         let total_seconds = sectors / 75;
         let seconds = total_seconds % 60;
         let minutes = total_seconds / 60;
-        Self::new(minutes as u8, seconds as u8, frames as u8)
+        Some(Self::new(minutes as u8, seconds as u8, frames as u8))
+    }
+
+    /// Whether every field is in range and representable as BCD
+    pub fn is_valid(self) -> bool {
+        self.minutes <= 99 && self.seconds < 60 && self.frames < 75
     }
 
     /// Add seconds to Msf (can be negative for subtraction)
+    ///
+    /// Results before 00:00:00 stop there; see [`Msf::from_sectors`] for
+    /// results past 99:59:74.
     pub fn add_seconds(self, seconds: i32) -> Self {
         let total_sectors = self.to_sectors() as i32 + (seconds * 75);
         if total_sectors < 0 {
@@ -63,12 +82,70 @@ impl Msf {
     }
 
     /// Convert to BCD (Binary-Coded Decimal) format used in VCD header
+    ///
+    /// # Panics
+    ///
+    /// When a field is out of range (see [`Msf::is_valid`]); use
+    /// [`Msf::checked_to_bcd`] for values that were not validated.
     pub fn to_bcd(self) -> [u8; 3] {
-        [
-            ((self.minutes / 10) << 4) | (self.minutes % 10),
-            ((self.seconds / 10) << 4) | (self.seconds % 10),
-            ((self.frames / 10) << 4) | (self.frames % 10),
-        ]
+        self.checked_to_bcd()
+            .unwrap_or_else(|| panic!("{} cannot be written as BCD", self))
+    }
+
+    /// Convert to BCD, or None when a field is out of range
+    pub fn checked_to_bcd(self) -> Option<[u8; 3]> {
+        if !self.is_valid() {
+            return None;
+        }
+        let bcd = |value: u8| ((value / 10) << 4) | (value % 10);
+        Some([bcd(self.minutes), bcd(self.seconds), bcd(self.frames)])
+    }
+
+    /// Decode a BCD MSF as stored in VCD headers and sector headers
+    pub fn from_bcd(bcd: [u8; 3]) -> Option<Self> {
+        let decode = |b: u8| {
+            if b >> 4 > 9 || b & 0x0F > 9 {
+                None
+            } else {
+                Some((b >> 4) * 10 + (b & 0x0F))
+            }
+        };
+        let msf = Self::new(decode(bcd[0])?, decode(bcd[1])?, decode(bcd[2])?);
+        if msf.seconds >= 60 || msf.frames >= 75 {
+            return None;
+        }
+        Some(msf)
+    }
+}
+
+impl std::str::FromStr for Msf {
+    type Err = anyhow::Error;
+
+    /// Parse Msf from string format "MM:SS:FF"
+    ///
+    /// Each field must be one or more digits, with seconds below 60,
+    /// frames below 75 and minutes at most 99.
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 3
+            || parts
+                .iter()
+                .any(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit()))
+        {
+            anyhow::bail!("Invalid MSF format: {}", s);
+        }
+        let msf = Self {
+            minutes: parts[0].parse()?,
+            seconds: parts[1].parse()?,
+            frames: parts[2].parse()?,
+        };
+        if !msf.is_valid() {
+            anyhow::bail!(
+                "MSF out of range: {} (seconds < 60, frames < 75, minutes <= 99)",
+                s
+            );
+        }
+        Ok(msf)
     }
 }
 
@@ -82,9 +159,148 @@ impl std::fmt::Display for Msf {
     }
 }
 
+impl serde::Serialize for Msf {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Logical block address: sector index counted from disc position 00:02:00
+///
+/// CUE sheets and BIN offsets use relative positions (sector 0 is the first
+/// sector of the image), while sector headers and the VCD header use
+/// absolute disc MSF, which adds the 150-sector lead-in pregap. Keeping the
+/// two apart in the type system avoids adding or forgetting that offset.
+///
+/// Every `Lba` lies between [`Lba::ZERO`] and [`Lba::MAX`], the last sector
+/// addressable as 99:59:74, so both MSF conversions are exact. Construction
+/// and arithmetic are checked: [`Lba::new`], `lba + n` and `lba - n` return
+/// `None` outside that range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Lba(u32);
+
+impl Lba {
+    /// Sectors of lead-in pregap before LBA 0 (2 seconds)
+    pub const LEAD_IN: u32 = 150;
+
+    pub const ZERO: Lba = Lba(0);
+
+    /// Last sector whose absolute position fits in 99:59:74
+    pub const MAX: Lba = Lba(Msf::MAX_SECTORS - Self::LEAD_IN);
+
+    /// Wrap a sector index, or None past [`Lba::MAX`]
+    pub fn new(sector: u32) -> Option<Self> {
+        (sector <= Self::MAX.0).then_some(Self(sector))
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// Position of a relative (CUE/BIN) MSF, or None when its absolute
+    /// position would be past 99:59:74
+    pub fn from_relative_msf(msf: Msf) -> Option<Self> {
+        Self::new(msf.to_sectors())
+    }
+
+    /// Relative (CUE/BIN) MSF of this position
+    pub fn to_relative_msf(self) -> Msf {
+        Msf::from_sectors(self.0)
+    }
+
+    /// Position of an absolute disc MSF, or None inside the lead-in or
+    /// for an invalid MSF
+    pub fn from_absolute_msf(msf: Msf) -> Option<Self> {
+        if !msf.is_valid() {
+            return None;
+        }
+        Self::new(msf.to_sectors().checked_sub(Self::LEAD_IN)?)
+    }
+
+    /// Absolute disc MSF
+    pub fn to_absolute_msf(self) -> Msf {
+        Msf::from_sectors(self.0 + Self::LEAD_IN)
+    }
+
+    /// Decode an absolute BCD MSF as found in sector and VCD headers
+    pub fn from_bcd(bcd: [u8; 3]) -> Option<Self> {
+        Self::from_absolute_msf(Msf::from_bcd(bcd)?)
+    }
+
+    /// Absolute BCD MSF as written in sector headers
+    pub fn to_bcd(self) -> [u8; 3] {
+        self.to_absolute_msf().to_bcd()
+    }
+
+    /// Move by a signed number of frames (sectors)
+    pub fn checked_offset(self, frames: i64) -> Option<Self> {
+        let sector = u32::try_from(self.0 as i64 + frames).ok()?;
+        Self::new(sector)
+    }
+
+    /// Frames from `earlier` to this position, or None if it is later
+    pub fn frames_since(self, earlier: Lba) -> Option<u32> {
+        self.0.checked_sub(earlier.0)
+    }
+}
+
+impl std::ops::Add<u32> for Lba {
+    type Output = Option<Lba>;
+
+    fn add(self, frames: u32) -> Option<Lba> {
+        Lba::new(self.0.checked_add(frames)?)
+    }
+}
+
+impl std::ops::Sub<u32> for Lba {
+    type Output = Option<Lba>;
+
+    fn sub(self, frames: u32) -> Option<Lba> {
+        self.0.checked_sub(frames).map(Lba)
+    }
+}
+
+impl std::fmt::Display for Lba {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Reduce "SCES_015.64" / "SCES-01564" to "SCES01564" for comparisons
+pub fn normalize_serial(serial: &str) -> String {
+    serial
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_uppercase()
+}
+
 /// Detect PlayStation Game ID from binary data
 pub fn detect_game_id(bin_path: &Path) -> Result<Option<String>> {
+    detect_game_id_at(bin_path, 0)
+}
+
+/// Detect PlayStation Game ID from disc data starting at `offset`
+/// (e.g. after the 1MB header of a VCD)
+pub fn detect_game_id_at(bin_path: &Path, offset: u64) -> Result<Option<String>> {
+    Ok(detect_game_id_match_at(bin_path, offset)?.map(|m| m.id))
+}
+
+/// A detected Game ID and the rule that found it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameIdMatch {
+    pub id: String,
+    /// Description of the matching rule, e.g. "SLUS_XXX.XX pattern"
+    pub method: &'static str,
+}
+
+/// Like [`detect_game_id_at`], also reporting how the ID was found
+pub fn detect_game_id_match_at(bin_path: &Path, offset: u64) -> Result<Option<GameIdMatch>> {
     let mut file = File::open(bin_path)?;
+    file.seek(SeekFrom::Start(offset))?;
 
     let mut buffer = vec![0u8; 150 * 1024];
     let bytes_read = file.read(&mut buffer)?;
@@ -94,15 +310,27 @@ pub fn detect_game_id(bin_path: &Path) -> Result<Option<String>> {
     buffer.truncate(bytes_read);
 
     let patterns = vec![
-        Regex::new(r"(S[CL][EUA][SD][_]\d{3}\.\d{2})")?, // SLUS_XXX.XX
-        Regex::new(r"(S[CL][EUA][SD][-]\d{3}\.\d{2})")?, // SLUS-XXX.XX
-        Regex::new(r"(S[CL][EUA][SD][_-]\d{5})")?,       // SLUS_XXXXX
-        Regex::new(r"(S[CL][EUA][SD][ ]\d{3}\.\d{2})")?, // SLUS XXX.XX
+        (
+            Regex::new(r"(S[CL][EUA][SD][_]\d{3}\.\d{2})")?,
+            "SLUS_XXX.XX pattern",
+        ),
+        (
+            Regex::new(r"(S[CL][EUA][SD][-]\d{3}\.\d{2})")?,
+            "SLUS-XXX.XX pattern",
+        ),
+        (
+            Regex::new(r"(S[CL][EUA][SD][_-]\d{5})")?,
+            "SLUS_XXXXX pattern",
+        ),
+        (
+            Regex::new(r"(S[CL][EUA][SD][ ]\d{3}\.\d{2})")?,
+            "SLUS XXX.XX pattern",
+        ),
     ];
 
     let search_str = String::from_utf8_lossy(&buffer);
 
-    for pattern in &patterns {
+    for (pattern, method) in &patterns {
         if let Some(caps) = pattern.find(&search_str) {
             let mut game_id = caps.as_str().to_string();
             game_id = game_id.replace('-', "_");
@@ -114,7 +342,10 @@ pub fn detect_game_id(bin_path: &Path) -> Result<Option<String>> {
                 game_id.truncate(11);
             }
 
-            return Ok(Some(game_id));
+            return Ok(Some(GameIdMatch {
+                id: game_id,
+                method,
+            }));
         }
     }
 
@@ -129,7 +360,10 @@ pub fn detect_game_id(bin_path: &Path) -> Result<Option<String>> {
             let potential_id = String::from_utf8_lossy(&buffer[i..i + 11]);
             if let Some(caps) = pattern.find(&potential_id) {
                 let game_id = caps.as_str().replace('-', "_");
-                return Ok(Some(game_id));
+                return Ok(Some(GameIdMatch {
+                    id: game_id,
+                    method: "serial prefix scan",
+                }));
             }
         }
     }
@@ -140,8 +374,10 @@ pub fn detect_game_id(bin_path: &Path) -> Result<Option<String>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
+    #[allow(clippy::identity_op)] // minutes spelled out
     fn test_msf_conversion() {
         let msf = Msf::new(1, 30, 50);
         let sectors = msf.to_sectors();
@@ -165,9 +401,19 @@ mod tests {
         assert_eq!(bcd, [0x12, 0x34, 0x56]);
     }
 
+    #[test]
+    fn test_msf_from_bcd() {
+        assert_eq!(
+            Msf::from_bcd([0x12, 0x34, 0x56]),
+            Some(Msf::new(12, 34, 56))
+        );
+        assert_eq!(Msf::from_bcd([0x00, 0x1A, 0x00]), None);
+        assert_eq!(Msf::from_bcd([0x00, 0x60, 0x00]), None);
+    }
+
     #[test]
     fn test_msf_from_str() {
-        let msf = Msf::from_str("01:30:50").unwrap();
+        let msf: Msf = "01:30:50".parse().unwrap();
         assert_eq!(msf, Msf::new(1, 30, 50));
     }
 
@@ -177,4 +423,85 @@ mod tests {
         let msf2 = msf.add_seconds(-3);
         assert_eq!(msf2, Msf::new(0, 0, 0));
     }
+
+    #[test]
+    fn test_msf_out_of_range() {
+        assert!("00:60:00".parse::<Msf>().is_err());
+        assert!("00:00:75".parse::<Msf>().is_err());
+        assert!("100:00:00".parse::<Msf>().is_err());
+        assert!("+1:00:00".parse::<Msf>().is_err());
+        assert_eq!(Msf::checked_from_sectors(Msf::MAX_SECTORS), Some(Msf::MAX));
+        assert_eq!(Msf::checked_from_sectors(Msf::MAX_SECTORS + 1), None);
+        assert_eq!(Msf::new(120, 0, 0).checked_to_bcd(), None);
+        assert_eq!(Msf::new(0, 60, 0).checked_to_bcd(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "past 99:59:74")]
+    fn test_msf_from_sectors_rejects_overflow() {
+        Msf::from_sectors(Msf::MAX_SECTORS + 1);
+    }
+
+    #[test]
+    fn test_lba_offsets() {
+        let lba = |sector| Lba::new(sector).unwrap();
+        assert_eq!(Lba::ZERO.to_absolute_msf(), Msf::new(0, 2, 0));
+        assert_eq!(Lba::from_absolute_msf(Msf::new(0, 1, 74)), None);
+        assert_eq!(Lba::from_absolute_msf(Msf::new(0, 60, 0)), None);
+        assert_eq!(Lba::from_bcd([0x00, 0x04, 0x00]), Some(lba(150)));
+        assert_eq!(Lba::from_relative_msf(Msf::new(0, 4, 0)), Some(lba(300)));
+        assert_eq!(Lba::from_relative_msf(Msf::new(99, 59, 0)), None);
+        assert_eq!(Lba::MAX.to_absolute_msf(), Msf::MAX);
+        assert_eq!(Lba::new(Lba::MAX.get() + 1), None);
+
+        assert_eq!(lba(10) + 5, Some(lba(15)));
+        assert_eq!(lba(10) - 11, None);
+        assert_eq!(Lba::MAX + 1, None);
+        assert_eq!(lba(150).checked_offset(-150), Some(Lba::ZERO));
+        assert_eq!(lba(150).checked_offset(-151), None);
+        assert_eq!(lba(300).frames_since(lba(100)), Some(200));
+        assert!(lba(1) < lba(2));
+    }
+
+    proptest! {
+        #[test]
+        fn prop_msf_sectors_round_trip(sectors in 0..=Msf::MAX_SECTORS) {
+            let msf = Msf::from_sectors(sectors);
+            prop_assert!(msf.is_valid());
+            prop_assert_eq!(msf.to_sectors(), sectors);
+        }
+
+        #[test]
+        fn prop_msf_bcd_round_trip(sectors in 0..=Msf::MAX_SECTORS) {
+            let msf = Msf::from_sectors(sectors);
+            prop_assert_eq!(Msf::from_bcd(msf.to_bcd()), Some(msf));
+        }
+
+        #[test]
+        fn prop_msf_bcd_only_when_valid(minutes: u8, seconds: u8, frames: u8) {
+            let msf = Msf::new(minutes, seconds, frames);
+            match msf.checked_to_bcd() {
+                Some(bcd) => prop_assert_eq!(Msf::from_bcd(bcd), Some(msf)),
+                None => prop_assert!(!msf.is_valid()),
+            }
+        }
+
+        #[test]
+        fn prop_msf_parse_display(minutes: u8, seconds: u8, frames: u8) {
+            let msf = Msf::new(minutes, seconds, frames);
+            match msf.to_string().parse::<Msf>() {
+                Ok(parsed) => prop_assert_eq!(parsed, msf),
+                Err(_) => prop_assert!(!msf.is_valid()),
+            }
+        }
+
+        #[test]
+        fn prop_lba_absolute_round_trip(sector in 0..=Lba::MAX.get()) {
+            let lba = Lba::new(sector).unwrap();
+            let msf = lba.to_absolute_msf();
+            prop_assert_eq!(msf.to_sectors(), sector + Lba::LEAD_IN);
+            prop_assert_eq!(Lba::from_absolute_msf(msf), Some(lba));
+            prop_assert_eq!(Lba::from_bcd(lba.to_bcd()), Some(lba));
+        }
+    }
 }
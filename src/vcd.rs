@@ -1,15 +1,334 @@
 // src/vcd.rs
-use anyhow::Result;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
-
-use crate::cue::CueSheet;
-use crate::utils::Msf;
+use anyhow::{bail, Context, Result};
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::cue::{CueSheet, DiscIndexes};
+use crate::error::Error;
+use crate::output::PartialFile;
+use crate::progress::{NoProgress, ProgressReporter, ProgressWriter};
+use crate::status;
+use crate::utils::{Lba, Msf};
 
 const SECTOR_SIZE: usize = 2352;
-const VCD_HEADER_SIZE: usize = 0x100000; // 1MB header
+pub const VCD_HEADER_SIZE: usize = 0x100000; // 1MB header
 const PREGAP_SECTORS: u32 = 150; // 2 seconds at 75 sectors/second
+pub(crate) const TRACK_ENTRY_OFFSET: usize = 30;
+pub(crate) const TRACK_ENTRY_SIZE: usize = 10;
+const MAX_TRACKS: usize = 99;
+
+/// Track entry decoded from a VCD header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcdTrackEntry {
+    pub number: u8,
+    pub is_audio: bool,
+    pub index00: Msf,
+    pub index01: Msf,
+}
+
+/// Fields decoded from an existing VCD header
+#[derive(Debug, Clone)]
+pub struct VcdHeader {
+    pub leadout: Msf,
+    pub tracks: Vec<VcdTrackEntry>,
+}
+
+impl VcdHeader {
+    /// Read and decode the header of a VCD file
+    pub fn read(vcd_path: &Path) -> Result<Self> {
+        Self::parse(&Self::read_raw(vcd_path)?)
+    }
+
+    /// Read the raw 1MB header of a VCD file
+    pub fn read_raw(vcd_path: &Path) -> Result<Vec<u8>> {
+        let mut file = File::open(vcd_path)
+            .with_context(|| format!("Failed to open VCD: {}", vcd_path.display()))?;
+        let mut header = vec![0u8; VCD_HEADER_SIZE];
+        file.read_exact(&mut header)
+            .with_context(|| format!("VCD header is truncated: {}", vcd_path.display()))?;
+        Ok(header)
+    }
+
+    /// Decode the TOC descriptors and track entries
+    pub fn parse(header: &[u8]) -> Result<Self> {
+        if header.len() < 0x410 || header[2] != 0xA0 || header[12] != 0xA1 || header[22] != 0xA2 {
+            bail!("Not a VCD file (missing A0/A1/A2 descriptors)");
+        }
+
+        let track_count = bcd_to_u8(header[17]).context("Invalid track count in VCD header")?;
+        let leadout = Msf::from_bcd([header[27], header[28], header[29]])
+            .context("Invalid lead-out MSF in VCD header")?;
+
+        let mut tracks = Vec::with_capacity(track_count as usize);
+        for i in 0..track_count as usize {
+            let entry = &header[TRACK_ENTRY_OFFSET + i * TRACK_ENTRY_SIZE..][..TRACK_ENTRY_SIZE];
+            let number = bcd_to_u8(entry[2])
+                .with_context(|| format!("Invalid track number in entry {}", i + 1))?;
+            let index00 = Msf::from_bcd([entry[3], entry[4], entry[5]])
+                .with_context(|| format!("Invalid INDEX 00 for track {:02}", number))?;
+            let index01 = Msf::from_bcd([entry[7], entry[8], entry[9]])
+                .with_context(|| format!("Invalid INDEX 01 for track {:02}", number))?;
+            tracks.push(VcdTrackEntry {
+                number,
+                is_audio: entry[0] == 0x01,
+                index00,
+                index01,
+            });
+        }
+
+        Ok(Self { leadout, tracks })
+    }
+
+    /// Payload sectors of each track's INDEX 00 and INDEX 01
+    ///
+    /// Headers whose Track 01 INDEX 01 is 00:02:00 (single-track and
+    /// multi-file discs) hold absolute disc positions, and a later track
+    /// with a pregap carries cue2pops' extra 2 seconds: its pregap starts
+    /// 150 sectors before its INDEX 00 entry. Other headers hold the CUE
+    /// positions of a single BIN, which are payload sectors already.
+    /// Entries moved by gap++/gap-- are taken as they read.
+    pub fn track_positions(&self) -> Vec<(Lba, Lba)> {
+        let absolute = self
+            .tracks
+            .first()
+            .is_some_and(|t| t.index01 == DiscIndexes::TRACK01.index01);
+        let payload = |msf: Msf| {
+            if absolute {
+                Lba::from_absolute_msf(msf)
+            } else {
+                Lba::from_relative_msf(msf)
+            }
+            .unwrap_or(Lba::ZERO)
+        };
+
+        self.tracks
+            .iter()
+            .enumerate()
+            .map(|(idx, t)| {
+                let (index00, index01) = (payload(t.index00), payload(t.index01));
+                if absolute && idx > 0 && index00 != index01 {
+                    let unshift = |lba: Lba| (lba - Lba::LEAD_IN).unwrap_or(Lba::ZERO);
+                    (unshift(index00), unshift(index01))
+                } else {
+                    (index00, index01)
+                }
+            })
+            .collect()
+    }
+
+    /// Payload sector of the lead-out, which is always absolute
+    pub fn leadout_position(&self) -> Lba {
+        Lba::from_absolute_msf(self.leadout).unwrap_or(Lba::ZERO)
+    }
+
+    /// Byte offset in the VCD file of a payload sector
+    pub fn byte_offset(lba: Lba) -> u64 {
+        VCD_HEADER_SIZE as u64 + lba.get() as u64 * SECTOR_SIZE as u64
+    }
+}
+
+pub(crate) fn bcd_to_u8(value: u8) -> Option<u8> {
+    if value >> 4 > 9 || value & 0x0F > 9 {
+        None
+    } else {
+        Some((value >> 4) * 10 + (value & 0x0F))
+    }
+}
+
+/// How the bytes of a header field are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFieldKind {
+    Byte,
+    /// 0x41 data / 0x01 audio
+    TrackType,
+    Bcd,
+    Msf,
+    /// Little-endian u32
+    Count,
+    Text,
+}
+
+/// A named field of the cue2pops v2.0 header
+#[derive(Debug, Clone)]
+pub struct HeaderField {
+    pub name: String,
+    pub offset: usize,
+    pub len: usize,
+    pub kind: HeaderFieldKind,
+}
+
+impl HeaderField {
+    fn new(name: impl Into<String>, offset: usize, len: usize, kind: HeaderFieldKind) -> Self {
+        Self {
+            name: name.into(),
+            offset,
+            len,
+            kind,
+        }
+    }
+
+    /// Bytes of the field within the header
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Known header fields, with `track_count` track entries
+pub fn header_fields(track_count: usize) -> Vec<HeaderField> {
+    use HeaderFieldKind::*;
+
+    let mut fields = vec![
+        HeaderField::new("A0 first track type", 0, 1, TrackType),
+        HeaderField::new("A0 descriptor", 2, 1, Byte),
+        HeaderField::new("A0 first track", 7, 1, Bcd),
+        HeaderField::new("A0 disc type", 8, 1, Byte),
+        HeaderField::new("A1 last track type", 10, 1, TrackType),
+        HeaderField::new("A1 descriptor", 12, 1, Byte),
+        HeaderField::new("A1 track count", 17, 1, Bcd),
+        HeaderField::new("A1 content type", 20, 1, TrackType),
+        HeaderField::new("A2 descriptor", 22, 1, Byte),
+        HeaderField::new("A2 lead-out", 27, 3, Msf),
+    ];
+    for idx in 0..track_count {
+        let offset = TRACK_ENTRY_OFFSET + idx * TRACK_ENTRY_SIZE;
+        let entry = idx + 1;
+        fields.push(HeaderField::new(
+            format!("Entry {:02} type", entry),
+            offset,
+            1,
+            TrackType,
+        ));
+        fields.push(HeaderField::new(
+            format!("Entry {:02} track", entry),
+            offset + 2,
+            1,
+            Bcd,
+        ));
+        fields.push(HeaderField::new(
+            format!("Entry {:02} INDEX 00", entry),
+            offset + 3,
+            3,
+            Msf,
+        ));
+        fields.push(HeaderField::new(
+            format!("Entry {:02} INDEX 01", entry),
+            offset + 7,
+            3,
+            Msf,
+        ));
+    }
+    fields.extend([
+        HeaderField::new("Signature", 0x400, 4, Text),
+        HeaderField::new("Sector count", 0x408, 4, Count),
+        HeaderField::new("Sector count (copy)", 0x40C, 4, Count),
+    ]);
+    fields
+}
+
+/// Set the track count of descriptor A1
+///
+/// The last track type (content type) is taken from track entry `count`,
+/// which must already be present in the header.
+pub fn set_track_count(header: &mut [u8], count: u8) -> Result<()> {
+    if count == 0 || count as usize > MAX_TRACKS {
+        bail!("track count {} is outside 1-{}", count, MAX_TRACKS);
+    }
+    let entry = TRACK_ENTRY_OFFSET + (count as usize - 1) * TRACK_ENTRY_SIZE;
+    if bcd_to_u8(header[entry + 2]) != Some(count) {
+        bail!("the header has no entry for track {:02}", count);
+    }
+
+    let content_type = header[entry];
+    header[10] = content_type;
+    header[17] = ((count / 10) << 4) | (count % 10);
+    header[20] = content_type;
+    Ok(())
+}
+
+/// Set the lead-out MSF of descriptor A2
+pub fn set_leadout(header: &mut [u8], leadout: Msf) {
+    header[27..30].copy_from_slice(&leadout.to_bcd());
+}
+
+/// Set the lead-out and both sector counts for `total_sectors` disc sectors
+pub fn set_total_sectors(header: &mut [u8], total_sectors: u32) -> crate::error::Result<Msf> {
+    let leadout = Lba::new(total_sectors)
+        .map(Lba::to_absolute_msf)
+        .ok_or_else(|| {
+            Error::HeaderOverflow(format!(
+                "lead-out at LBA {} is beyond 99:59:74",
+                total_sectors
+            ))
+        })?;
+    set_leadout(header, leadout);
+
+    // Sector count at offsets 0x408 and 0x40C (1032, 1036)
+    let sector_bytes = total_sectors.to_le_bytes();
+    header[1032..1036].copy_from_slice(&sector_bytes);
+    header[1036..1040].copy_from_slice(&sector_bytes);
+    Ok(leadout)
+}
+
+/// Where [`write_header_in_place`] keeps the original header
+pub fn header_backup_path(vcd_path: &Path) -> PathBuf {
+    let mut backup = vcd_path.as_os_str().to_owned();
+    backup.push(".header.bak");
+    PathBuf::from(backup)
+}
+
+/// Overwrite the first 1MB of a VCD, leaving the disc data untouched
+///
+/// The header found in the file is saved first, unless a backup already
+/// exists: the backup always holds the header from before the first edit.
+/// It is written under a temporary name and never replaces another file.
+/// Returns the backup path.
+pub fn write_header_in_place(vcd_path: &Path, header: &[u8]) -> Result<PathBuf> {
+    if header.len() != VCD_HEADER_SIZE {
+        bail!("VCD header must be {} bytes", VCD_HEADER_SIZE);
+    }
+
+    let backup = header_backup_path(vcd_path);
+    if !backup.exists() {
+        let original = VcdHeader::read_raw(vcd_path)?;
+        let partial = PartialFile::new(&backup);
+        std::fs::write(partial.path(), original).map_err(|e| Error::io(partial.path(), e))?;
+        partial.persist(false)?;
+    }
+
+    overwrite_header(vcd_path, header)?;
+    Ok(backup)
+}
+
+/// Put back the header saved by [`write_header_in_place`] and remove the backup
+pub fn restore_header(vcd_path: &Path) -> Result<PathBuf> {
+    let backup = header_backup_path(vcd_path);
+    if !backup.exists() {
+        bail!("No header backup found: {}", backup.display());
+    }
+    let header =
+        std::fs::read(&backup).with_context(|| format!("Failed to read: {}", backup.display()))?;
+    if header.len() != VCD_HEADER_SIZE {
+        bail!("Header backup has the wrong size: {}", backup.display());
+    }
+
+    overwrite_header(vcd_path, &header)?;
+    std::fs::remove_file(&backup)?;
+    Ok(backup)
+}
+
+fn overwrite_header(vcd_path: &Path, header: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(vcd_path)
+        .with_context(|| format!("Failed to open VCD: {}", vcd_path.display()))?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(header)?;
+    file.flush()?;
+    Ok(())
+}
 
 /// VCD Converter - creates POPSTARTER-compatible VCD files
 ///
@@ -21,6 +340,7 @@ const PREGAP_SECTORS: u32 = 150; // 2 seconds at 75 sectors/second
 /// The header format matches cue2pops v2.0 for maximum compatibility.
 pub struct VcdConverter {
     gap_adjustment: i32,
+    progress: RefCell<Box<dyn ProgressReporter + Send>>,
 }
 
 impl VcdConverter {
@@ -38,7 +358,17 @@ impl VcdConverter {
             0
         };
 
-        Self { gap_adjustment }
+        Self {
+            gap_adjustment,
+            progress: RefCell::new(Box::new(NoProgress)),
+        }
+    }
+
+    /// Report the bytes written by [`convert_to_vcd`](Self::convert_to_vcd)
+    /// to `progress`
+    pub fn with_progress(mut self, progress: Box<dyn ProgressReporter + Send>) -> Self {
+        self.progress = RefCell::new(progress);
+        self
     }
 
     /// Convert a combined BIN file to VCD format
@@ -54,36 +384,46 @@ impl VcdConverter {
         combined_bin: &Path,
         vcd_path: &Path,
         cue_sheet: &CueSheet,
-    ) -> Result<()> {
-        println!("  Creating VCD file...");
+    ) -> crate::error::Result<()> {
+        status!("  Creating VCD file...");
 
-        let bin_size = std::fs::metadata(combined_bin)?.len();
+        let bin_err = |e| Error::io(combined_bin, e);
+        let vcd_err = |e| Error::io(vcd_path, e);
+
+        let bin_size = std::fs::metadata(combined_bin).map_err(bin_err)?.len();
 
         // Create VCD header with TOC information
         let header = self.create_vcd_header(bin_size, cue_sheet)?;
 
         // Write VCD file
-        let mut vcd_file = File::create(vcd_path)?;
+        let vcd_file = File::create(vcd_path).map_err(vcd_err)?;
+        let mut progress = self.progress.borrow_mut();
+        let mut vcd_file = ProgressWriter::new(
+            vcd_file,
+            progress.as_mut(),
+            "Writing VCD",
+            VCD_HEADER_SIZE as u64 + bin_size,
+        );
 
         // Write 1MB header
-        vcd_file.write_all(&header)?;
+        vcd_file.write_all(&header).map_err(vcd_err)?;
 
         // Copy BIN data after header
-        let mut bin_file = File::open(combined_bin)?;
+        let mut bin_file = File::open(combined_bin).map_err(bin_err)?;
         let mut buffer = vec![0u8; 1024 * 1024]; // 1MB buffer for efficient copying
 
         loop {
-            let bytes_read = bin_file.read(&mut buffer)?;
+            let bytes_read = bin_file.read(&mut buffer).map_err(bin_err)?;
             if bytes_read == 0 {
                 break;
             }
-            vcd_file.write_all(&buffer[..bytes_read])?;
+            vcd_file.write_all(&buffer[..bytes_read]).map_err(vcd_err)?;
         }
 
-        vcd_file.flush()?;
+        vcd_file.finish().map_err(vcd_err)?;
 
-        let vcd_size = std::fs::metadata(vcd_path)?.len();
-        println!(
+        let vcd_size = std::fs::metadata(vcd_path).map_err(vcd_err)?.len();
+        status!(
             "  [+] VCD created: {:.2} MB",
             vcd_size as f64 / (1024.0 * 1024.0)
         );
@@ -101,11 +441,16 @@ impl VcdConverter {
     /// - 0x400-0x403: cue2pops signature
     /// - 0x408-0x40B: Total sector count
     /// - 0x40C-0x40F: Total sector count (duplicate)
-    fn create_vcd_header(&self, bin_size: u64, cue_sheet: &CueSheet) -> Result<Vec<u8>> {
+    pub fn create_vcd_header(
+        &self,
+        bin_size: u64,
+        cue_sheet: &CueSheet,
+    ) -> crate::error::Result<Vec<u8>> {
         let mut header = vec![0u8; VCD_HEADER_SIZE];
 
-        // Calculate actual sectors from combined BIN
-        let bin_sectors = (bin_size / SECTOR_SIZE as u64) as u32;
+        // Calculate actual sectors from combined BIN; anything past the
+        // MSF range is rejected below, so saturating here is safe
+        let bin_sectors = u32::try_from(bin_size / SECTOR_SIZE as u64).unwrap_or(u32::MAX);
 
         // CRITICAL: cue2pops only counts explicit PREGAP keywords in CUE
         // Multi-file CUEs like Tekken 3 use INDEX 00 but no PREGAP keywords
@@ -114,29 +459,47 @@ impl VcdConverter {
 
         // cue2pops exact calculation:
         // total = bin_sectors + (pregap_count * 150) + (postgap_count * 150)
-        let total_sectors =
-            bin_sectors + (pregap_count * PREGAP_SECTORS) + (postgap_count * PREGAP_SECTORS);
-
-        println!("\n  === VCD Header Calculation (cue2pops v2.0) ===");
-        println!("  BIN size: {} bytes", bin_size);
-        println!("  BIN sectors: {}", bin_sectors);
-        println!("  Pregap keywords: {}", pregap_count);
-        println!("  Postgap keywords: {}", postgap_count);
-        println!("  Total sectors (for header): {}", total_sectors);
+        let total_sectors = bin_sectors
+            .saturating_add(pregap_count * PREGAP_SECTORS)
+            .saturating_add(postgap_count * PREGAP_SECTORS);
+
+        status!("\n  === VCD Header Calculation (cue2pops v2.0) ===");
+        status!("  BIN size: {} bytes", bin_size);
+        status!("  BIN sectors: {}", bin_sectors);
+        status!("  Pregap keywords: {}", pregap_count);
+        status!("  Postgap keywords: {}", postgap_count);
+        status!("  Total sectors (for header): {}", total_sectors);
+
+        // Track entries must end before the signature at 0x400 and the
+        // lead-out must be expressible as BCD MSF
+        let track_count = cue_sheet.get_total_tracks();
+        if track_count > MAX_TRACKS {
+            return Err(Error::HeaderOverflow(format!(
+                "{} tracks, the header holds at most {}",
+                track_count, MAX_TRACKS
+            )));
+        }
+        let leadout = Lba::new(total_sectors)
+            .map(Lba::to_absolute_msf)
+            .ok_or_else(|| {
+                Error::HeaderOverflow(format!(
+                    "lead-out at LBA {} is beyond 99:59:74",
+                    total_sectors
+                ))
+            })?;
+        let entries = self.track_entries(cue_sheet)?;
 
         // Build the 3 TOC descriptors
         self.build_descriptor_a0(&mut header);
         self.build_descriptor_a1(&mut header, cue_sheet);
-        self.build_descriptor_a2(&mut header, total_sectors);
+        self.build_descriptor_a2(&mut header, total_sectors, leadout);
 
         // Write track entries starting at offset 0x1E (30)
-        println!("\n  === Track Entries ===");
-        self.write_track_entries(&mut header, cue_sheet);
+        status!("\n  === Track Entries ===");
+        self.write_track_entries(&mut header, &entries);
 
         // Write sector count at offsets 0x408 and 0x40C (1032, 1036)
-        let sector_bytes = total_sectors.to_le_bytes();
-        header[1032..1036].copy_from_slice(&sector_bytes);
-        header[1036..1040].copy_from_slice(&sector_bytes);
+        set_total_sectors(&mut header, total_sectors)?;
 
         // Write cue2pops version signature at 0x400 (1024)
         header[1024] = 0x6B; // 'k'
@@ -144,7 +507,7 @@ impl VcdConverter {
         header[1026] = 0x6E; // 'n'
         header[1027] = 0x20; // ' ' - cue2pops v2.0 identifier
 
-        println!("  ============================================\n");
+        status!("  ============================================\n");
 
         Ok(header)
     }
@@ -188,24 +551,90 @@ impl VcdConverter {
     /// Build Descriptor A2 (Lead-Out Position)
     ///
     /// The lead-out marks the end of the disc's playable area.
-    /// CRITICAL: cue2pops adds +150 sectors to the total for the lead-out MSF.
-    fn build_descriptor_a2(&self, header: &mut [u8], total_sectors: u32) {
+    /// CRITICAL: cue2pops places the lead-out at the absolute disc MSF of
+    /// the total, i.e. 150 sectors (the lead-in) after it.
+    fn build_descriptor_a2(&self, header: &mut [u8], total_sectors: u32, leadout: Msf) {
         header[22] = 0xA2; // Descriptor ID
+        set_leadout(header, leadout); // Minutes, seconds, frames at 27-29
 
-        // CRITICAL: Add 150 sectors for lead-out MSF
-        // This matches cue2pops original C code exactly
-        let leadout_sectors_for_msf = total_sectors + 150;
-        let leadout_msf = Msf::from_sectors(leadout_sectors_for_msf);
-        let leadout_bcd = leadout_msf.to_bcd();
+        status!(
+            "  Lead-Out MSF: {} (sectors: {} + {} lead-in)",
+            leadout,
+            total_sectors,
+            Lba::LEAD_IN
+        );
+    }
 
-        header[27] = leadout_bcd[0]; // Minutes
-        header[28] = leadout_bcd[1]; // Seconds
-        header[29] = leadout_bcd[2]; // Frames
+    /// Header entries of every track, with gap adjustment applied
+    ///
+    /// Rejects track numbers and positions that cannot be written as BCD.
+    /// Gap adjustment only moves tracks after the first and may not push
+    /// them before 00:02:00 or past 99:59:74.
+    fn track_entries(&self, cue_sheet: &CueSheet) -> crate::error::Result<Vec<VcdTrackEntry>> {
+        let mut entries = Vec::new();
+
+        for track in cue_sheet.files.iter().flat_map(|f| &f.tracks) {
+            if track.number == 0 || track.number > 99 {
+                return Err(Error::HeaderOverflow(format!(
+                    "track number {} is outside 1-99",
+                    track.number
+                )));
+            }
 
-        println!(
-            "  Lead-Out MSF: {} (sectors: {} + 150 = {})",
-            leadout_msf, total_sectors, leadout_sectors_for_msf
-        );
+            let disc = track.disc_indexes();
+            entries.push(self.adjust_entry(VcdTrackEntry {
+                number: track.number,
+                is_audio: track.is_audio(),
+                index00: disc.index00,
+                index01: disc.index01,
+            })?);
+        }
+        Ok(entries)
+    }
+
+    /// Apply the gap adjustment to one track entry
+    fn adjust_entry(&self, entry: VcdTrackEntry) -> crate::error::Result<VcdTrackEntry> {
+        let adjustment = if entry.number > 1 {
+            self.gap_adjustment as i64 * 75
+        } else {
+            0
+        };
+        let adjust = |msf: Msf| {
+            let adjusted = if adjustment == 0 {
+                Some(msf).filter(|m| m.is_valid())
+            } else {
+                Lba::from_absolute_msf(msf)
+                    .and_then(|lba| lba.checked_offset(adjustment))
+                    .map(Lba::to_absolute_msf)
+            };
+            adjusted.ok_or_else(|| {
+                Error::HeaderOverflow(format!(
+                    "track {:02} position {} moved by {:+} frames is outside 00:02:00-99:59:74",
+                    entry.number, msf, adjustment
+                ))
+            })
+        };
+
+        Ok(VcdTrackEntry {
+            index00: adjust(entry.index00)?,
+            index01: adjust(entry.index01)?,
+            ..entry
+        })
+    }
+
+    /// Apply the gap adjustment to the track entries of an existing header
+    ///
+    /// Adjustments add up: re-gapping a header made with `--gap-plus` again
+    /// moves the tracks another 2 seconds.
+    pub fn regap_header(&self, header: &mut [u8]) -> Result<()> {
+        let parsed = VcdHeader::parse(header)?;
+        let entries = parsed
+            .tracks
+            .into_iter()
+            .map(|entry| self.adjust_entry(entry))
+            .collect::<crate::error::Result<Vec<_>>>()?;
+        self.write_track_entries(header, &entries);
+        Ok(())
     }
 
     /// Write track entries to header (starting at offset 30/0x1E)
@@ -216,59 +645,131 @@ impl VcdConverter {
     /// - Bytes 3-5: INDEX 00 MSF (BCD)
     /// - Byte 6: NULL
     /// - Bytes 7-9: INDEX 01 MSF (BCD)
-    ///
-    /// Gap adjustment (if any) is applied here to INDEX positions.
-    fn write_track_entries(&self, header: &mut [u8], cue_sheet: &CueSheet) {
-        let mut offset = 30;
-
-        for file in &cue_sheet.files {
-            for track in &file.tracks {
-                // Track type (0x41 = DATA, 0x01 = AUDIO)
-                header[offset] = if track.is_audio() { 0x01 } else { 0x41 };
-
-                // Track number (BCD)
-                offset += 2;
-                header[offset] = ((track.number / 10) << 4) | (track.number % 10);
-
-                // INDEX 00 MSF
-                offset += 1;
-                let index00_msf = track.index00_msf.unwrap_or(track.index01_msf);
-
-                // Apply user-requested gap adjustment (only for tracks > 1)
-                let adjusted_index00 = if track.number > 1 && self.gap_adjustment != 0 {
-                    index00_msf.add_seconds(self.gap_adjustment)
-                } else {
-                    index00_msf
-                };
+    fn write_track_entries(&self, header: &mut [u8], entries: &[VcdTrackEntry]) {
+        for (idx, entry) in entries.iter().enumerate() {
+            let offset = TRACK_ENTRY_OFFSET + idx * TRACK_ENTRY_SIZE;
+            let field = &mut header[offset..offset + TRACK_ENTRY_SIZE];
+
+            // Track type (0x41 = DATA, 0x01 = AUDIO)
+            field[0] = if entry.is_audio { 0x01 } else { 0x41 };
+            field[2] = ((entry.number / 10) << 4) | (entry.number % 10);
+            field[3..6].copy_from_slice(&entry.index00.to_bcd());
+            field[7..10].copy_from_slice(&entry.index01.to_bcd());
+
+            status!(
+                "  Track {:02} [{:5}]: INDEX 00={} (sector {}) | INDEX 01={} (sector {})",
+                entry.number,
+                if entry.is_audio { "AUDIO" } else { "DATA" },
+                entry.index00,
+                entry.index00.to_sectors(),
+                entry.index01,
+                entry.index01.to_sectors()
+            );
+        }
+    }
+}
 
-                let index00_bcd = adjusted_index00.to_bcd();
-                header[offset..offset + 3].copy_from_slice(&index00_bcd);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cue::{DiscIndexes, FileEntry, Track, TrackType};
+
+    #[test]
+    fn test_header_roundtrip() {
+        let mut file = FileEntry::new("game.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2352, Lba::ZERO));
+        let index01 = Lba::from_relative_msf(Msf::new(1, 0, 10)).unwrap();
+        file.tracks.push(Track::new(2, TrackType::Audio, index01));
+        file.tracks[0].disc = Some(DiscIndexes::TRACK01);
+        let mut cue_sheet = CueSheet::new();
+        cue_sheet.files.push(file);
+
+        let header = VcdConverter::new(false, false)
+            .create_vcd_header(3000 * SECTOR_SIZE as u64, &cue_sheet)
+            .unwrap();
+        let parsed = VcdHeader::parse(&header).unwrap();
+
+        assert_eq!(parsed.leadout, Msf::from_sectors(3150));
+        assert_eq!(parsed.tracks.len(), 2);
+        assert_eq!(parsed.tracks[1].number, 2);
+        assert!(parsed.tracks[1].is_audio);
+        assert_eq!(parsed.tracks[1].index01, Msf::new(1, 0, 10));
+        assert_eq!(VcdHeader::byte_offset(Lba::ZERO), VCD_HEADER_SIZE as u64);
+    }
 
-                // Skip NULL byte
-                offset += 4;
+    #[test]
+    fn test_patch_header_in_place() {
+        let mut file = FileEntry::new("game.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2352, Lba::new(150).unwrap()));
+        file.tracks
+            .push(Track::new(2, TrackType::Audio, Lba::new(750).unwrap()));
+        let mut cue_sheet = CueSheet::new();
+        cue_sheet.files.push(file);
+
+        let converter = VcdConverter::new(false, false);
+        let original = converter
+            .create_vcd_header(1000 * SECTOR_SIZE as u64, &cue_sheet)
+            .unwrap();
+        let vcd_path =
+            std::env::temp_dir().join(format!("psx-vcd-patch-{}.VCD", std::process::id()));
+        let payload = vec![0x5Au8; 1000 * SECTOR_SIZE];
+        std::fs::write(&vcd_path, [original.as_slice(), &payload].concat()).unwrap();
+
+        let mut header = original.clone();
+        VcdConverter::new(true, false)
+            .regap_header(&mut header)
+            .unwrap();
+        set_track_count(&mut header, 1).unwrap();
+        assert!(set_track_count(&mut header, 3).is_err());
+        set_total_sectors(&mut header, 900).unwrap();
+
+        let backup = write_header_in_place(&vcd_path, &header).unwrap();
+        let parsed = VcdHeader::read(&vcd_path).unwrap();
+        assert_eq!(parsed.tracks.len(), 1);
+        assert_eq!(parsed.leadout, Msf::new(0, 14, 0));
+        assert_eq!(&header[30 + 10 + 7..30 + 20], &Msf::new(0, 12, 0).to_bcd());
+        assert_eq!(
+            std::fs::metadata(&vcd_path).unwrap().len(),
+            (VCD_HEADER_SIZE + payload.len()) as u64
+        );
 
-                // INDEX 01 MSF
-                let adjusted_index01 = if track.number > 1 && self.gap_adjustment != 0 {
-                    track.index01_msf.add_seconds(self.gap_adjustment)
-                } else {
-                    track.index01_msf
-                };
-
-                let index01_bcd = adjusted_index01.to_bcd();
-                header[offset..offset + 3].copy_from_slice(&index01_bcd);
-
-                println!(
-                    "  Track {:02} [{:5}]: INDEX 00={} (sector {}) | INDEX 01={} (sector {})",
-                    track.number,
-                    track.track_type,
-                    adjusted_index00,
-                    adjusted_index00.to_sectors(),
-                    adjusted_index01,
-                    adjusted_index01.to_sectors()
-                );
-
-                offset += 3;
-            }
-        }
+        // A second edit keeps the first backup
+        set_leadout(&mut header, Msf::new(0, 20, 0));
+        write_header_in_place(&vcd_path, &header).unwrap();
+        restore_header(&vcd_path).unwrap();
+        let restored = std::fs::read(&vcd_path).unwrap();
+        std::fs::remove_file(&vcd_path).unwrap();
+
+        assert!(!backup.exists());
+        assert_eq!(&restored[..VCD_HEADER_SIZE], &original[..]);
+        assert_eq!(&restored[VCD_HEADER_SIZE..], &payload[..]);
+    }
+
+    #[test]
+    fn test_header_rejects_out_of_range_positions() {
+        let mut file = FileEntry::new("game.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2352, Lba::new(150).unwrap()));
+        let mut track = Track::new(2, TrackType::Audio, Lba::new(1000).unwrap());
+        track.disc = Some(DiscIndexes {
+            index00: Msf::new(99, 59, 0),
+            index01: Msf::new(99, 59, 0),
+        });
+        file.tracks.push(track);
+        let mut cue_sheet = CueSheet::new();
+        cue_sheet.files.push(file);
+
+        let converter = VcdConverter::new(false, false);
+        assert!(converter.create_vcd_header(3000 * 2352, &cue_sheet).is_ok());
+        assert!(matches!(
+            VcdConverter::new(true, false).create_vcd_header(3000 * 2352, &cue_sheet),
+            Err(Error::HeaderOverflow(_))
+        ));
+        assert!(matches!(
+            converter.create_vcd_header(u64::MAX, &cue_sheet),
+            Err(Error::HeaderOverflow(_))
+        ));
     }
 }
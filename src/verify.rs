@@ -0,0 +1,291 @@
+// src/verify.rs
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::cue::{CueSheet, Severity, TrackType};
+use crate::error::{Error, Result};
+use crate::sector::{self, SectorError, SECTOR_SIZE};
+use crate::status;
+use crate::utils::{Lba, Msf};
+
+const BUFFER_SIZE: usize = 1024 * 1024; // 1MB buffer
+
+/// Sectors sampled per track by the track type check
+const TYPE_CHECK_SAMPLES: u32 = 16;
+
+/// A data sector that failed verification
+#[derive(Debug, Clone)]
+pub struct CorruptSector {
+    pub lba: Lba,
+    pub track: u8,
+    pub error: SectorError,
+}
+
+impl CorruptSector {
+    /// Absolute disc position (LBA + 150 sectors of lead-in pregap)
+    pub fn msf(&self) -> Msf {
+        self.lba.to_absolute_msf()
+    }
+}
+
+/// Result of verifying every data sector of a disc
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub sectors_checked: u64,
+    /// Data tracks that could not be checked (MODE1/2048 has no EDC/ECC)
+    pub skipped_tracks: Vec<u8>,
+    pub corrupt: Vec<CorruptSector>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+
+    /// Number of corrupt sectors in a track
+    pub fn corrupt_in_track(&self, track: u8) -> usize {
+        self.corrupt.iter().filter(|c| c.track == track).count()
+    }
+}
+
+/// Disagreement between a CUE and the sectors it describes
+#[derive(Debug, Clone)]
+pub struct LayoutIssue {
+    pub severity: Severity,
+    pub filename: String,
+    /// Track the issue belongs to (None for file-level issues)
+    pub track: Option<u8>,
+    pub message: String,
+}
+
+impl std::fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.track {
+            Some(track) => write!(f, "Track {:02}: {}", track, self.message),
+            None => write!(f, "{}: {}", self.filename, self.message),
+        }
+    }
+}
+
+/// Cross-check declared track types against sampled sector contents
+///
+/// Up to [`TYPE_CHECK_SAMPLES`] sectors spread over each 2352-byte track
+/// are read. Data tracks must start with the sync pattern, carry the
+/// declared mode byte and a valid BCD header MSF; audio tracks must not
+/// look like data. Each file's length must also be a whole number of
+/// sectors. Decoded audio files and cooked (2336/2048) tracks, which have
+/// no sync or header to look at, only get the length check.
+pub fn check_track_types(cue_sheet: &CueSheet, cue_dir: &Path) -> Result<Vec<LayoutIssue>> {
+    let mut issues = Vec::new();
+    let mut buffer = vec![0u8; SECTOR_SIZE];
+
+    for file in &cue_sheet.files {
+        if file.audio_format.is_some() {
+            continue;
+        }
+        let file_path = cue_dir.join(&file.filename);
+        let mut input = File::open(&file_path).map_err(|e| Error::io(&file_path, e))?;
+        let issue = |severity, track, message| LayoutIssue {
+            severity,
+            filename: file.filename.clone(),
+            track,
+            message,
+        };
+
+        for (track, extent) in file.tracks.iter().zip(file.track_extents()) {
+            let remainder = extent.length % track.sector_size() as u64;
+            if remainder != 0 {
+                issues.push(issue(
+                    Severity::Warning,
+                    None,
+                    format!(
+                        "file length is not a multiple of {} bytes ({} trailing bytes)",
+                        track.sector_size(),
+                        remainder
+                    ),
+                ));
+            }
+            if track.sector_size() != SECTOR_SIZE || extent.sectors == 0 {
+                continue;
+            }
+
+            // Sample the track body, skipping its INDEX 00 pregap
+            let pregap = track.pregap_sectors().min(extent.sectors - 1);
+            let body = extent.sectors - pregap;
+            let samples = body.min(TYPE_CHECK_SAMPLES);
+
+            let (mut data, mut wrong_mode, mut bad_msf) = (0u32, 0u32, 0u32);
+            for n in 0..samples {
+                let sector_index = pregap + n * body / samples;
+                input
+                    .seek(SeekFrom::Start(
+                        extent.offset + sector_index as u64 * SECTOR_SIZE as u64,
+                    ))
+                    .and_then(|_| input.read_exact(&mut buffer))
+                    .map_err(|e| Error::io(&file_path, e))?;
+
+                if !sector::has_sync(&buffer) {
+                    continue;
+                }
+                data += 1;
+                let expected_mode = if track.track_type == TrackType::Mode1_2352 {
+                    1
+                } else {
+                    2
+                };
+                if buffer[15] != expected_mode {
+                    wrong_mode += 1;
+                }
+                if Msf::from_bcd([buffer[12], buffer[13], buffer[14]]).is_none() {
+                    bad_msf += 1;
+                }
+            }
+
+            let message = if track.is_audio() {
+                (data * 2 > samples).then(|| {
+                    format!(
+                        "declared AUDIO but {}/{} sampled sectors are data sectors",
+                        data, samples
+                    )
+                })
+            } else if data * 2 <= samples {
+                Some(format!(
+                    "declared {} but only {}/{} sampled sectors have a data sync pattern",
+                    track.track_type, data, samples
+                ))
+            } else if wrong_mode * 2 > data {
+                Some(format!(
+                    "declared {} but sampled sectors carry a different mode byte",
+                    track.track_type
+                ))
+            } else if bad_msf * 2 > data {
+                Some(format!(
+                    "declared {} but sampled sector headers have no valid MSF",
+                    track.track_type
+                ))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                issues.push(issue(Severity::Error, Some(track.number), message));
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Verify sync, header, subheader and EDC/ECC of every data sector
+///
+/// Sector addresses follow the combined BIN layout: files are laid out
+/// back to back and LBA 0 is the first sector of the first file.
+pub fn verify_cue(cue_sheet: &CueSheet, cue_dir: &Path) -> Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let mut buffer = vec![0u8; SECTOR_SIZE];
+    let mut file_base_lba = 0u32;
+
+    for file in &cue_sheet.files {
+        let file_path = cue_dir.join(&file.filename);
+        let input = File::open(&file_path).map_err(|e| Error::io(&file_path, e))?;
+        let mut reader = BufReader::with_capacity(BUFFER_SIZE, input);
+
+        for (track, extent) in file.tracks.iter().zip(file.track_extents()) {
+            if track.is_audio() {
+                continue;
+            }
+            // MODE1/2048 sectors carry no EDC/ECC
+            if track.track_type == TrackType::Mode1_2048 {
+                report.skipped_tracks.push(track.number);
+                continue;
+            }
+
+            status!(
+                "  Track {:02} [{}]: verifying {} sector(s)",
+                track.number,
+                track.track_type,
+                extent.sectors
+            );
+
+            let sector_size = track.sector_size();
+            let start = track.first_index().get();
+            reader
+                .seek(SeekFrom::Start(extent.offset))
+                .map_err(|e| Error::io(&file_path, e))?;
+            for sector_index in start..start + extent.sectors {
+                let lba = Lba::new(file_base_lba + sector_index).ok_or_else(|| {
+                    Error::HeaderOverflow(format!(
+                        "track {:02} extends past 99:59:74",
+                        track.number
+                    ))
+                })?;
+                if sector_size == SECTOR_SIZE {
+                    reader.read_exact(&mut buffer)
+                } else {
+                    // MODE2/2336: restore sync and header in front of the subheader
+                    buffer[..12].copy_from_slice(&sector::SYNC_PATTERN);
+                    buffer[12..15].copy_from_slice(&lba.to_bcd());
+                    buffer[15] = 2;
+                    reader.read_exact(&mut buffer[16..])
+                }
+                .map_err(|e| Error::io(&file_path, e))?;
+
+                if let Err(error) = sector::verify_sector(&buffer, lba) {
+                    report.corrupt.push(CorruptSector {
+                        lba,
+                        track: track.number,
+                        error,
+                    });
+                }
+                report.sectors_checked += 1;
+            }
+        }
+
+        file_base_lba += file.sector_count();
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cue::{FileEntry, Track};
+
+    #[test]
+    fn test_track_type_mismatch() {
+        // 20 Mode 2 data sectors followed by 10 sectors of audio
+        let mut image = Vec::new();
+        for lba in 0..20u32 {
+            let mut data = [0u8; SECTOR_SIZE];
+            data[..12].copy_from_slice(&sector::SYNC_PATTERN);
+            data[12..15].copy_from_slice(&Lba::new(lba).unwrap().to_bcd());
+            data[15] = 2;
+            image.extend_from_slice(&data);
+        }
+        image.extend_from_slice(&[0x55u8; SECTOR_SIZE * 10]);
+        image.extend_from_slice(&[0u8; 100]);
+
+        let dir = std::env::temp_dir();
+        let path = dir.join("psx-vcd-type-check.bin");
+        std::fs::write(&path, &image).unwrap();
+
+        let mut file = FileEntry::new("psx-vcd-type-check.bin".to_string(), "BINARY".to_string());
+        file.tracks
+            .push(Track::new(1, TrackType::Mode2_2352, Lba::new(0).unwrap()));
+        file.tracks
+            .push(Track::new(2, TrackType::Mode2_2352, Lba::new(20).unwrap()));
+        file.file_size = image.len() as u64;
+        let mut cue_sheet = CueSheet::new();
+        cue_sheet.files.push(file);
+
+        let issues = check_track_types(&cue_sheet, &dir).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let found: Vec<_> = issues.iter().map(|i| (i.severity, i.track)).collect();
+        assert_eq!(
+            found,
+            [(Severity::Warning, None), (Severity::Error, Some(2))]
+        );
+    }
+}
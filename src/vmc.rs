@@ -0,0 +1,166 @@
+// src/vmc.rs
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Size of a raw PS1 memory card (16 blocks of 8KB)
+pub const CARD_SIZE: usize = 128 * 1024;
+
+const FRAME_SIZE: usize = 128;
+const DEXDRIVE_HEADER_SIZE: usize = 0xF40;
+const DEXDRIVE_MAGIC: &[u8] = b"123-456-STD";
+const VMP_HEADER_SIZE: usize = 0x80;
+const VMP_MAGIC: &[u8] = b"\0PMV";
+
+/// Memory card image container formats
+///
+/// POPSTARTER VMC files are plain raw card images, so converting
+/// between formats only means adding or stripping the container header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardFormat {
+    /// Raw image (.mcr, .mcd, .srm, .VMC)
+    Raw,
+    /// DexDrive image (.gme) with a 3904-byte header
+    DexDrive,
+    /// PSP/PS3 virtual memory card (SCEVMC*.VMP) with a 128-byte header
+    Vmp,
+}
+
+impl CardFormat {
+    /// Detect the container format from the image contents
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.len() == DEXDRIVE_HEADER_SIZE + CARD_SIZE && data.starts_with(DEXDRIVE_MAGIC) {
+            Some(CardFormat::DexDrive)
+        } else if data.len() == VMP_HEADER_SIZE + CARD_SIZE && data.starts_with(VMP_MAGIC) {
+            Some(CardFormat::Vmp)
+        } else if data.len() == CARD_SIZE {
+            Some(CardFormat::Raw)
+        } else {
+            None
+        }
+    }
+
+    pub fn header_size(&self) -> usize {
+        match self {
+            CardFormat::Raw => 0,
+            CardFormat::DexDrive => DEXDRIVE_HEADER_SIZE,
+            CardFormat::Vmp => VMP_HEADER_SIZE,
+        }
+    }
+}
+
+impl std::fmt::Display for CardFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CardFormat::Raw => "Raw",
+            CardFormat::DexDrive => "DexDrive",
+            CardFormat::Vmp => "PSP VMP",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Raw PS1 memory card image (128KB)
+#[derive(Debug, Clone)]
+pub struct MemoryCard {
+    pub source_format: CardFormat,
+    data: Vec<u8>,
+}
+
+impl MemoryCard {
+    /// Load a memory card image in any supported format
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read memory card: {}", path.display()))?;
+
+        let format = CardFormat::detect(&data).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unrecognized memory card image ({} bytes): {}",
+                data.len(),
+                path.display()
+            )
+        })?;
+
+        Self::from_bytes(format, &data[format.header_size()..])
+    }
+
+    /// Create a memory card from raw card data (without container header)
+    pub fn from_bytes(source_format: CardFormat, raw: &[u8]) -> Result<Self> {
+        if raw.len() != CARD_SIZE {
+            bail!(
+                "Memory card must be {} bytes, found {}",
+                CARD_SIZE,
+                raw.len()
+            );
+        }
+        if &raw[0..2] != b"MC" {
+            bail!("Missing memory card header (expected 'MC' signature)");
+        }
+
+        Ok(Self {
+            source_format,
+            data: raw.to_vec(),
+        })
+    }
+
+    /// Count save files (directory frames marked as "first block in use")
+    pub fn save_count(&self) -> usize {
+        (1..16)
+            .filter(|i| self.data[i * FRAME_SIZE] == 0x51)
+            .count()
+    }
+
+    /// Write the card as a raw image (POPSTARTER VMC or .mcr)
+    pub fn write_raw(&self, path: &Path) -> Result<()> {
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create memory card: {}", path.display()))?;
+        file.write_all(&self.data)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blank_card() -> Vec<u8> {
+        let mut raw = vec![0u8; CARD_SIZE];
+        raw[0] = b'M';
+        raw[1] = b'C';
+        raw[FRAME_SIZE] = 0x51;
+        raw
+    }
+
+    #[test]
+    fn test_detect_raw() {
+        let raw = blank_card();
+        assert_eq!(CardFormat::detect(&raw), Some(CardFormat::Raw));
+
+        let card = MemoryCard::from_bytes(CardFormat::Raw, &raw).unwrap();
+        assert_eq!(card.save_count(), 1);
+    }
+
+    #[test]
+    fn test_detect_containers() {
+        let mut gme = vec![0u8; DEXDRIVE_HEADER_SIZE];
+        gme[..DEXDRIVE_MAGIC.len()].copy_from_slice(DEXDRIVE_MAGIC);
+        gme.extend_from_slice(&blank_card());
+        assert_eq!(CardFormat::detect(&gme), Some(CardFormat::DexDrive));
+
+        let mut vmp = vec![0u8; VMP_HEADER_SIZE];
+        vmp[..VMP_MAGIC.len()].copy_from_slice(VMP_MAGIC);
+        vmp.extend_from_slice(&blank_card());
+        assert_eq!(CardFormat::detect(&vmp), Some(CardFormat::Vmp));
+
+        let raw = &vmp[CardFormat::Vmp.header_size()..];
+        assert!(MemoryCard::from_bytes(CardFormat::Vmp, raw).is_ok());
+    }
+
+    #[test]
+    fn test_reject_invalid_card() {
+        assert_eq!(CardFormat::detect(&[0u8; 1000]), None);
+        assert!(MemoryCard::from_bytes(CardFormat::Raw, &[0u8; CARD_SIZE]).is_err());
+    }
+}
@@ -0,0 +1,10 @@
+FILE "multi_file (Track 1).bin" BINARY
+  TRACK 01 MODE2/2352
+    INDEX 01 00:00:00
+FILE "multi_file (Track 2).bin" BINARY
+  TRACK 02 AUDIO
+    INDEX 00 00:00:00
+    INDEX 01 00:02:00
+FILE "multi_file (Track 3).bin" BINARY
+  TRACK 03 AUDIO
+    INDEX 01 00:00:00
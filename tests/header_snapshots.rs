@@ -0,0 +1,234 @@
+// tests/header_snapshots.rs
+//! VCD header snapshots
+//!
+//! Each case combines a fixture CUE from `tests/fixtures/` with synthetic
+//! zero-filled BINs, builds the 1 MB header and compares it byte for byte
+//! with the snapshot in `tests/header_snapshots/<case>.hex`. Mismatches are
+//! reported per header field. The snapshots record what this crate writes;
+//! they guard against unintended changes, not against differences from
+//! cue2pops. Run with `UPDATE_SNAPSHOTS=1` to rewrite them after an
+//! intended change.
+
+use std::path::{Path, PathBuf};
+
+use psx_vcd::combiner::BinCombiner;
+use psx_vcd::cue::CueSheet;
+use psx_vcd::vcd::{header_fields, VcdConverter, VcdHeader, VCD_HEADER_SIZE};
+
+const SECTOR_SIZE: usize = 2352;
+
+struct Case {
+    name: &'static str,
+    /// First line of the snapshot
+    description: &'static str,
+    cue: &'static str,
+    /// BIN files of the fixture and their length in sectors
+    bins: &'static [(&'static str, usize)],
+    gap_plus: bool,
+    gap_minus: bool,
+}
+
+const SINGLE_FILE_BINS: &[(&str, usize)] = &[("single_file.bin", 1800)];
+const MULTI_FILE_BINS: &[(&str, usize)] = &[
+    ("multi_file (Track 1).bin", 1000),
+    ("multi_file (Track 2).bin", 400),
+    ("multi_file (Track 3).bin", 300),
+];
+
+fn run_case(case: &Case) {
+    let dir = std::env::temp_dir().join(format!("psx-vcd-snapshot-{}", case.name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let cue_path = dir.join(case.cue);
+    std::fs::copy(tests_dir().join("fixtures").join(case.cue), &cue_path).unwrap();
+    for (name, sectors) in case.bins {
+        std::fs::write(dir.join(name), vec![0u8; sectors * SECTOR_SIZE]).unwrap();
+    }
+
+    let mut cue_sheet = CueSheet::parse(&cue_path).unwrap();
+    cue_sheet.load_file_sizes(&dir).unwrap();
+    let combined = dir.join("combined.bin");
+    BinCombiner::new(Vec::new(), false)
+        .combine(&mut cue_sheet, &dir, &combined)
+        .unwrap();
+    let bin_size = std::fs::metadata(&combined).unwrap().len();
+
+    let actual = VcdConverter::new(case.gap_plus, case.gap_minus)
+        .create_vcd_header(bin_size, &cue_sheet)
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let path = snapshot_dir().join(format!("{}.hex", case.name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, dump(case.description, &actual)).unwrap();
+        return;
+    }
+
+    let expected = load_snapshot(&path);
+    let diffs = field_diffs(&expected, &actual);
+    assert!(
+        diffs.is_empty(),
+        "{}: header differs from snapshot (UPDATE_SNAPSHOTS=1 rewrites it):\n  {}",
+        case.name,
+        diffs.join("\n  ")
+    );
+}
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn snapshot_dir() -> PathBuf {
+    tests_dir().join("header_snapshots")
+}
+
+/// Decoded track entries as comments, then the non-zero 16-byte rows
+fn dump(description: &str, header: &[u8]) -> String {
+    let mut text = format!("# {}\n", description);
+    let parsed = VcdHeader::parse(header).unwrap();
+    text += &format!("# lead-out {}\n", parsed.leadout);
+    for track in &parsed.tracks {
+        text += &format!(
+            "# track {:02} {}: INDEX 00 {}, INDEX 01 {}\n",
+            track.number,
+            if track.is_audio { "audio" } else { "data" },
+            track.index00,
+            track.index01
+        );
+    }
+    text += "# Offsets not listed are zero up to 0x100000.\n";
+
+    for (row, bytes) in header.chunks(16).enumerate() {
+        if bytes.iter().any(|&b| b != 0) {
+            text += &format!("{:06x}: {}\n", row * 16, hex(bytes));
+        }
+    }
+    text
+}
+
+/// Parse a `OFFSET: hex bytes` dump; unlisted bytes are zero
+fn load_snapshot(path: &Path) -> Vec<u8> {
+    let text = std::fs::read_to_string(path).unwrap();
+    let mut header = vec![0u8; VCD_HEADER_SIZE];
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (offset, bytes) = line.split_once(':').expect("expected OFFSET: bytes");
+        let offset = usize::from_str_radix(offset.trim(), 16).unwrap();
+        for (i, byte) in bytes.split_whitespace().enumerate() {
+            header[offset + i] = u8::from_str_radix(byte, 16).unwrap();
+        }
+    }
+    header
+}
+
+fn field_diffs(expected: &[u8], actual: &[u8]) -> Vec<String> {
+    if actual.len() != expected.len() {
+        return vec![format!(
+            "header size: expected {} bytes, got {}",
+            expected.len(),
+            actual.len()
+        )];
+    }
+
+    let fields = header_fields(99);
+    let mut diffs: Vec<String> = fields
+        .iter()
+        .filter(|field| expected[field.range()] != actual[field.range()])
+        .map(|field| {
+            format!(
+                "{}: expected {}, got {}",
+                field.name,
+                hex(&expected[field.range()]),
+                hex(&actual[field.range()])
+            )
+        })
+        .collect();
+
+    // Bytes outside every named field
+    diffs.extend(
+        (0..expected.len())
+            .filter(|&i| expected[i] != actual[i])
+            .filter(|i| !fields.iter().any(|field| field.range().contains(i)))
+            .take(16)
+            .map(|i| {
+                format!(
+                    "byte {:#06x}: expected {:02x}, got {:02x}",
+                    i, expected[i], actual[i]
+                )
+            }),
+    );
+    diffs
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn snapshot_single_track() {
+    run_case(&Case {
+        name: "single_track",
+        description: "1 file, 1 MODE2/2352 track of 1000 sectors",
+        cue: "single_track.cue",
+        bins: &[("single_track.bin", 1000)],
+        gap_plus: false,
+        gap_minus: false,
+    });
+}
+
+#[test]
+fn snapshot_single_file() {
+    run_case(&Case {
+        name: "single_file",
+        description: "1 file of 1800 sectors: data, audio with INDEX 00, audio",
+        cue: "single_file.cue",
+        bins: SINGLE_FILE_BINS,
+        gap_plus: false,
+        gap_minus: false,
+    });
+}
+
+#[test]
+fn snapshot_multi_file() {
+    run_case(&Case {
+        name: "multi_file",
+        description: "3 files of 1000/400/300 sectors: data, audio with INDEX 00, audio",
+        cue: "multi_file.cue",
+        bins: MULTI_FILE_BINS,
+        gap_plus: false,
+        gap_minus: false,
+    });
+}
+
+#[test]
+fn snapshot_single_file_gap_plus() {
+    run_case(&Case {
+        name: "single_file_gap_plus",
+        description: "single_file with gap++ (tracks 02+ moved 2 seconds later)",
+        cue: "single_file.cue",
+        bins: SINGLE_FILE_BINS,
+        gap_plus: true,
+        gap_minus: false,
+    });
+}
+
+#[test]
+fn snapshot_multi_file_gap_minus() {
+    run_case(&Case {
+        name: "multi_file_gap_minus",
+        description: "multi_file with gap-- (tracks 02+ moved 2 seconds earlier)",
+        cue: "multi_file.cue",
+        bins: MULTI_FILE_BINS,
+        gap_plus: false,
+        gap_minus: true,
+    });
+}